@@ -0,0 +1,199 @@
+use crate::analysis::lint::{Level, Lint};
+use crate::parser::ast::{Attribute, FunctionDef, Program, Stmt, StmtKind};
+use crate::parser::visit::{walk_function_def, walk_stmt, Visitor};
+
+// 関数定義と文に付いた属性を検査する
+// - 関数には lint の水準のほかに #[inline] と #[inline(never)] のどちらか1つを付けられる
+// - return 文には lint の水準のほかに #[tail] を1つ付けられる
+// - ほかの文には lint の水準だけを付けられる
+// 後のパスはここで弾いた属性が来ないものとして扱う
+pub fn check_attributes(program: &Program) -> Result<(), String> {
+    let mut checker = AttributeChecker { result: Ok(()) };
+    checker.visit_program(program);
+    checker.result
+}
+
+struct AttributeChecker {
+    result: Result<(), String>,
+}
+
+// 属性を付ける場所
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Function,
+    Return,
+    Stmt,
+}
+
+fn describe(attr: &Attribute) -> String {
+    match attr.args.first() {
+        Some(arg) => format!("{}({})", attr.name, arg),
+        None => attr.name.clone(),
+    }
+}
+
+fn check(attrs: &[Attribute], target: Target) -> Result<(), String> {
+    // 最初の inline 属性と tail 属性。2つ目は同じものでも使われないのでエラーにする
+    let mut inline: Option<&Attribute> = None;
+    let mut tail: Option<&Attribute> = None;
+    for attr in attrs {
+        let first = match attr.name.as_str() {
+            "inline" => {
+                if target != Target::Function {
+                    return Err(format!(
+                        "{}: Attribute 'inline' is only allowed on functions",
+                        attr.span
+                    ));
+                }
+                if !(attr.args.is_empty() || attr.args == ["never"]) {
+                    return Err(format!(
+                        "{}: Attribute 'inline' expects no arguments or 'never'",
+                        attr.span
+                    ));
+                }
+                inline.replace(attr)
+            }
+            "tail" => {
+                if target != Target::Return {
+                    return Err(format!(
+                        "{}: Attribute 'tail' is only allowed on 'return' statements",
+                        attr.span
+                    ));
+                }
+                if !attr.args.is_empty() {
+                    return Err(format!(
+                        "{}: Attribute 'tail' expects no arguments",
+                        attr.span
+                    ));
+                }
+                tail.replace(attr)
+            }
+            name => {
+                if Level::from_name(name).is_none() {
+                    return Err(format!("{}: Unknown attribute '{}'", attr.span, name));
+                }
+                if attr.args.is_empty() {
+                    return Err(format!(
+                        "{}: Attribute '{}' expects a list of lints",
+                        attr.span, name
+                    ));
+                }
+                if let Some(arg) = attr.args.iter().find(|arg| Lint::from_name(arg).is_none()) {
+                    return Err(format!("{}: Unknown lint '{}'", attr.span, arg));
+                }
+                None
+            }
+        };
+        if let Some(first) = first {
+            let message = if first.args == attr.args {
+                format!("Duplicate attribute '{}'", describe(attr))
+            } else {
+                format!(
+                    "Attribute '{}' conflicts with '{}'",
+                    describe(attr),
+                    describe(first)
+                )
+            };
+            return Err(format!(
+                "{}: {}\n{}: note: '{}' first given here",
+                attr.span,
+                message,
+                first.span,
+                describe(first)
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Visitor<'a> for AttributeChecker {
+    fn visit_function_def(&mut self, function: &'a FunctionDef) {
+        if self.result.is_ok() {
+            self.result = check(&function.attrs, Target::Function);
+        }
+        walk_function_def(self, function);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if self.result.is_ok() {
+            let target = match stmt.kind {
+                StmtKind::Return(_) => Target::Return,
+                _ => Target::Stmt,
+            };
+            self.result = check(&stmt.attrs, target);
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn check(source: &str) -> Result<(), String> {
+        check_attributes(&parse(source))
+    }
+
+    #[test]
+    fn test_valid_attributes() {
+        let tests = vec![
+            "#[allow(unused_functions, unused_parameters)]\n#[inline(never)]\nfunction f(x:i64) { let y:i64 = 1; }\n#[deny(unused_variables)]\nlet z:i64 = 1;",
+            "#[inline] function f() {} f();",
+            "function f() -> i64 { 1 } function g() -> i64 { #[allow(unreachable_code)] #[tail] return f(); } g();",
+        ];
+        for source in tests {
+            assert_eq!(check(source), Ok(()), "Should accept: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_invalid_attributes() {
+        let tests = vec![
+            ("#[allow(unused_things)] let x:i64 = 1;", "1:1: Unknown lint 'unused_things'"),
+            ("#[forbid(unused_variables)] let x:i64 = 1;", "1:1: Unknown attribute 'forbid'"),
+            ("#[allow] let x:i64 = 1;", "1:1: Attribute 'allow' expects a list of lints"),
+            ("#[inline] let x:i64 = 1;", "1:1: Attribute 'inline' is only allowed on functions"),
+            (
+                "#[inline(always)] function f() {} f();",
+                "1:1: Attribute 'inline' expects no arguments or 'never'",
+            ),
+            (
+                "#[tail] function f() {} f();",
+                "1:1: Attribute 'tail' is only allowed on 'return' statements",
+            ),
+            (
+                "function f(x:i64) { #[tail] print(x); }",
+                "1:21: Attribute 'tail' is only allowed on 'return' statements",
+            ),
+            (
+                "function f() -> i64 { 1 } function g() -> i64 { #[tail(always)] return f(); } g();",
+                "1:49: Attribute 'tail' expects no arguments",
+            ),
+        ];
+        for (source, message) in tests {
+            assert_eq!(check(source), Err(message.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_repeated_attributes() {
+        let tests = vec![
+            (
+                "#[inline]\n#[inline(never)]\nfunction f() {} f();",
+                "2:1: Attribute 'inline(never)' conflicts with 'inline'\n1:1: note: 'inline' first given here",
+            ),
+            (
+                "#[inline] #[inline] function f() {} f();",
+                "1:11: Duplicate attribute 'inline'\n1:1: note: 'inline' first given here",
+            ),
+            (
+                "function f() -> i64 { 1 }\nfunction g() -> i64 { #[tail] #[tail] return f(); } g();",
+                "2:31: Duplicate attribute 'tail'\n2:23: note: 'tail' first given here",
+            ),
+        ];
+        for (source, message) in tests {
+            assert_eq!(check(source), Err(message.to_string()), "{}", source);
+        }
+    }
+}
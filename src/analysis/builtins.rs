@@ -0,0 +1,31 @@
+use crate::parser::ast::Op;
+
+// 宣言なしで呼べる組み込み関数
+// 同じ名前の関数を定義した場合はそちらが優先される
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    // 桁あふれを検査せずに折り返す算術。wrapping_add(a, b) は a + b と同じ型を返す
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "wrapping_add" => Some(Builtin::WrappingAdd),
+            "wrapping_sub" => Some(Builtin::WrappingSub),
+            "wrapping_mul" => Some(Builtin::WrappingMul),
+            _ => None,
+        }
+    }
+
+    // 対応する二項演算
+    pub fn op(self) -> Op {
+        match self {
+            Builtin::WrappingAdd => Op::Add,
+            Builtin::WrappingSub => Op::Subtract,
+            Builtin::WrappingMul => Op::Multiply,
+        }
+    }
+}
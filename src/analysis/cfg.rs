@@ -0,0 +1,358 @@
+use crate::parser::ast::{
+    Block, Expr, ExprKind, FunctionDef, Item, Literal, NodeId, Program, Stmt, StmtKind,
+};
+use std::collections::{HashMap, HashSet};
+
+// 関数本体 (とトップレベルの文) ごとの制御フローグラフ
+// - 基本ブロックには、そのブロックで実行が始まる文と末尾の式の NodeId を順に並べる
+// - return は出口へ、break/continue はループの後/先頭へ飛び、その後ろは前任のない新しいブロックになる
+// - 条件がリテラルの if/while は、通らない側の辺を張らない (定数畳み込みの後に作ると効く)
+#[derive(Debug)]
+pub struct ControlFlow {
+    // 関数の NodeId と、その本体のグラフ
+    pub functions: HashMap<NodeId, Cfg>,
+    // トップレベルの文のグラフ
+    pub main: Cfg,
+}
+
+impl ControlFlow {
+    pub fn function(&self, function: NodeId) -> Option<&Cfg> {
+        self.functions.get(&function)
+    }
+
+    // 文または末尾の式 node の実行が始まりうるか
+    pub fn is_reachable(&self, node: NodeId) -> bool {
+        self.cfgs().any(|cfg| cfg.reachable.contains(&node))
+    }
+
+    // 文 stmt の後ろへ実行が続きうるか
+    pub fn completes(&self, stmt: NodeId) -> bool {
+        self.cfgs().any(|cfg| cfg.completes.contains(&stmt))
+    }
+
+    fn cfgs(&self) -> impl Iterator<Item = &Cfg> {
+        std::iter::once(&self.main).chain(self.functions.values())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Default)]
+pub struct BasicBlock {
+    pub nodes: Vec<NodeId>,
+    pub successors: Vec<BlockId>,
+}
+
+#[derive(Debug)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub entry: BlockId,
+    pub exit: BlockId,
+    // 本体の終わりに達したブロック。ここから出口への辺は return を通らずに抜ける経路
+    pub fallthrough: BlockId,
+    reachable: HashSet<NodeId>,
+    completes: HashSet<NodeId>,
+}
+
+impl Cfg {
+    // return を通らずに本体の終わりまで実行が続きうるか
+    pub fn falls_off_end(&self) -> bool {
+        self.reachable_blocks().contains(&self.fallthrough)
+    }
+
+    fn reachable_blocks(&self) -> HashSet<BlockId> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![self.entry];
+        while let Some(block) = stack.pop() {
+            if visited.insert(block) {
+                stack.extend(&self.blocks[block.0].successors);
+            }
+        }
+        visited
+    }
+}
+
+pub fn build_control_flow(program: &Program) -> ControlFlow {
+    let mut functions = HashMap::new();
+    let mut main = CfgBuilder::new();
+    for item in &program.items {
+        match item {
+            Item::Function(function) => {
+                functions.insert(function.id, build_function_cfg(function));
+            }
+            Item::Stmt(stmt) => main.stmt(stmt),
+        }
+    }
+    ControlFlow {
+        functions,
+        main: main.finish(),
+    }
+}
+
+pub fn build_function_cfg(function: &FunctionDef) -> Cfg {
+    let mut builder = CfgBuilder::new();
+    builder.block(&function.body);
+    builder.finish()
+}
+
+struct CfgBuilder {
+    blocks: Vec<BasicBlock>,
+    current: BlockId,
+    exit: BlockId,
+    // 囲んでいるループ (ラベル, 先頭, 後ろ)。内側が末尾
+    loops: Vec<(Option<String>, BlockId, BlockId)>,
+    // 文と、その文の直後のブロック
+    stmt_ends: Vec<(NodeId, BlockId)>,
+}
+
+impl CfgBuilder {
+    fn new() -> Self {
+        let mut builder = CfgBuilder {
+            blocks: Vec::new(),
+            current: BlockId(0),
+            exit: BlockId(0),
+            loops: Vec::new(),
+            stmt_ends: Vec::new(),
+        };
+        builder.current = builder.new_block();
+        builder.exit = builder.new_block();
+        builder
+    }
+
+    fn finish(mut self) -> Cfg {
+        let fallthrough = self.current;
+        self.edge(fallthrough, self.exit);
+        let mut cfg = Cfg {
+            blocks: self.blocks,
+            entry: BlockId(0),
+            exit: self.exit,
+            fallthrough,
+            reachable: HashSet::new(),
+            completes: HashSet::new(),
+        };
+        let reachable = cfg.reachable_blocks();
+        cfg.reachable = reachable
+            .iter()
+            .flat_map(|block| cfg.blocks[block.0].nodes.iter().copied())
+            .collect();
+        cfg.completes = self
+            .stmt_ends
+            .into_iter()
+            .filter(|(_, block)| reachable.contains(block))
+            .map(|(stmt, _)| stmt)
+            .collect();
+        cfg
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock::default());
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        self.blocks[from.0].successors.push(to);
+    }
+
+    // 現在のブロックから target へ飛び、その後ろは到達しない新しいブロックにする
+    fn jump(&mut self, target: BlockId) {
+        self.edge(self.current, target);
+        self.current = self.new_block();
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.blocks[self.current.0].nodes.push(tail.id);
+            self.expr(tail);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.blocks[self.current.0].nodes.push(stmt.id);
+        match &stmt.kind {
+            StmtKind::Let { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            StmtKind::Assignment { value, .. } | StmtKind::Print(value) | StmtKind::Expr(value) => {
+                self.expr(value)
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.jump(self.exit);
+            }
+            StmtKind::Break(label) => {
+                if let Some((_, _, after)) = self.loop_target(label) {
+                    self.jump(after);
+                }
+            }
+            StmtKind::Continue(label) => {
+                if let Some((_, header, _)) = self.loop_target(label) {
+                    self.jump(header);
+                }
+            }
+            StmtKind::WhileLoop {
+                label,
+                condition,
+                body,
+            } => {
+                let header = self.new_block();
+                self.edge(self.current, header);
+                self.current = header;
+                self.expr(condition);
+                let body_block = self.new_block();
+                let after = self.new_block();
+                let constant = constant_condition(condition);
+                if constant != Some(false) {
+                    self.edge(self.current, body_block);
+                }
+                if constant != Some(true) {
+                    self.edge(self.current, after);
+                }
+                self.loops.push((label.clone(), header, after));
+                self.current = body_block;
+                self.block(body);
+                self.edge(self.current, header);
+                self.loops.pop();
+                self.current = after;
+            }
+        }
+        self.stmt_ends.push((stmt.id, self.current));
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition);
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let join = self.new_block();
+                let constant = constant_condition(condition);
+                if constant != Some(false) {
+                    self.edge(self.current, then_block);
+                }
+                if constant != Some(true) {
+                    self.edge(self.current, else_block);
+                }
+                self.current = then_block;
+                self.block(consequence);
+                self.edge(self.current, join);
+                self.current = else_block;
+                if let Some(alt) = alternative {
+                    self.block(alt);
+                }
+                self.edge(self.current, join);
+                self.current = join;
+            }
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::BinaryOp { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::FunctionCall { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+        }
+    }
+
+    // ループの外の break/continue は名前解決で弾いていないので、辺を張らずに無視する
+    fn loop_target(&self, label: &Option<String>) -> Option<(Option<String>, BlockId, BlockId)> {
+        self.loops
+            .iter()
+            .rev()
+            .find(|(name, _, _)| label.is_none() || name == label)
+            .cloned()
+    }
+}
+
+// 条件がリテラルなら、常に成り立つか
+fn constant_condition(condition: &Expr) -> Option<bool> {
+    match &condition.kind {
+        ExprKind::Literal(Literal::I32(i)) => Some(*i != 0),
+        ExprKind::Literal(Literal::I64(i)) => Some(*i != 0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn function_falls_off_end(source: &str) -> bool {
+        let program = parse(source);
+        let control_flow = build_control_flow(&program);
+        match &program.items[0] {
+            Item::Function(function) => control_flow.function(function.id).unwrap().falls_off_end(),
+            item => panic!("Unexpected item: {:?}", item),
+        }
+    }
+
+    #[test]
+    fn test_falls_off_end() {
+        let tests = vec![
+            ("function f() { print(1); }", true),
+            ("function f() { return; }", false),
+            ("function f(x:i64) { if (x < 1) { return; } }", true),
+            (
+                "function f(x:i64) { if (x < 1) { return; } else { return; } }",
+                false,
+            ),
+            ("function f(x:i64) { while (x < 1) { return; } }", true),
+            ("function f() { while (1) { return; } }", false),
+            ("function f() { while (1) { break; } }", true),
+            (
+                "function f() { 'a: while (1) { while (1) { break 'a; } } }",
+                true,
+            ),
+            (
+                "function f() { 'a: while (1) { while (1) { break; } } }",
+                false,
+            ),
+            ("function f() { let x:i64 = { return; }; }", false),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(function_falls_off_end(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_reachability() {
+        let program = parse(
+            "let x:i64 = 1; while (x < 2) { continue; print(1); } print(2); while (1) { } print(3);",
+        );
+        let control_flow = build_control_flow(&program);
+        let reachable: Vec<bool> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Stmt(stmt) => control_flow.is_reachable(stmt.id),
+                Item::Function(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(reachable, vec![true, true, true, true, false]);
+
+        let Item::Stmt(loop_stmt) = &program.items[1] else {
+            unreachable!()
+        };
+        let StmtKind::WhileLoop { body, .. } = &loop_stmt.kind else {
+            unreachable!()
+        };
+        assert!(control_flow.is_reachable(body.stmts[0].id));
+        assert!(!control_flow.completes(body.stmts[0].id));
+        assert!(!control_flow.is_reachable(body.stmts[1].id));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::analysis::typeck::TypeTable;
+use crate::parser::ast::{Expr, ExprKind, Literal, Op, Program};
+use crate::parser::visit::{walk_expr_mut, VisitorMut};
+
+// リテラルだけでできた算術と比較をコンパイル時に計算し、リテラルに置き換える
+// - 演算は型検査で決まった型 (i32/i64) の範囲で行い、桁あふれと 0 除算はエラーにする
+// - 置き換えた式は元の NodeId を保つので、型検査の結果はそのまま使える
+pub fn fold_constants(program: &mut Program, types: &TypeTable) -> Result<(), String> {
+    let mut folder = ConstantFolder {
+        types,
+        errors: Vec::new(),
+    };
+    folder.visit_program_mut(program);
+    if folder.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(folder.errors.join("\n"))
+    }
+}
+
+struct ConstantFolder<'a> {
+    types: &'a TypeTable,
+    errors: Vec<String>,
+}
+
+impl VisitorMut for ConstantFolder<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        let ExprKind::BinaryOp { left, op, right } = &expr.kind else {
+            return;
+        };
+        let (Some(lhs), Some(rhs)) = (integer_value(left), integer_value(right)) else {
+            return;
+        };
+        // 比較の結果は i32 なので、演算の型は被演算子の型で決める
+        let is_i64 = self
+            .types
+            .expr_type(left.id)
+            .is_some_and(|ty| ty.is_named("i64"));
+        match evaluate(lhs, op, rhs, is_i64) {
+            Ok(value) => {
+                let literal = match op {
+                    Op::LessThan | Op::GreaterThan => Literal::I32(value as i32),
+                    _ if is_i64 => Literal::I64(value),
+                    _ => Literal::I32(value as i32),
+                };
+                expr.kind = ExprKind::Literal(literal);
+            }
+            Err(message) => {
+                let ty = if is_i64 { "i64" } else { "i32" };
+                self.errors.push(format!(
+                    "{}: {} in constant expression '{} {} {}' of type '{}'",
+                    expr.span, message, lhs, op, rhs, ty
+                ));
+                // 同じ式の外側で重ねて報告しない
+                expr.kind = ExprKind::Literal(Literal::Unit);
+            }
+        }
+    }
+}
+
+fn integer_value(expr: &Expr) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::Literal(Literal::I32(i)) => Some(*i as i64),
+        ExprKind::Literal(Literal::I64(i)) => Some(*i),
+        _ => None,
+    }
+}
+
+// i32 同士の演算の結果は必ず i64 に収まるので、i64 で計算してから範囲を確かめる
+fn evaluate(lhs: i64, op: &Op, rhs: i64, is_i64: bool) -> Result<i64, &'static str> {
+    let result = match op {
+        Op::Add => lhs.checked_add(rhs),
+        Op::Subtract => lhs.checked_sub(rhs),
+        Op::Multiply => lhs.checked_mul(rhs),
+        Op::Divide | Op::Modulo if rhs == 0 => return Err("Division by zero"),
+        Op::Divide => lhs.checked_div(rhs),
+        Op::Modulo => lhs.checked_rem(rhs),
+        Op::LessThan => Some((lhs < rhs) as i64),
+        Op::GreaterThan => Some((lhs > rhs) as i64),
+    };
+    result
+        .filter(|value| is_i64 || i32::try_from(*value).is_ok())
+        .ok_or("Overflow")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::resolve::resolve_names;
+    use crate::analysis::typeck::check_types;
+    use crate::parser::ast::{Item, StmtKind};
+    use crate::test_util::parse;
+
+    fn fold(source: &str) -> Result<Program, String> {
+        let mut ast = parse(source);
+        let resolutions = resolve_names(&ast).expect("Failed to resolve names");
+        let types = check_types(&ast, &resolutions).expect("Failed to check types");
+        fold_constants(&mut ast, &types)?;
+        Ok(ast)
+    }
+
+    // 最後の文の式
+    fn folded(source: &str) -> ExprKind {
+        let ast = fold(source).expect("Failed to fold constants");
+        match ast.items.last() {
+            Some(Item::Stmt(stmt)) => match &stmt.kind {
+                StmtKind::Print(expr) | StmtKind::Expr(expr) => expr.kind.clone(),
+                StmtKind::Let {
+                    value: Some(expr), ..
+                } => expr.kind.clone(),
+                kind => panic!("Unexpected statement: {:?}", kind),
+            },
+            item => panic!("Unexpected item: {:?}", item),
+        }
+    }
+
+    #[test]
+    fn test_folds_literal_expressions() {
+        let tests = vec![
+            ("print(10 + 10);", Literal::I32(20)),
+            // 演算子は左結合: ((2 * 3 + 4) * 5 - 6) / 4 % 5
+            ("print(2 * 3 + 4 * 5 - 6 / 4 % 5);", Literal::I32(1)),
+            ("print(1 < 2);", Literal::I32(1)),
+            ("print((0 - 7) / 2);", Literal::I32(-3)),
+            ("let x:i64 = 2147483647 + 1;", Literal::I64(2147483648)),
+            ("let x:i32 = 3000000000 > 1;", Literal::I32(1)),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(folded(source), ExprKind::Literal(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_keeps_non_constant_operands() {
+        let source = "let x:i64 = 1; print(x + (2 * 3));";
+        match folded(source) {
+            ExprKind::BinaryOp { right, .. } => {
+                assert_eq!(right.kind, ExprKind::Literal(Literal::I64(6)))
+            }
+            kind => panic!("Unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_reports_errors() {
+        let tests = vec![
+            (
+                "let x:i32 = 2147483647 + 1;",
+                "1:13: Overflow in constant expression '2147483647 + 1' of type 'i32'",
+            ),
+            (
+                "print(1 / (2 - 2));",
+                "1:7: Division by zero in constant expression '1 / 0' of type 'i32'",
+            ),
+            (
+                "let x:i64 = 9223372036854775807 * 2 + 1;",
+                "1:13: Overflow in constant expression '9223372036854775807 * 2' of type 'i64'",
+            ),
+            (
+                "print(1 % 0);\nprint(2147483647 * 2147483647);",
+                "1:7: Division by zero in constant expression '1 % 0' of type 'i32'\n2:7: Overflow in constant expression '2147483647 * 2147483647' of type 'i32'",
+            ),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(fold(source).err().as_deref(), Some(expected), "{}", source);
+        }
+    }
+}
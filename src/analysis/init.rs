@@ -0,0 +1,336 @@
+use crate::analysis::resolve::{Resolutions, SymbolId, SymbolKind};
+use crate::parser::ast::{
+    Block, Expr, ExprKind, FunctionDef, Item, NodeId, Program, Stmt, StmtKind,
+};
+use crate::parser::span::Span;
+use std::collections::{HashMap, HashSet};
+
+// 代入される前に読まれる可能性のある変数を報告する
+// - 初期値のない let は、すべての経路で代入されるまで読めない。複合代入も読み取りになる
+// - if は両方の分岐で代入された変数だけが代入済みになる。else のない if は代入しない分岐を持つ
+// - while の本体は一度も実行されないかもしれないので、本体の代入はループの後に残らない
+// - return/break/continue の後は到達しないので、その分岐は合流の際に無視する
+// - 関数の本体では引数を代入済みとみなす。関数は定義より前からも呼べるので、
+//   関数が (呼び出す関数を通して) 代入せずに読むトップレベルの変数は、呼び出しの時点で必ず代入されていること
+pub fn check_initialization(program: &Program, resolutions: &Resolutions) -> Result<(), String> {
+    let mut checker = InitChecker {
+        resolutions,
+        state: State::default(),
+        errors: Vec::new(),
+        reads: HashMap::new(),
+        function_reads: None,
+    };
+    // 各関数が読むトップレベルの変数を求める。再帰があるので変わらなくなるまで繰り返す
+    // ここで見つかったエラーは最後にもう一度検査するときに報告する
+    let functions: Vec<&FunctionDef> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Function(function) => Some(function),
+            Item::Stmt(_) => None,
+        })
+        .collect();
+    loop {
+        let mut changed = false;
+        for function in &functions {
+            let reads = checker.check_function(function);
+            if let Some(symbol) = resolutions.lookup(function.id) {
+                if checker.reads.get(&symbol) != Some(&reads) {
+                    checker.reads.insert(symbol, reads);
+                    changed = true;
+                }
+            }
+        }
+        checker.errors.clear();
+        if !changed {
+            break;
+        }
+    }
+    for item in &program.items {
+        match item {
+            Item::Function(function) => {
+                checker.check_function(function);
+            }
+            Item::Stmt(stmt) => checker.check_stmt(stmt),
+        }
+    }
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors.join("\n"))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct State {
+    // 必ず代入されている変数
+    assigned: HashSet<SymbolId>,
+    // return/break/continue の後で、この位置には到達しない
+    unreachable: bool,
+}
+
+impl State {
+    // 二つの経路の合流。到達しない経路は無視する
+    fn merge(self, other: State) -> State {
+        if self.unreachable {
+            return other;
+        }
+        if other.unreachable {
+            return self;
+        }
+        State {
+            assigned: self
+                .assigned
+                .intersection(&other.assigned)
+                .copied()
+                .collect(),
+            unreachable: false,
+        }
+    }
+}
+
+struct InitChecker<'a> {
+    resolutions: &'a Resolutions,
+    state: State,
+    errors: Vec<String>,
+    // 関数ごとの、代入せずに読むトップレベルの変数
+    reads: HashMap<SymbolId, HashSet<SymbolId>>,
+    // 検査中の関数が代入せずに読むトップレベルの変数。関数の外では None
+    function_reads: Option<HashSet<SymbolId>>,
+}
+
+impl InitChecker<'_> {
+    // 関数の本体を検査し、代入せずに読むトップレベルの変数を返す
+    fn check_function(&mut self, function: &FunctionDef) -> HashSet<SymbolId> {
+        let mut state = State::default();
+        for param in &function.params {
+            if let Some(symbol) = self.resolutions.lookup(param.id) {
+                state.assigned.insert(symbol);
+            }
+        }
+        let outer = std::mem::replace(&mut self.state, state);
+        let outer_reads = self.function_reads.replace(HashSet::new());
+        self.check_block(&function.body);
+        self.state = outer;
+        std::mem::replace(&mut self.function_reads, outer_reads).unwrap_or_default()
+    }
+
+    fn check_block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.check_stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.check_expr(tail);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { value, .. } => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                    self.assign(stmt.id);
+                }
+            }
+            StmtKind::Assignment { name, op, value } => {
+                if op.is_some() {
+                    self.check_read(stmt.id, name, stmt.span);
+                }
+                self.check_expr(value);
+                self.assign(stmt.id);
+            }
+            StmtKind::WhileLoop {
+                condition, body, ..
+            } => {
+                self.check_expr(condition);
+                // 本体は一度も実行されないかもしれない
+                let before = self.state.clone();
+                self.check_block(body);
+                self.state = before;
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+                self.state.unreachable = true;
+            }
+            StmtKind::Break(_) | StmtKind::Continue(_) => self.state.unreachable = true,
+            StmtKind::Print(expr) | StmtKind::Expr(expr) => self.check_expr(expr),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.check_read(expr.id, name, expr.span),
+            ExprKind::FunctionCall { name, args } => {
+                for arg in args {
+                    self.check_expr(arg);
+                }
+                self.check_call(expr.id, name, expr.span);
+            }
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.check_expr(condition);
+                let before = self.state.clone();
+                self.check_block(consequence);
+                let after_consequence = std::mem::replace(&mut self.state, before);
+                if let Some(alt) = alternative {
+                    self.check_block(alt);
+                }
+                let after_alternative = std::mem::take(&mut self.state);
+                self.state = after_consequence.merge(after_alternative);
+            }
+            ExprKind::BinaryOp { left, right, .. } => {
+                self.check_expr(left);
+                self.check_expr(right);
+            }
+            ExprKind::Block(block) => self.check_block(block),
+            ExprKind::Literal(_) => {}
+        }
+    }
+
+    fn assign(&mut self, node: NodeId) {
+        if let Some(symbol) = self.resolutions.lookup(node) {
+            self.state.assigned.insert(symbol);
+        }
+    }
+
+    // 呼び出した関数が読むトップレベルの変数は、ここで代入済みでなければならない
+    fn check_call(&mut self, node: NodeId, name: &str, span: Span) {
+        let Some(function) = self.resolutions.lookup(node) else {
+            return;
+        };
+        let Some(reads) = self.reads.get(&function) else {
+            return;
+        };
+        let mut missing: Vec<SymbolId> = reads
+            .iter()
+            .filter(|global| !self.state.assigned.contains(global))
+            .copied()
+            .collect();
+        if self.state.unreachable || missing.is_empty() {
+            return;
+        }
+        missing.sort_by_key(|global| global.0);
+        for global in missing {
+            if let Some(function_reads) = &mut self.function_reads {
+                function_reads.insert(global);
+                continue;
+            }
+            let variable = self.resolutions.symbol(global);
+            self.errors.push(format!(
+                "{}: Function '{}' may read '{}' before it is assigned\n{}: note: '{}' declared here",
+                span, name, variable.name, variable.span, variable.name
+            ));
+            self.state.assigned.insert(global);
+        }
+    }
+
+    fn check_read(&mut self, node: NodeId, name: &str, span: Span) {
+        let symbol = match self.resolutions.lookup(node) {
+            Some(symbol) => symbol,
+            None => return,
+        };
+        if self.state.unreachable || self.state.assigned.contains(&symbol) {
+            return;
+        }
+        // 関数の中で読むトップレベルの変数は、呼び出す側で検査する
+        if let Some(function_reads) = &mut self.function_reads {
+            if self.resolutions.symbol(symbol).kind == SymbolKind::Global {
+                function_reads.insert(symbol);
+                return;
+            }
+        }
+        let declaration = self.resolutions.symbol(symbol).span;
+        self.errors.push(format!(
+            "{}: Variable '{}' may be used before being assigned\n{}: note: '{}' declared here without a value",
+            span, name, declaration, name
+        ));
+        // 同じ変数について何度も報告しない
+        self.state.assigned.insert(symbol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::resolve::resolve_names;
+    use crate::test_util::parse;
+
+    fn check(source: &str) -> Result<(), String> {
+        let ast = parse(source);
+        let resolutions = resolve_names(&ast).expect("Failed to resolve names");
+        check_initialization(&ast, &resolutions)
+    }
+
+    #[test]
+    fn test_assigned_before_use() {
+        let tests = vec![
+            "let x:i64 = 1; print(x);",
+            "let x:i64; x = 1; print(x);",
+            "let c:i64 = 1; let x:i64; if (c < 2) { x = 1; } else { x = 2; } print(x);",
+            "let c:i64 = 1; let x:i64; if (c < 2) { x = 1; } else { return; } print(x);",
+            "let mut i:i64 = 0; let x:i64; while (i < 3) { i += 1; } x = i; print(x);",
+            "let x:i64; x = 1; function f() -> i64 { x };",
+            "function f(x:i64) -> i64 { let y:i64; y = x; y };",
+            "let x:i64; let y:i64 = { x = 1; x + 1 }; print(x);",
+        ];
+        for test in tests {
+            assert!(check(test).is_ok(), "Should accept: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_use_before_assignment() {
+        let tests = vec![
+            "let x:i64; print(x);",
+            "let c:i64 = 1; let x:i64; if (c < 2) { x = 1; } print(x);",
+            "let c:i64 = 1; let x:i64; if (c < 2) { x = 1; } else { print(c); } print(x);",
+            "let mut i:i64 = 0; let x:i64; while (i < 3) { x = 1; i += 1; } print(x);",
+            "let mut x:i64; x += 1;",
+            "let x:i64; function f() -> i64 { x }; print(f()); x = 1;",
+            "function f() -> i64 { let y:i64; if (1 < 2) { y = 1; } y };",
+        ];
+        for test in tests {
+            assert!(check(test).is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_globals_read_by_called_functions() {
+        let tests = vec![
+            "let x:i64; function f() -> i64 { x }; x = 1; print(f());",
+            "let x:i64; function f() -> i64 { x }; x = 1;",
+            "let x:i64; function f() -> i64 { x = 1; x }; print(f());",
+            "let x:i64 = 1; function f(n:i64) -> i64 { if (n < 1) { x } else { f(n - 1) } }; print(f(3));",
+        ];
+        for test in tests {
+            assert!(check(test).is_ok(), "Should accept: {}", test);
+        }
+        // 関数は定義より前から呼べるので、定義の前の代入では足りない
+        assert_eq!(
+            check("let mut y:i64;\nprint(f());\ny = 1;\nfunction f() -> i64 { y }"),
+            Err("2:7: Function 'f' may read 'y' before it is assigned\n1:1: note: 'y' declared here".to_string())
+        );
+        assert_eq!(
+            check("let x:i64 = f();\nfunction f() -> i64 { x + 1 }\nprint(x);"),
+            Err("1:13: Function 'f' may read 'x' before it is assigned\n1:1: note: 'x' declared here".to_string())
+        );
+        // 呼び出す関数を通して読む場合も同じ
+        assert_eq!(
+            check("let x:i64;\nfunction g() -> i64 { x }\nfunction f() -> i64 { g() }\nprint(f());"),
+            Err("4:7: Function 'f' may read 'x' before it is assigned\n1:1: note: 'x' declared here".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_reports_declaration() {
+        assert_eq!(
+            check("let x:i64;\nprint(x);\nprint(x);"),
+            Err("2:7: Variable 'x' may be used before being assigned\n1:1: note: 'x' declared here without a value".to_string())
+        );
+    }
+}
@@ -0,0 +1,541 @@
+use crate::analysis::cfg::ControlFlow;
+use crate::analysis::resolve::{Resolutions, SymbolId};
+use crate::parser::ast::{
+    Attribute, Block, Expr, ExprKind, FunctionDef, Item, NodeId, Program, Stmt, StmtKind,
+};
+use crate::parser::span::Span;
+use crate::parser::visit::{walk_expr, walk_function_def, Visitor};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// 名前の付いた警告
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedFunctions,
+    UnusedParameters,
+    UnreachableCode,
+    ConstantCondition,
+    Shadowing,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::UnusedVariables,
+        Lint::UnusedFunctions,
+        Lint::UnusedParameters,
+        Lint::UnreachableCode,
+        Lint::ConstantCondition,
+        Lint::Shadowing,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedFunctions => "unused_functions",
+            Lint::UnusedParameters => "unused_parameters",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::ConstantCondition => "constant_condition",
+            Lint::Shadowing => "shadowing",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    // 内側のブロックで名前を隠すのは普通に書くので、shadowing は既定では報告しない
+    fn default_level(self) -> Level {
+        match self {
+            Lint::Shadowing => Level::Allow,
+            _ => Level::Warn,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+// コマンドラインで指定された水準
+#[derive(Clone, Debug, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, Level>,
+    // 警告をすべてエラーとして扱う
+    pub deny_warnings: bool,
+}
+
+impl LintConfig {
+    pub fn set_level(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels
+            .get(&lint)
+            .copied()
+            .unwrap_or_else(|| lint.default_level())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    // Warn か Deny
+    pub level: Level,
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Deny
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = if self.is_error() { "error" } else { "warning" };
+        write!(
+            f,
+            "{}: {}: {} [{}]",
+            self.span,
+            severity,
+            self.message,
+            self.lint.name()
+        )?;
+        for (span, note) in &self.notes {
+            write!(f, "\n{}: note: {}", span, note)?;
+        }
+        Ok(())
+    }
+}
+
+// プログラムの警告を集める
+// 水準は関数定義や文に付けた #[allow(..)] / #[warn(..)] / #[deny(..)] で、その中に限って変えられる
+// 属性は check_attributes で検査してから渡す
+pub fn check_lints(
+    program: &Program,
+    resolutions: &Resolutions,
+    control_flow: &ControlFlow,
+    config: &LintConfig,
+) -> Vec<Diagnostic> {
+    let mut uses = Uses {
+        resolutions,
+        reads: HashSet::new(),
+        calls: HashSet::new(),
+        current_function: None,
+    };
+    uses.visit_program(program);
+
+    let mut linter = Linter {
+        resolutions,
+        control_flow,
+        config,
+        reads: uses.reads,
+        calls: uses.calls,
+        levels: Vec::new(),
+        scopes: vec![HashMap::new()],
+        diagnostics: Vec::new(),
+    };
+    for item in &program.items {
+        if let Item::Function(function) = item {
+            linter.declare(&function.name, function.span);
+        }
+    }
+    let mut previous = None;
+    for item in &program.items {
+        match item {
+            Item::Function(function) => linter.lint_function(function),
+            Item::Stmt(stmt) => {
+                linter.check_reachable(stmt.id, stmt.span, previous);
+                linter.lint_stmt(stmt);
+                previous = Some(stmt);
+            }
+        }
+    }
+    linter.diagnostics
+}
+
+// 読まれた変数と呼ばれた関数を集める。関数の中からの自分自身の呼び出しは数えない
+struct Uses<'a> {
+    resolutions: &'a Resolutions,
+    reads: HashSet<SymbolId>,
+    calls: HashSet<SymbolId>,
+    current_function: Option<SymbolId>,
+}
+
+impl<'ast> Visitor<'ast> for Uses<'_> {
+    fn visit_function_def(&mut self, function: &'ast FunctionDef) {
+        self.current_function = self.resolutions.lookup(function.id);
+        walk_function_def(self, function);
+        self.current_function = None;
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        let symbol = self.resolutions.lookup(expr.id);
+        match &expr.kind {
+            ExprKind::Variable(_) => self.reads.extend(symbol),
+            ExprKind::FunctionCall { .. } if symbol != self.current_function => {
+                self.calls.extend(symbol)
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+struct Linter<'a> {
+    resolutions: &'a Resolutions,
+    control_flow: &'a ControlFlow,
+    config: &'a LintConfig,
+    reads: HashSet<SymbolId>,
+    calls: HashSet<SymbolId>,
+    // 属性で変えた水準。内側が末尾
+    levels: Vec<HashMap<Lint, Level>>,
+    // 名前と宣言の位置。shadowing の検出に使う
+    scopes: Vec<HashMap<String, Span>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn lint_function(&mut self, function: &FunctionDef) {
+        self.push_attributes(&function.attrs);
+        if !self.is_used(function.id, &function.name, &self.calls) {
+            self.report(
+                Lint::UnusedFunctions,
+                function.span,
+                format!("Function '{}' is never called", function.name),
+                Vec::new(),
+            );
+        }
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            self.check_shadowing(&param.name, param.span);
+            self.declare(&param.name, param.span);
+            if !self.is_used(param.id, &param.name, &self.reads) {
+                self.report(
+                    Lint::UnusedParameters,
+                    param.span,
+                    format!("Parameter '{}' is never read", param.name),
+                    Vec::new(),
+                );
+            }
+        }
+        self.lint_block_contents(&function.body);
+        self.scopes.pop();
+        self.levels.pop();
+    }
+
+    fn lint_block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        self.lint_block_contents(block);
+        self.scopes.pop();
+    }
+
+    fn lint_block_contents(&mut self, block: &Block) {
+        // 到達しない文の並びは、到達する文の直後の先頭だけ報告する
+        let mut previous: Option<&Stmt> = None;
+        for stmt in &block.stmts {
+            self.check_reachable(stmt.id, stmt.span, previous);
+            self.lint_stmt(stmt);
+            previous = Some(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.check_reachable(tail.id, tail.span, previous);
+            self.lint_expr(tail);
+        }
+    }
+
+    fn lint_stmt(&mut self, stmt: &Stmt) {
+        self.push_attributes(&stmt.attrs);
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                if let Some(value) = value {
+                    self.lint_expr(value);
+                }
+                self.check_shadowing(name, stmt.span);
+                self.declare(name, stmt.span);
+                if !self.is_used(stmt.id, name, &self.reads) {
+                    self.report(
+                        Lint::UnusedVariables,
+                        stmt.span,
+                        format!("Variable '{}' is never read", name),
+                        Vec::new(),
+                    );
+                }
+            }
+            StmtKind::WhileLoop {
+                condition, body, ..
+            } => {
+                self.check_condition(condition, "while");
+                self.lint_expr(condition);
+                self.lint_block(body);
+            }
+            StmtKind::Assignment { value, .. }
+            | StmtKind::Return(Some(value))
+            | StmtKind::Print(value)
+            | StmtKind::Expr(value) => self.lint_expr(value),
+            StmtKind::Return(None) | StmtKind::Break(_) | StmtKind::Continue(_) => {}
+        }
+        self.levels.pop();
+    }
+
+    fn lint_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.check_condition(condition, "if");
+                self.lint_expr(condition);
+                self.lint_block(consequence);
+                if let Some(alt) = alternative {
+                    self.lint_block(alt);
+                }
+            }
+            ExprKind::Block(block) => self.lint_block(block),
+            ExprKind::BinaryOp { left, right, .. } => {
+                self.lint_expr(left);
+                self.lint_expr(right);
+            }
+            ExprKind::FunctionCall { args, .. } => {
+                for arg in args {
+                    self.lint_expr(arg);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+        }
+    }
+
+    // `_` で始まる名前は使わなくても報告しない
+    fn is_used(&self, node: NodeId, name: &str, uses: &HashSet<SymbolId>) -> bool {
+        name.starts_with('_')
+            || self
+                .resolutions
+                .lookup(node)
+                .is_none_or(|symbol| uses.contains(&symbol))
+    }
+
+    fn check_condition(&mut self, condition: &Expr, keyword: &str) {
+        if is_constant(condition) {
+            self.report(
+                Lint::ConstantCondition,
+                condition.span,
+                format!("Condition of '{}' is a constant expression", keyword),
+                Vec::new(),
+            );
+        }
+    }
+
+    fn check_shadowing(&mut self, name: &str, span: Span) {
+        let previous = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied());
+        if let Some(previous) = previous {
+            self.report(
+                Lint::Shadowing,
+                span,
+                format!("'{}' shadows an earlier declaration", name),
+                vec![(previous, format!("'{}' previously declared here", name))],
+            );
+        }
+    }
+
+    // 直前の文 previous から実行が続かないときだけ報告する
+    // 先頭の文が到達しないのは囲んでいる文が到達しないためで、そちらで報告済み
+    fn check_reachable(&mut self, node: NodeId, span: Span, previous: Option<&Stmt>) {
+        let Some(previous) = previous else {
+            return;
+        };
+        if self.control_flow.is_reachable(node) || !self.control_flow.is_reachable(previous.id) {
+            return;
+        }
+        self.report(
+            Lint::UnreachableCode,
+            span,
+            "Unreachable code".to_string(),
+            vec![(
+                previous.span,
+                "any code following this statement is unreachable".to_string(),
+            )],
+        );
+    }
+
+    fn declare(&mut self, name: &str, span: Span) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), span);
+        }
+    }
+
+    // 属性は check_attributes で検査済みなので、lint の水準以外は読み飛ばす
+    fn push_attributes(&mut self, attrs: &[Attribute]) {
+        let mut levels = HashMap::new();
+        for attr in attrs {
+            let Some(level) = Level::from_name(&attr.name) else {
+                continue;
+            };
+            for lint in attr.args.iter().filter_map(|name| Lint::from_name(name)) {
+                levels.insert(lint, level);
+            }
+        }
+        self.levels.push(levels);
+    }
+
+    fn level(&self, lint: Lint) -> Level {
+        let level = self
+            .levels
+            .iter()
+            .rev()
+            .find_map(|levels| levels.get(&lint).copied())
+            .unwrap_or_else(|| self.config.level(lint));
+        if level == Level::Warn && self.config.deny_warnings {
+            Level::Deny
+        } else {
+            level
+        }
+    }
+
+    fn report(&mut self, lint: Lint, span: Span, message: String, notes: Vec<(Span, String)>) {
+        let level = self.level(lint);
+        if level == Level::Allow {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            lint,
+            level,
+            span,
+            message,
+            notes,
+        });
+    }
+}
+
+// リテラルとその演算だけでできた式
+fn is_constant(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Literal(_) => true,
+        ExprKind::BinaryOp { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::cfg::build_control_flow;
+    use crate::analysis::resolve::resolve_names;
+    use crate::test_util::parse;
+
+    fn lint_with(source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+        let ast = parse(source);
+        let resolutions = resolve_names(&ast).expect("Failed to resolve names");
+        check_lints(&ast, &resolutions, &build_control_flow(&ast), config)
+    }
+
+    fn lints(source: &str) -> Vec<Lint> {
+        lint_with(source, &LintConfig::default())
+            .iter()
+            .map(|diagnostic| diagnostic.lint)
+            .collect()
+    }
+
+    #[test]
+    fn test_each_lint() {
+        let tests = vec![
+            ("let x:i64 = 1;", vec![Lint::UnusedVariables]),
+            ("let _x:i64 = 1;", vec![]),
+            ("let mut x:i64 = 1; x += 1;", vec![Lint::UnusedVariables]),
+            ("function f() { }", vec![Lint::UnusedFunctions]),
+            (
+                "function f(n:i64) -> i64 { if (n < 1) { 0 } else { f(n - 1) } }",
+                vec![Lint::UnusedFunctions],
+            ),
+            ("function f(x:i64) { }; f(1);", vec![Lint::UnusedParameters]),
+            (
+                "function f() -> i64 { return 1; print(2); }; print(f());",
+                vec![Lint::UnreachableCode],
+            ),
+            (
+                "let x:i64 = 1; while (x < 2) { break; print(x); print(x); }",
+                vec![Lint::UnreachableCode],
+            ),
+            ("if (1 < 2) { print(1); }", vec![Lint::ConstantCondition]),
+            (
+                "while (1) { } print(1);",
+                vec![Lint::ConstantCondition, Lint::UnreachableCode],
+            ),
+            (
+                "let x:i64 = 1; print(x); { let x:i64 = 2; print(x); }",
+                vec![],
+            ),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(lints(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_levels_from_config() {
+        let source = "let x:i64 = 1; print(x); { let x:i64 = 2; }";
+        let mut config = LintConfig::default();
+        config.set_level(Lint::Shadowing, Level::Warn);
+        config.set_level(Lint::UnusedVariables, Level::Deny);
+        let diagnostics = lint_with(source, &config);
+        let levels: Vec<(Lint, Level)> = diagnostics.iter().map(|d| (d.lint, d.level)).collect();
+        assert_eq!(
+            levels,
+            vec![
+                (Lint::Shadowing, Level::Warn),
+                (Lint::UnusedVariables, Level::Deny)
+            ]
+        );
+
+        config.deny_warnings = true;
+        let diagnostics = lint_with(source, &config);
+        assert!(diagnostics.iter().all(Diagnostic::is_error));
+    }
+
+    #[test]
+    fn test_levels_from_attributes() {
+        let source = "#[allow(unused_functions, unused_parameters)]\n#[inline(never)]\nfunction f(x:i64) { let y:i64 = 1; }\n#[deny(unused_variables)]\nlet z:i64 = 1;";
+        let diagnostics = lint_with(source, &LintConfig::default());
+        let levels: Vec<(Lint, Level)> = diagnostics.iter().map(|d| (d.lint, d.level)).collect();
+        assert_eq!(
+            levels,
+            vec![
+                (Lint::UnusedVariables, Level::Warn),
+                (Lint::UnusedVariables, Level::Deny)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_format() {
+        let mut config = LintConfig::default();
+        config.set_level(Lint::Shadowing, Level::Deny);
+        let diagnostics = lint_with("let x:i64 = 1;\n{ let x:i64 = x; print(x); }", &config);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "2:3: error: 'x' shadows an earlier declaration [shadowing]\n1:1: note: 'x' previously declared here"
+        );
+    }
+}
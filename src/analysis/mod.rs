@@ -0,0 +1,11 @@
+pub mod attributes;
+pub mod builtins;
+pub mod cfg;
+pub mod consteval;
+pub mod init;
+pub mod lint;
+pub mod mutability;
+pub mod resolve;
+pub mod returns;
+pub mod tail;
+pub mod typeck;
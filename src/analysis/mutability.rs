@@ -0,0 +1,287 @@
+use crate::parser::ast::{
+    Block, DeclKind, Expr, ExprKind, FunctionDef, Item, Program, Stmt, StmtKind,
+};
+use crate::parser::span::Span;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BindingKind {
+    Variable { mutable: bool },
+    Constant,
+    Parameter,
+}
+
+#[derive(Clone, Debug)]
+struct Binding {
+    kind: BindingKind,
+    span: Span,
+    // すでに代入されている可能性があるか
+    assigned: bool,
+    // 宣言された位置のループ(関数)の深さ
+    loop_depth: usize,
+}
+
+// 不変な束縛への再代入を検査する
+// - const と関数の引数には代入できない
+// - mut のない let は初期化されていない場合に限り一度だけ代入できる
+pub fn check_mutability(program: &Program) -> Result<(), String> {
+    let mut checker = MutabilityChecker {
+        scopes: vec![HashMap::new()],
+        loop_depth: 0,
+    };
+    for item in &program.items {
+        match item {
+            Item::Function(function) => checker.check_function(function)?,
+            Item::Stmt(stmt) => checker.check_stmt(stmt)?,
+        }
+    }
+    Ok(())
+}
+
+struct MutabilityChecker {
+    scopes: Vec<HashMap<String, Binding>>,
+    loop_depth: usize,
+}
+
+impl MutabilityChecker {
+    fn check_function(&mut self, function: &FunctionDef) -> Result<(), String> {
+        // 関数は何度でも呼ばれるので、外側の変数にとってはループと同じ扱い
+        let mut scope = HashMap::new();
+        self.loop_depth += 1;
+        for param in &function.params {
+            scope.insert(
+                param.name.clone(),
+                Binding {
+                    kind: BindingKind::Parameter,
+                    span: param.span,
+                    assigned: true,
+                    loop_depth: self.loop_depth,
+                },
+            );
+        }
+        self.scopes.push(scope);
+        let result = self.check_block(&function.body);
+        self.scopes.pop();
+        self.loop_depth -= 1;
+        result
+    }
+
+    fn check_block(&mut self, block: &Block) -> Result<(), String> {
+        self.scopes.push(HashMap::new());
+        let result = block
+            .stmts
+            .iter()
+            .try_for_each(|stmt| self.check_stmt(stmt))
+            .and_then(|_| match &block.tail {
+                Some(tail) => self.check_expr(tail),
+                None => Ok(()),
+            });
+        self.scopes.pop();
+        result
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match &stmt.kind {
+            StmtKind::Let {
+                kind,
+                mutable,
+                name,
+                value,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.check_expr(value)?;
+                }
+                let kind = match kind {
+                    DeclKind::Const => {
+                        if !value.as_ref().is_some_and(|value| self.is_constant(value)) {
+                            return Err(format!(
+                                "{}: Initializer of constant '{}' is not a constant expression",
+                                stmt.span, name
+                            ));
+                        }
+                        BindingKind::Constant
+                    }
+                    DeclKind::Let => BindingKind::Variable { mutable: *mutable },
+                };
+                self.declare(name, kind, stmt.span, value.is_some());
+                Ok(())
+            }
+            StmtKind::Assignment { name, op, value } => {
+                self.check_expr(value)?;
+                self.check_assignment(name, op.is_some(), stmt.span)
+            }
+            StmtKind::WhileLoop {
+                condition, body, ..
+            } => {
+                self.check_expr(condition)?;
+                self.loop_depth += 1;
+                let result = self.check_block(body);
+                self.loop_depth -= 1;
+                result
+            }
+            StmtKind::Return(Some(expr)) | StmtKind::Print(expr) | StmtKind::Expr(expr) => {
+                self.check_expr(expr)
+            }
+            StmtKind::Return(None) | StmtKind::Break(_) | StmtKind::Continue(_) => Ok(()),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Result<(), String> {
+        match &expr.kind {
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.check_expr(condition)?;
+                // 両方の分岐を同じ状態から検査し、代入の可能性を合流させる
+                let before = self.scopes.clone();
+                self.check_block(consequence)?;
+                let after_consequence = std::mem::replace(&mut self.scopes, before);
+                if let Some(alt) = alternative {
+                    self.check_block(alt)?;
+                }
+                for (scope, other) in self.scopes.iter_mut().zip(after_consequence) {
+                    for (name, binding) in scope.iter_mut() {
+                        if other.get(name).is_some_and(|b| b.assigned) {
+                            binding.assigned = true;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            ExprKind::Block(block) => self.check_block(block),
+            ExprKind::BinaryOp { left, right, .. } => {
+                self.check_expr(left)?;
+                self.check_expr(right)
+            }
+            ExprKind::FunctionCall { args, .. } => {
+                args.iter().try_for_each(|arg| self.check_expr(arg))
+            }
+            ExprKind::Literal(_) | ExprKind::Variable(_) => Ok(()),
+        }
+    }
+
+    fn declare(&mut self, name: &str, kind: BindingKind, span: Span, assigned: bool) {
+        let binding = Binding {
+            kind,
+            span,
+            assigned,
+            loop_depth: self.loop_depth,
+        };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    // 複合代入は現在の値を読むので、mut のない変数には一度目でも代入できない
+    fn check_assignment(&mut self, name: &str, compound: bool, span: Span) -> Result<(), String> {
+        let loop_depth = self.loop_depth;
+        // 未定義の変数は名前解決で報告する
+        let binding = match self.lookup(name) {
+            Some(binding) => binding,
+            None => return Ok(()),
+        };
+        match binding.kind {
+            BindingKind::Variable { mutable: true } => Ok(()),
+            BindingKind::Constant => Err(format!(
+                "{}: Cannot assign to constant '{}'\n{}: note: '{}' declared here",
+                span, name, binding.span, name
+            )),
+            BindingKind::Parameter => Err(format!(
+                "{}: Cannot assign to function parameter '{}'\n{}: note: '{}' declared here; parameters are immutable",
+                span, name, binding.span, name
+            )),
+            BindingKind::Variable { mutable: false } => {
+                if compound || binding.assigned || binding.loop_depth < loop_depth {
+                    Err(format!(
+                        "{}: Cannot assign twice to immutable variable '{}'\n{}: note: '{}' declared here; consider making it mutable with 'let mut {}'",
+                        span, name, binding.span, name, name
+                    ))
+                } else {
+                    binding.assigned = true;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    // 定数式はリテラル、定数、およびそれらの二項演算のみ
+    fn is_constant(&mut self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Literal(_) => true,
+            ExprKind::Variable(name) => self
+                .lookup(name)
+                .is_some_and(|binding| binding.kind == BindingKind::Constant),
+            ExprKind::BinaryOp { left, right, .. } => {
+                self.is_constant(left) && self.is_constant(right)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn check(source: &str) -> Result<(), String> {
+        let ast = parse(source);
+        check_mutability(&ast)
+    }
+
+    #[test]
+    fn test_allowed_assignments() {
+        let tests = vec![
+            "let mut x:i64 = 1; x = 2; x = 3;",
+            "let mut x:i64 = 1; x += 2; x %= 3;",
+            "x:i64 = 1; x = 2;",
+            "let x:i64; x = 1;",
+            "let x:i64; if (a < b) { x = 1; } else { x = 2; }",
+            "let x:i64 = 1; if (a < b) { let x:i64; x = 2; }",
+            "const N:i64 = 10; const M:i64 = N * 2;",
+            "function f(x:i64) { let mut y:i64 = x; y = y + 1; };",
+        ];
+        for test in tests {
+            assert!(check(test).is_ok(), "Should accept: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_rejected_assignments() {
+        let tests = vec![
+            "let x:i64 = 1; x = 2;",
+            "let x:i64; x = 1; x = 2;",
+            "let x:i64; if (a < b) { x = 1; } x = 2;",
+            "let x:i64; while (a < b) { x = 1; }",
+            "let x:i64; function f() { x = 1; };",
+            "const N:i64 = 10; N = 11;",
+            "const N:i64 = 10; N += 1;",
+            "let x:i64 = 1; x += 1;",
+            "function f(x:i64) { x *= 2; }",
+            "const N:i64 = f();",
+            "function f(x:i64) { x = 1; };",
+        ];
+        for test in tests {
+            assert!(check(test).is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_error_reports_both_spans() {
+        let result = check("let x:i64 = 1;\nprint(x);\nx = 2;");
+        assert_eq!(
+            result,
+            Err("3:1: Cannot assign twice to immutable variable 'x'\n1:1: note: 'x' declared here; consider making it mutable with 'let mut x'".to_string())
+        );
+    }
+}
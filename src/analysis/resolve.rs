@@ -0,0 +1,381 @@
+use crate::analysis::builtins::Builtin;
+use crate::parser::ast::{
+    Block, Expr, ExprKind, FunctionDef, Item, NodeId, Program, Stmt, StmtKind,
+};
+use crate::parser::span::Span;
+use std::collections::HashMap;
+
+// 名前解決で作られるシンボルの番号。同じ名前でも宣言ごとに別の番号になる
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolKind {
+    // トップレベルの let/const
+    Global,
+    // 関数やブロックの中の let/const
+    Local,
+    Parameter,
+    Function,
+}
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // 宣言された位置
+    pub span: Span,
+}
+
+// 名前解決の結果
+#[derive(Clone, Debug, Default)]
+pub struct Resolutions {
+    pub symbols: Vec<Symbol>,
+    // 宣言 (let/const の文、引数、関数定義) と参照 (変数、関数呼び出し、代入文) のノードからシンボルへの対応
+    pub bindings: HashMap<NodeId, SymbolId>,
+}
+
+impl Resolutions {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    // ノードが宣言または参照しているシンボル
+    pub fn lookup(&self, node: NodeId) -> Option<SymbolId> {
+        self.bindings.get(&node).copied()
+    }
+}
+
+// すべての変数と関数呼び出しを宣言に対応付ける
+// - 関数はトップレベルのどこからでも呼べる (定義より前からも呼べる)
+// - 変数は宣言の後から、宣言したブロックの終わりまで見える。初期化式の中ではまだ見えない
+// - 関数の本体からは、その関数より前に宣言されたトップレベルの変数が見える
+// - 内側のブロックの宣言は外側の同じ名前を隠す。同じスコープで同じ名前を二度宣言するとエラー
+// - 引数は関数本体の一番外側のブロックと同じスコープに属する
+// - 関数とトップレベルの変数は同じ名前空間を使う
+pub fn resolve_names(program: &Program) -> Result<Resolutions, String> {
+    let mut resolver = Resolver {
+        resolutions: Resolutions::default(),
+        scopes: vec![HashMap::new()],
+        errors: Vec::new(),
+    };
+    for item in &program.items {
+        if let Item::Function(function) = item {
+            resolver.declare(
+                &function.name,
+                SymbolKind::Function,
+                function.span,
+                function.id,
+            );
+        }
+    }
+    for item in &program.items {
+        match item {
+            Item::Function(function) => resolver.resolve_function(function),
+            Item::Stmt(stmt) => resolver.resolve_stmt(stmt),
+        }
+    }
+    if resolver.errors.is_empty() {
+        Ok(resolver.resolutions)
+    } else {
+        Err(resolver.errors.join("\n"))
+    }
+}
+
+struct Resolver {
+    resolutions: Resolutions,
+    scopes: Vec<HashMap<String, SymbolId>>,
+    // 見つかったエラーはまとめて報告する
+    errors: Vec<String>,
+}
+
+impl Resolver {
+    fn resolve_function(&mut self, function: &FunctionDef) {
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            self.declare(&param.name, SymbolKind::Parameter, param.span, param.id);
+        }
+        self.resolve_block_contents(&function.body);
+        self.scopes.pop();
+    }
+
+    fn resolve_block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        self.resolve_block_contents(block);
+        self.scopes.pop();
+    }
+
+    // スコープを作らずにブロックの中身を解決する
+    fn resolve_block_contents(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.resolve_stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.resolve_expr(tail);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+                let kind = if self.scopes.len() == 1 {
+                    SymbolKind::Global
+                } else {
+                    SymbolKind::Local
+                };
+                self.declare(name, kind, stmt.span, stmt.id);
+            }
+            StmtKind::Assignment { name, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_variable(name, stmt.span, stmt.id);
+            }
+            StmtKind::WhileLoop {
+                condition, body, ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_block(body);
+            }
+            StmtKind::Return(Some(expr)) | StmtKind::Print(expr) | StmtKind::Expr(expr) => {
+                self.resolve_expr(expr)
+            }
+            StmtKind::Return(None) | StmtKind::Break(_) | StmtKind::Continue(_) => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.resolve_variable(name, expr.span, expr.id),
+            ExprKind::FunctionCall { name, args } => {
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+                self.resolve_function_call(name, expr.span, expr.id);
+            }
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_block(consequence);
+                if let Some(alt) = alternative {
+                    self.resolve_block(alt);
+                }
+            }
+            ExprKind::BinaryOp { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            ExprKind::Block(block) => self.resolve_block(block),
+            ExprKind::Literal(_) => {}
+        }
+    }
+
+    fn declare(&mut self, name: &str, kind: SymbolKind, span: Span, node: NodeId) {
+        if let Some(previous) = self.scopes.last().and_then(|scope| scope.get(name)) {
+            // 関数は先に宣言するので、先の宣言がソースでは後ろにあることがある
+            // エラーはソースで後ろにある方に付ける
+            let previous = self.resolutions.symbol(*previous).span;
+            let (first, second) = if (previous.line, previous.column) <= (span.line, span.column) {
+                (previous, span)
+            } else {
+                (span, previous)
+            };
+            self.errors.push(format!(
+                "{}: Duplicate definition of '{}' in the same scope\n{}: note: '{}' first defined here",
+                second, name, first, name
+            ));
+            return;
+        }
+        let id = SymbolId(self.resolutions.symbols.len());
+        self.resolutions.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+        });
+        self.resolutions.bindings.insert(node, id);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), id);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn resolve_variable(&mut self, name: &str, span: Span, node: NodeId) {
+        match self.lookup(name) {
+            Some(id) if self.resolutions.symbol(id).kind == SymbolKind::Function => {
+                let symbol = self.resolutions.symbol(id);
+                self.errors.push(format!(
+                    "{}: '{}' is a function, not a variable\n{}: note: '{}' defined here",
+                    span, name, symbol.span, name
+                ));
+            }
+            Some(id) => {
+                self.resolutions.bindings.insert(node, id);
+            }
+            None => self
+                .errors
+                .push(format!("{}: Undefined variable '{}'", span, name)),
+        }
+    }
+
+    fn resolve_function_call(&mut self, name: &str, span: Span, node: NodeId) {
+        match self.lookup(name) {
+            Some(id) if self.resolutions.symbol(id).kind == SymbolKind::Function => {
+                self.resolutions.bindings.insert(node, id);
+            }
+            Some(id) => {
+                let symbol = self.resolutions.symbol(id);
+                self.errors.push(format!(
+                    "{}: '{}' is not a function\n{}: note: '{}' defined here",
+                    span, name, symbol.span, name
+                ));
+            }
+            // 組み込み関数の呼び出しはシンボルに結び付けない
+            None if Builtin::from_name(name).is_some() => {}
+            None => self
+                .errors
+                .push(format!("{}: Undefined function '{}'", span, name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::parse;
+
+    fn resolve(source: &str) -> Result<Resolutions, String> {
+        resolve_names(&parse(source))
+    }
+
+    #[test]
+    fn test_resolved_programs() {
+        let tests = vec![
+            "let x:i64 = 1; print(x);",
+            "let x:i64 = 1; { let x:i64 = x + 1; print(x); }",
+            "let x:i64 = 1; if (x < 2) { let y:i64 = x; print(y); }",
+            "print(f(1)); function f(x:i64) -> i64 { x };",
+            "function f(x:i64) -> i64 { if (x < 1) { 0 } else { g(x) } }; function g(x:i64) -> i64 { f(x - 1) };",
+            "let n:i64 = 1; function f(x:i64) -> i64 { x + n };",
+            "function f(x:i64) { let y:i64 = x; { let x:i64 = y; print(x); } };",
+            "let mut i:i64 = 0; while (i < 10) { i += 1; }",
+        ];
+        for test in tests {
+            assert!(resolve(test).is_ok(), "Should resolve: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_unresolved_programs() {
+        let tests = vec![
+            "print(x);",
+            "x = 1;",
+            "let x:i64 = x;",
+            "{ let x:i64 = 1; } print(x);",
+            "print(f(1));",
+            "let x:i64 = 1; let x:i64 = 2;",
+            "function f(x:i64, x:i64) { };",
+            "function f(x:i64) { let x:i64 = 1; };",
+            "function f() { }; function f() { };",
+            "function f() { }; let f:i64 = 1;",
+            "function f() { }; print(f);",
+            "let f:i64 = 1; print(f());",
+            "function f() -> i64 { n }; let n:i64 = 1;",
+        ];
+        for test in tests {
+            assert!(resolve(test).is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_errors_report_spans() {
+        assert_eq!(
+            resolve("let x:i64 = 1;\nprint(y);\nlet x:i64 = 2;").unwrap_err(),
+            "2:7: Undefined variable 'y'\n3:1: Duplicate definition of 'x' in the same scope\n1:1: note: 'x' first defined here"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_reported_at_later_definition() {
+        assert_eq!(
+            resolve("let f:i64 = 1; function f() {}").unwrap_err(),
+            "1:16: Duplicate definition of 'f' in the same scope\n1:1: note: 'f' first defined here"
+        );
+        assert_eq!(
+            resolve("function f() {}\nlet f:i64 = 1;").unwrap_err(),
+            "2:1: Duplicate definition of 'f' in the same scope\n1:1: note: 'f' first defined here"
+        );
+    }
+
+    #[test]
+    fn test_builtin_calls() {
+        let call = |program: &Program| match program.items.last() {
+            Some(Item::Stmt(Stmt {
+                kind: StmtKind::Print(call),
+                ..
+            })) => call.id,
+            item => panic!("Unexpected item: {:?}", item),
+        };
+        // 組み込み関数の呼び出しはシンボルに結び付かない
+        let program = parse("print(wrapping_add(1, 2));");
+        let resolutions = resolve_names(&program).unwrap();
+        assert_eq!(resolutions.lookup(call(&program)), None);
+        // 同じ名前の関数を定義すればそちらを呼ぶ
+        let program = parse("function wrapping_mul(x:i64) -> i64 { x }; print(wrapping_mul(1));");
+        let resolutions = resolve_names(&program).unwrap();
+        assert!(resolutions.lookup(call(&program)).is_some());
+        assert_eq!(
+            resolve("print(wrapping_div(1, 2));").unwrap_err(),
+            "1:7: Undefined function 'wrapping_div'"
+        );
+    }
+
+    #[test]
+    fn test_shadowing_creates_distinct_symbols() {
+        let program = parse("let x:i64 = 1; { let x:i64 = x; print(x); }");
+        let resolutions = resolve_names(&program).unwrap();
+        let outer = match &program.items[0] {
+            Item::Stmt(stmt) => resolutions.lookup(stmt.id).unwrap(),
+            item => panic!("Unexpected item: {:?}", item),
+        };
+        let block = match &program.items[1] {
+            Item::Stmt(Stmt {
+                kind:
+                    StmtKind::Expr(Expr {
+                        kind: ExprKind::Block(block),
+                        ..
+                    }),
+                ..
+            }) => block,
+            item => panic!("Unexpected item: {:?}", item),
+        };
+        let (inner, initializer) = match &block.stmts[0] {
+            Stmt {
+                kind: StmtKind::Let {
+                    value: Some(value), ..
+                },
+                id,
+                ..
+            } => (resolutions.lookup(*id).unwrap(), value),
+            stmt => panic!("Unexpected statement: {:?}", stmt),
+        };
+        let printed = match &block.stmts[1].kind {
+            StmtKind::Print(expr) => expr,
+            stmt => panic!("Unexpected statement: {:?}", stmt),
+        };
+        assert_ne!(outer, inner);
+        assert_eq!(resolutions.symbol(outer).kind, SymbolKind::Global);
+        assert_eq!(resolutions.symbol(inner).kind, SymbolKind::Local);
+        assert_eq!(resolutions.lookup(initializer.id), Some(outer));
+        assert_eq!(resolutions.lookup(printed.id), Some(inner));
+    }
+}
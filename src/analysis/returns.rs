@@ -0,0 +1,205 @@
+use crate::analysis::cfg::ControlFlow;
+use crate::analysis::typeck::TypeTable;
+use crate::parser::ast::{Block, Expr, ExprKind, FunctionDef, Item, Program, Stmt, StmtKind};
+use crate::parser::span::Span;
+use crate::parser::visit::{walk_stmt, Visitor};
+
+// 関数の戻り値の型を検査する
+// - すべての return とブロックの値が宣言された戻り値の型と一致すること
+// - 戻り値の型を持つ関数が、return を通らずに本体の終わりに達する経路で値を返すこと
+//   経路の有無は制御フローグラフで判断する
+pub fn check_function_returns(
+    program: &Program,
+    types: &TypeTable,
+    control_flow: &ControlFlow,
+) -> Result<(), String> {
+    for item in &program.items {
+        if let Item::Function(function) = item {
+            let mut checker = ReturnChecker {
+                function,
+                types,
+                control_flow,
+                result: Ok(()),
+            };
+            checker.visit_block(&function.body);
+            checker.result.clone()?;
+            checker.check_block_value(&function.body)?;
+        }
+    }
+    Ok(())
+}
+
+struct ReturnChecker<'a> {
+    function: &'a FunctionDef,
+    types: &'a TypeTable,
+    control_flow: &'a ControlFlow,
+    // 最初に見つかった return の誤り
+    result: Result<(), String>,
+}
+
+// 式の中 (print の引数や演算子のオペランドなど) にある return も含め、すべての return を検査する
+impl<'a> Visitor<'a> for ReturnChecker<'a> {
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let (Ok(()), StmtKind::Return(value)) = (&self.result, &stmt.kind) {
+            self.result = self.check_value(value.as_ref(), stmt.span);
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+impl ReturnChecker<'_> {
+    // 関数本体の値(末尾の式)が戻り値になる。return で抜けない経路はここで検査する
+    fn check_block_value(&self, block: &Block) -> Result<(), String> {
+        // 終わりに到達しないブロックは値を返さなくてよい
+        let end_reachable = match (&block.tail, block.stmts.last()) {
+            (Some(tail), _) => self.control_flow.is_reachable(tail.id),
+            (None, Some(last)) => self.control_flow.completes(last.id),
+            (None, None) => true,
+        };
+        if !end_reachable {
+            return Ok(());
+        }
+        match (&block.tail, &self.function.return_type) {
+            (Some(tail), _) => match &tail.kind {
+                ExprKind::IfExpr {
+                    consequence,
+                    alternative: Some(alt),
+                    ..
+                } => {
+                    self.check_block_value(consequence)?;
+                    self.check_block_value(alt)
+                }
+                ExprKind::Block(inner) => self.check_block_value(inner),
+                _ => self.check_value(Some(tail), tail.span),
+            },
+            (None, Some(type_name)) => Err(format!(
+                "{}: Function '{}' declared to return '{}' does not return a value on all paths",
+                self.function.span, self.function.name, type_name
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn check_value(&self, value: Option<&Expr>, span: Span) -> Result<(), String> {
+        let found = value.and_then(|value| self.types.expr_type(value.id));
+        let function = &self.function.name;
+        match (&self.function.return_type, found) {
+            (None, None) => Ok(()),
+            (None, Some(found)) => Err(format!(
+                "{}: Function '{}' has no return type but returns a value of type '{}'",
+                span, function, found
+            )),
+            (Some(expected), None) => Err(format!(
+                "{}: Function '{}' must return a value of type '{}'",
+                span, function, expected
+            )),
+            (Some(expected), Some(found)) if expected == found => Ok(()),
+            (Some(expected), Some(found)) => Err(format!(
+                "{}: Type mismatch in return of function '{}': expected '{}', found '{}'",
+                span, function, expected, found
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::cfg::build_control_flow;
+    use crate::analysis::resolve::resolve_names;
+    use crate::analysis::typeck::check_types;
+    use crate::test_util::parse;
+
+    fn check(source: &str) -> Result<(), String> {
+        let ast = parse(source);
+        let resolutions = resolve_names(&ast).expect("Failed to resolve names");
+        let types = check_types(&ast, &resolutions).expect("Failed to check types");
+        check_function_returns(&ast, &types, &build_control_flow(&ast))
+    }
+
+    #[test]
+    fn test_matching_returns() {
+        let tests = vec![
+            "function add(x:i64, y:i64) -> i64 { return x + y; };",
+            "function one() -> i64 { return 1; };",
+            "function f(x:i32) -> i32 { if (x < 1) { return 0; } else { return x; } };",
+            "function g() { print(1); return; };",
+            "function two() -> i64 { return 2; }; function h() -> i64 { return two(); };",
+            "function f(x:i64) -> i64 { let y = x; return y; };",
+            "function add(x:i64, y:i64) -> i64 { x + y }",
+            "function max(x:i64, y:i64) -> i64 { if (x > y) { x } else { y } }",
+            "function f(x:i64) -> i64 { if (x < 1) { return 0; } else { x } }",
+            "function f() { if (1 < 2) { print(1); } }",
+            "function f() -> i64 { while (1) { return 1; } }",
+            "function f(x:i64) -> i64 { if (x < 1) { return 0; } else { return 1; } print(x); }",
+        ];
+        for test in tests {
+            assert!(check(test).is_ok(), "Should accept: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_mismatched_returns() {
+        let tests = vec![
+            "function f(x:i64) -> i32 { return x; };",
+            "function f() -> i64 { return \"hello\"; };",
+            "function f() { return 1; };",
+            "function f() -> i64 { return; };",
+            "function g() { print(1); }; function f() -> i64 { return g(); };",
+        ];
+        for test in tests {
+            assert!(check(test).is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_nested_returns() {
+        // 式の中の return も検査する
+        let tests = vec![
+            "function f(a:i32) -> i64 { print({ return a; 1 }); 2 }",
+            "function g(x:i64) -> i64 { x } function f(a:i32) -> i64 { g({ return a; 1 }) }",
+            "function f(a:i32) -> i64 { let b:i64 = 1 + { return a; 2 }; b }",
+            "function f(a:i32) -> i64 { if ({ return a; 1 }) { 1 } else { 2 } }",
+            "function f(a:i32) -> i64 { while ({ return a; 1 }) { } 2 }",
+        ];
+        for test in tests {
+            let result = check(test);
+            assert!(
+                result
+                    .as_ref()
+                    .is_err_and(|e| e.contains("Type mismatch in return of function 'f'")),
+                "Should reject: {}: {:?}",
+                test,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_return_on_some_path() {
+        let tests = vec![
+            "function f() -> i64 { print(1); };",
+            "function f(x:i64) -> i64 { if (x < 1) { return 0; } };",
+            "function f(x:i64) -> i64 { while (x < 1) { return 0; } };",
+            "function f(x:i64) -> i64 { if (x < 1) { x } }",
+            "function f(x:i64) -> i32 { x }",
+            "function f(x:i64) { x }",
+            "function f() -> i64 { while (1) { break; } }",
+        ];
+        for test in tests {
+            assert!(check(test).is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_error_reports_position() {
+        let result = check("function f(x:i64) -> i32 {\n    return x;\n}");
+        assert_eq!(
+            result,
+            Err(
+                "2:5: Type mismatch in return of function 'f': expected 'i32', found 'i64'"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::analysis::resolve::Resolutions;
+use crate::analysis::typeck::TypeTable;
+use crate::parser::ast::{ExprKind, FunctionDef, Program, Stmt, StmtKind, Type};
+use crate::parser::visit::{walk_function_def, walk_stmt, Visitor};
+
+// #[tail] return f(...) を検査する
+// 呼び出し元のフレームを使い回して f へ飛ぶので、次のときは末尾呼び出しにできない
+// - return する値が関数の呼び出しでない (組み込み関数は命令になるので呼び出しではない)
+// - 関数の外にある
+// - f の引数が呼び出し元の引数より多い。引数は呼び出し元が積んだ場所に書き直すので、そこに収まらない
+pub fn check_tail_calls(
+    program: &Program,
+    resolutions: &Resolutions,
+    types: &TypeTable,
+) -> Result<(), String> {
+    let mut checker = TailCallChecker {
+        resolutions,
+        types,
+        function: None,
+        result: Ok(()),
+    };
+    checker.visit_program(program);
+    checker.result
+}
+
+struct TailCallChecker<'a> {
+    resolutions: &'a Resolutions,
+    types: &'a TypeTable,
+    // 検査中の関数。トップレベルでは None
+    function: Option<&'a FunctionDef>,
+    result: Result<(), String>,
+}
+
+impl<'a> TailCallChecker<'a> {
+    fn check_stmt(&self, stmt: &Stmt) -> Result<(), String> {
+        // ほかの文に付いた #[tail] は check_attributes で弾いている
+        let StmtKind::Return(value) = &stmt.kind else {
+            return Ok(());
+        };
+        if !stmt.attrs.iter().any(|attr| attr.name == "tail") {
+            return Ok(());
+        }
+        let Some(function) = self.function else {
+            return Err(format!(
+                "{}: '#[tail] return' is only allowed inside functions",
+                stmt.span
+            ));
+        };
+        let Some(value) = value else {
+            return Err(format!(
+                "{}: '#[tail] return' expects a function call",
+                stmt.span
+            ));
+        };
+        let ExprKind::FunctionCall { name, .. } = &value.kind else {
+            return Err(format!(
+                "{}: Cannot make a tail call: the returned value is not a function call",
+                value.span
+            ));
+        };
+        let Some(symbol) = self.resolutions.lookup(value.id) else {
+            return Err(format!(
+                "{}: Cannot make a tail call to builtin '{}'",
+                value.span, name
+            ));
+        };
+        let Some(Type::Function { params, .. }) = self.types.symbol_type(symbol) else {
+            return Err(format!("{}: '{}' is not a function", value.span, name));
+        };
+        if params.len() > function.params.len() {
+            return Err(format!(
+                "{}: Cannot make a tail call to '{}': it takes {} argument(s) but '{}' takes only {}",
+                value.span,
+                name,
+                params.len(),
+                function.name,
+                function.params.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Visitor<'a> for TailCallChecker<'a> {
+    fn visit_function_def(&mut self, function: &'a FunctionDef) {
+        self.function = Some(function);
+        walk_function_def(self, function);
+        self.function = None;
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if self.result.is_ok() {
+            self.result = self.check_stmt(stmt);
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::resolve::resolve_names;
+    use crate::analysis::typeck::check_types;
+    use crate::test_util::parse;
+
+    fn check(source: &str) -> Result<(), String> {
+        let ast = parse(source);
+        let resolutions = resolve_names(&ast).expect("Failed to resolve names");
+        let types = check_types(&ast, &resolutions).expect("Failed to check types");
+        check_tail_calls(&ast, &resolutions, &types)
+    }
+
+    #[test]
+    fn test_valid_tail_calls() {
+        let tests = vec![
+            "function f(n:i64) -> i64 { if (n < 1) { return 0; } #[tail] return f(n - 1); }",
+            "function g(a:i64, b:i64) -> i64 { a } function f(a:i64, b:i64) -> i64 { #[tail] return g(b, a); }",
+            "function g() { print(1); } function f(x:i64) { #[tail] return g(); }",
+        ];
+        for test in tests {
+            assert_eq!(check(test), Ok(()), "Should accept: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_invalid_tail_calls() {
+        let tests = vec![
+            (
+                "function f(x:i64) -> i64 { #[tail] return x + 1; }",
+                "1:43: Cannot make a tail call: the returned value is not a function call",
+            ),
+            (
+                "function f(x:i64) -> i64 { #[tail] return wrapping_add(x, 1); }",
+                "1:43: Cannot make a tail call to builtin 'wrapping_add'",
+            ),
+            (
+                "function g(a:i64, b:i64) -> i64 { a } function f(a:i64) -> i64 { #[tail] return g(a, a); }",
+                "1:81: Cannot make a tail call to 'g': it takes 2 argument(s) but 'f' takes only 1",
+            ),
+        ];
+        for (source, message) in tests {
+            assert_eq!(check(source), Err(message.to_string()), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,643 @@
+use crate::analysis::builtins::Builtin;
+use crate::analysis::resolve::{Resolutions, SymbolId};
+use crate::parser::ast::{
+    Block, Expr, ExprKind, FunctionDef, Item, Literal, NodeId, Op, Program, Stmt, StmtKind, Type,
+};
+use crate::parser::span::Span;
+use std::collections::HashMap;
+
+// 型検査の結果
+#[derive(Clone, Debug, Default)]
+pub struct TypeTable {
+    // 値を持つ式の型。値を持たない式 (戻り値のない関数の呼び出しなど) は含まない
+    pub exprs: HashMap<NodeId, Type>,
+    // 変数、引数、関数の型
+    pub symbols: HashMap<SymbolId, Type>,
+}
+
+impl TypeTable {
+    pub fn expr_type(&self, node: NodeId) -> Option<&Type> {
+        self.exprs.get(&node)
+    }
+
+    pub fn symbol_type(&self, symbol: SymbolId) -> Option<&Type> {
+        self.symbols.get(&symbol)
+    }
+}
+
+// すべての式の型を求め、演算子、代入、print の型を検査する
+// - 算術演算と比較は同じ整数型 (i32 か i64) どうしでのみ行える。比較の結果は i32
+// - 整数リテラルは文脈が i64 を期待していれば i64 になる (`let x:i64 = 1 + 2;`)
+// - if/while の条件は整数。else のある if の両方の分岐が値を持つなら型が一致すること
+//   return などで抜ける分岐は、もう一方の分岐の型に合わせる
+// - 型注釈のない let は初期化式の型になる
+// - 関数呼び出しの引数は定義と同じ数で、各引数は引数の型 (i64 の引数には i32 も渡せる)
+// 戻り値の型は check_function_returns で検査する
+pub fn check_types(program: &Program, resolutions: &Resolutions) -> Result<TypeTable, String> {
+    let mut checker = TypeChecker {
+        resolutions,
+        types: TypeTable::default(),
+        return_type: None,
+    };
+    // 関数は定義より前から呼べるので、先にシグネチャを登録しておく
+    for item in &program.items {
+        if let Item::Function(function) = item {
+            checker.declare_function(function)?;
+        }
+    }
+    for item in &program.items {
+        match item {
+            Item::Function(function) => checker.check_function(function)?,
+            Item::Stmt(stmt) => checker.check_stmt(stmt)?,
+        }
+    }
+    Ok(checker.types)
+}
+
+struct TypeChecker<'a> {
+    resolutions: &'a Resolutions,
+    types: TypeTable,
+    // 検査中の関数の戻り値の型
+    return_type: Option<Type>,
+}
+
+impl TypeChecker<'_> {
+    fn declare_function(&mut self, function: &FunctionDef) -> Result<(), String> {
+        for param in &function.params {
+            check_type_exists(&param.ty, param.span)?;
+            let symbol = self.symbol(param.id, &param.name)?;
+            self.types.symbols.insert(symbol, param.ty.clone());
+        }
+        if let Some(return_type) = &function.return_type {
+            check_type_exists(return_type, function.span)?;
+        }
+        let symbol = self.symbol(function.id, &function.name)?;
+        self.types.symbols.insert(
+            symbol,
+            Type::Function {
+                params: function
+                    .params
+                    .iter()
+                    .map(|param| param.ty.clone())
+                    .collect(),
+                return_type: function.return_type.clone().map(Box::new),
+            },
+        );
+        Ok(())
+    }
+
+    fn check_function(&mut self, function: &FunctionDef) -> Result<(), String> {
+        self.return_type = function.return_type.clone();
+        let return_type = function.return_type.as_ref();
+        let result = self.check_block(&function.body, return_type);
+        self.return_type = None;
+        result.map(|_| ())
+    }
+
+    // expected は文脈が期待する型。整数リテラルの型を決めるのに使う
+    fn check_block(
+        &mut self,
+        block: &Block,
+        expected: Option<&Type>,
+    ) -> Result<Option<Type>, String> {
+        for stmt in &block.stmts {
+            self.check_stmt(stmt)?;
+        }
+        match &block.tail {
+            Some(tail) => self.check_expr(tail, expected),
+            None => Ok(None),
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match &stmt.kind {
+            StmtKind::Let {
+                name,
+                type_decl,
+                value,
+                ..
+            } => {
+                if let Some(ty) = type_decl {
+                    check_type_exists(ty, stmt.span)?;
+                }
+                let found = match value {
+                    Some(value) => self.check_expr(value, type_decl.as_ref())?,
+                    None => None,
+                };
+                let ty = match (type_decl, value) {
+                    (Some(ty), Some(value)) => {
+                        let context = format!("declaration of '{}'", name);
+                        expect_type(ty, found, value.span, &context)?;
+                        ty.clone()
+                    }
+                    (Some(ty), None) => ty.clone(),
+                    (None, Some(value)) => found.ok_or_else(|| {
+                        format!(
+                            "{}: Cannot infer the type of '{}' from an expression without a value",
+                            value.span, name
+                        )
+                    })?,
+                    (None, None) => {
+                        return Err(format!(
+                            "{}: Type annotation needed for '{}'",
+                            stmt.span, name
+                        ))
+                    }
+                };
+                let symbol = self.symbol(stmt.id, name)?;
+                self.types.symbols.insert(symbol, ty);
+                Ok(())
+            }
+            StmtKind::Assignment { name, op, value } => {
+                let ty = self.symbol_type(stmt.id, name, stmt.span)?;
+                let found = self.check_expr(value, Some(&ty))?;
+                if let Some(op) = op {
+                    if !is_integer(&ty) {
+                        return Err(format!(
+                            "{}: Operator '{}=' cannot be applied to '{}'",
+                            stmt.span, op, ty
+                        ));
+                    }
+                }
+                expect_type(&ty, found, value.span, &format!("assignment to '{}'", name))
+            }
+            StmtKind::WhileLoop {
+                condition, body, ..
+            } => {
+                self.check_condition(condition)?;
+                self.check_block(body, None).map(|_| ())
+            }
+            StmtKind::Return(Some(value)) => {
+                let return_type = self.return_type.clone();
+                self.check_expr(value, return_type.as_ref()).map(|_| ())
+            }
+            StmtKind::Print(expr) => match self.check_expr(expr, None)? {
+                Some(ty) if is_integer(&ty) || ty.is_named("string") => Ok(()),
+                Some(ty) => Err(format!(
+                    "{}: Cannot print a value of type '{}'",
+                    expr.span, ty
+                )),
+                None => Err(format!(
+                    "{}: print expects a value, found an expression without a value",
+                    expr.span
+                )),
+            },
+            StmtKind::Expr(expr) => self.check_expr(expr, None).map(|_| ()),
+            StmtKind::Return(None) | StmtKind::Break(_) | StmtKind::Continue(_) => Ok(()),
+        }
+    }
+
+    // 式の型を求めて記録する。値を持たない式は None
+    fn check_expr(&mut self, expr: &Expr, expected: Option<&Type>) -> Result<Option<Type>, String> {
+        let ty = match &expr.kind {
+            ExprKind::Literal(Literal::I32(_)) => match expected {
+                Some(ty) if ty.is_named("i64") => Some(Type::named("i64")),
+                _ => Some(Type::named("i32")),
+            },
+            ExprKind::Literal(Literal::I64(_)) => Some(Type::named("i64")),
+            ExprKind::Literal(Literal::String(_)) => Some(Type::named("string")),
+            ExprKind::Literal(Literal::Unit) => None,
+            ExprKind::Variable(name) => Some(self.symbol_type(expr.id, name, expr.span)?),
+            ExprKind::FunctionCall { name, args } => self.check_call(expr, name, args, expected)?,
+            ExprKind::BinaryOp { left, op, right } => {
+                self.check_binary_op(left, op, right, expected, expr.span)?
+            }
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.check_condition(condition)?;
+                let mut consequence_ty = self.check_block(consequence, expected)?;
+                match alternative {
+                    Some(alt) => {
+                        let alt_ty = self.check_block(alt, expected.or(consequence_ty.as_ref()))?;
+                        // then 側がリテラルのときは else 側の型に合わせる
+                        if alt_ty.is_some() && consequence_ty != alt_ty {
+                            consequence_ty = self.check_block(consequence, alt_ty.as_ref())?;
+                        }
+                        // return などで抜ける分岐は値を持たず、もう一方の分岐の型になる
+                        match (consequence_ty, alt_ty) {
+                            (None, ty) if diverges(consequence) => ty,
+                            (ty, None) if diverges(alt) => ty,
+                            (Some(a), Some(b)) if a != b => {
+                                return Err(format!(
+                                    "{}: 'if' and 'else' have incompatible types: '{}' and '{}'",
+                                    expr.span, a, b
+                                ))
+                            }
+                            (Some(a), Some(_)) => Some(a),
+                            _ => None,
+                        }
+                    }
+                    None => None,
+                }
+            }
+            ExprKind::Block(block) => self.check_block(block, expected)?,
+        };
+        if let Some(ty) = &ty {
+            self.types.exprs.insert(expr.id, ty.clone());
+        }
+        Ok(ty)
+    }
+
+    // 引数の数と型を関数の定義と照合する。i32 の引数は i64 の引数に渡せる
+    fn check_call(
+        &mut self,
+        call: &Expr,
+        name: &str,
+        args: &[Expr],
+        expected: Option<&Type>,
+    ) -> Result<Option<Type>, String> {
+        if let (None, Some(builtin)) = (self.resolutions.lookup(call.id), Builtin::from_name(name))
+        {
+            return self.check_builtin_call(call, name, builtin, args, expected);
+        }
+        let (params, return_type) = match self.symbol_type(call.id, name, call.span)? {
+            Type::Function {
+                params,
+                return_type,
+            } => (params, return_type),
+            ty => {
+                return Err(format!(
+                    "{}: '{}' of type '{}' is not a function",
+                    call.span, name, ty
+                ))
+            }
+        };
+        let definition = self.resolutions.symbol(self.symbol(call.id, name)?).span;
+        if args.len() != params.len() {
+            return Err(format!(
+                "{}: Function '{}' takes {} but {} supplied\n{}: note: '{}' defined here",
+                call.span,
+                name,
+                count_arguments(params.len()),
+                match args.len() {
+                    1 => "1 was".to_string(),
+                    n => format!("{} were", n),
+                },
+                definition,
+                name
+            ));
+        }
+        for (i, (arg, param)) in args.iter().zip(&params).enumerate() {
+            let found = self.check_expr(arg, Some(param))?;
+            let widened =
+                param.is_named("i64") && found.as_ref().is_some_and(|ty| ty.is_named("i32"));
+            if found.as_ref() == Some(param) || widened {
+                continue;
+            }
+            let found = match found {
+                Some(found) => format!("'{}'", found),
+                None => "an expression without a value".to_string(),
+            };
+            return Err(format!(
+                "{}: Type mismatch in argument {} of '{}': expected '{}', found {}\n{}: note: '{}' defined here",
+                arg.span,
+                i + 1,
+                name,
+                param,
+                found,
+                definition,
+                name
+            ));
+        }
+        Ok(return_type.map(|ty| *ty))
+    }
+
+    // wrapping_add などは、同じ型の2つの整数を取り、その型を返す
+    fn check_builtin_call(
+        &mut self,
+        call: &Expr,
+        name: &str,
+        builtin: Builtin,
+        args: &[Expr],
+        expected: Option<&Type>,
+    ) -> Result<Option<Type>, String> {
+        let [left, right] = args else {
+            return Err(format!(
+                "{}: Builtin function '{}' takes 2 arguments but {} supplied",
+                call.span,
+                name,
+                match args.len() {
+                    1 => "1 was".to_string(),
+                    n => format!("{} were", n),
+                }
+            ));
+        };
+        self.check_binary_op(left, &builtin.op(), right, expected, call.span)
+    }
+
+    fn check_binary_op(
+        &mut self,
+        left: &Expr,
+        op: &Op,
+        right: &Expr,
+        expected: Option<&Type>,
+        span: Span,
+    ) -> Result<Option<Type>, String> {
+        let comparison = matches!(op, Op::LessThan | Op::GreaterThan);
+        let hint = if comparison {
+            None
+        } else {
+            expected.filter(|ty| is_integer(ty))
+        };
+        let mut left_ty = self.check_expr(left, hint)?;
+        let right_ty = self.check_expr(right, left_ty.as_ref().or(hint))?;
+        // 左辺がリテラルのときは右辺の型に合わせる
+        if right_ty.is_some() && left_ty != right_ty {
+            left_ty = self.check_expr(left, right_ty.as_ref())?;
+        }
+        match (left_ty, right_ty) {
+            (Some(l), Some(r)) if is_integer(&l) && l == r => {
+                Ok(Some(if comparison { Type::named("i32") } else { l }))
+            }
+            (Some(l), Some(r)) => Err(format!(
+                "{}: Operator '{}' cannot be applied to '{}' and '{}'",
+                span, op, l, r
+            )),
+            _ => Err(format!("{}: Operands of '{}' must have values", span, op)),
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expr) -> Result<(), String> {
+        match self.check_expr(condition, None)? {
+            Some(ty) if is_integer(&ty) => Ok(()),
+            Some(ty) => Err(format!(
+                "{}: Condition must be an integer, found '{}'",
+                condition.span, ty
+            )),
+            None => Err(format!(
+                "{}: Condition must be an integer, found an expression without a value",
+                condition.span
+            )),
+        }
+    }
+
+    fn symbol(&self, node: NodeId, name: &str) -> Result<SymbolId, String> {
+        self.resolutions
+            .lookup(node)
+            .ok_or_else(|| format!("Name '{}' was not resolved", name))
+    }
+
+    fn symbol_type(&self, node: NodeId, name: &str, span: Span) -> Result<Type, String> {
+        let symbol = self.symbol(node, name)?;
+        self.types
+            .symbol_type(symbol)
+            .cloned()
+            .ok_or_else(|| format!("{}: Type of '{}' is not known", span, name))
+    }
+}
+
+// ブロックが return、break、continue で抜け、終わりに達しないか
+fn diverges(block: &Block) -> bool {
+    match (&block.tail, block.stmts.last()) {
+        (Some(tail), _) => expr_diverges(tail),
+        (None, Some(last)) => match &last.kind {
+            StmtKind::Return(_) | StmtKind::Break(_) | StmtKind::Continue(_) => true,
+            StmtKind::Expr(expr) => expr_diverges(expr),
+            _ => false,
+        },
+        (None, None) => false,
+    }
+}
+
+fn expr_diverges(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::IfExpr {
+            consequence,
+            alternative: Some(alt),
+            ..
+        } => diverges(consequence) && diverges(alt),
+        ExprKind::Block(block) => diverges(block),
+        _ => false,
+    }
+}
+
+fn is_integer(ty: &Type) -> bool {
+    ty.is_named("i32") || ty.is_named("i64")
+}
+
+fn count_arguments(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
+
+fn expect_type(
+    expected: &Type,
+    found: Option<Type>,
+    span: Span,
+    context: &str,
+) -> Result<(), String> {
+    match found {
+        Some(found) if found == *expected => Ok(()),
+        Some(found) => Err(format!(
+            "{}: Type mismatch in {}: expected '{}', found '{}'",
+            span, context, expected, found
+        )),
+        None => Err(format!(
+            "{}: Type mismatch in {}: expected '{}', found an expression without a value",
+            span, context, expected
+        )),
+    }
+}
+
+// 型注釈に書かれた名前がすべて既知の型か
+fn check_type_exists(ty: &Type, span: Span) -> Result<(), String> {
+    match ty {
+        Type::Named(name) if matches!(name.as_str(), "i32" | "i64" | "string") => Ok(()),
+        Type::Named(name) | Type::Generic { name, .. } => {
+            Err(format!("{}: Unknown type '{}'", span, name))
+        }
+        Type::Array { element, .. } => check_type_exists(element, span),
+        Type::Pointer { pointee, .. } => check_type_exists(pointee, span),
+        Type::Function {
+            params,
+            return_type,
+        } => {
+            for param in params {
+                check_type_exists(param, span)?;
+            }
+            match return_type {
+                Some(return_type) => check_type_exists(return_type, span),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::resolve::resolve_names;
+    use crate::test_util::parse;
+
+    fn check(source: &str) -> Result<TypeTable, String> {
+        let program = parse(source);
+        let resolutions = resolve_names(&program).expect("Failed to resolve names");
+        check_types(&program, &resolutions)
+    }
+
+    #[test]
+    fn test_well_typed_programs() {
+        let tests = vec![
+            "let x:i64 = 1; let y:i64 = x + 2; print(y);",
+            "let x:i64 = 1 + 2 * 3;",
+            "let x:i32 = 1; let y = x % 2; print(y < 1);",
+            "let s:string = \"hello\"; print(s);",
+            "let mut x:i64 = 0; x += 1; x = x * 2;",
+            "let x:i64 = 1; while (x < 10) { print(x); }",
+            "function f(x:i64) -> i64 { x }; let y:i64 = f(1) + 1;",
+            "function f(x:i64, s:string) { }; let n:i32 = 1; f(n, \"a\");",
+            "let x:i64 = if (1 < 2) { 1 } else { 2 };",
+            "let x:i64 = 5; let y = if (x > 2) { 1 } else { x };",
+            "let x:i64 = { let y:i64 = 2; y + 1 };",
+            "let x:i32 = wrapping_add(2147483647, 1); let y:i32 = wrapping_mul(x, 2);",
+            "function wrapping_add(s:string) { }; wrapping_add(\"a\");",
+            "function f(x:i64) -> i64 { let y:i64 = if (x < 1) { return 1; } else { 2 }; y }",
+            "function f(x:i64) -> i64 { let y:i64 = if (x < 1) { x } else { return 1; }; y }",
+            "let mut i:i64 = 0; while (i < 3) { i = if (i > 1) { break; } else { i + 1 }; }",
+        ];
+        for test in tests {
+            assert!(check(test).is_ok(), "Should accept: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_ill_typed_programs() {
+        let tests = vec![
+            "let x:i32 = 2147483648;",
+            "let x:i64 = 1; let y:i32 = 2; print(x + y);",
+            "let x:i32 = 1; let y:i64 = x;",
+            "let s:string = \"a\"; print(s + s);",
+            "let s:string = \"a\"; let t:string = \"b\"; print(s < t);",
+            "let x:string = 1;",
+            "let x:i32 = \"hello\";",
+            "let mut x:i32 = 1; x = \"a\";",
+            "let mut s:string = \"a\"; s += \"b\";",
+            "function f() { }; print(f());",
+            "function f(x:i64, y:i64) { }; f(1);",
+            "function f(x:i64, y:i64) { }; f(1, 2, 3);",
+            "function f(x:i32) { }; let n:i64 = 1; f(n);",
+            "function f(s:string) { }; f(1);",
+            "function f(x:i64) { }; function g() { }; f(g());",
+            "function f() { }; let x = f();",
+            "let s:string = \"a\"; if (s) { print(1); }",
+            "let x:i64 = 1; let y = if (x < 2) { x } else { \"a\" };",
+            "function f(x:i64) -> i64 { let y:i32 = if (x < 1) { return 1; } else { x }; 1 }",
+            "let x:foo = 1;",
+            "function f(x:Vec<i64>) { };",
+            "let x:i64 = 1; let y:i32 = 2; print(wrapping_sub(x, y));",
+            "print(wrapping_add(1));",
+            "print(wrapping_mul(\"a\", \"b\"));",
+        ];
+        for test in tests {
+            assert!(check(test).is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_expression_types_are_recorded() {
+        let program = parse("let x:i64 = 1; print(x < 2); print(2 + x);");
+        let resolutions = resolve_names(&program).unwrap();
+        let types = check_types(&program, &resolutions).unwrap();
+        let printed = |i: usize| match &program.items[i] {
+            Item::Stmt(Stmt {
+                kind: StmtKind::Print(expr),
+                ..
+            }) => expr.clone(),
+            item => panic!("Unexpected item: {:?}", item),
+        };
+        let (comparison, sum) = (printed(1), printed(2));
+        assert_eq!(types.expr_type(comparison.id), Some(&Type::named("i32")));
+        assert_eq!(types.expr_type(sum.id), Some(&Type::named("i64")));
+        match &sum.kind {
+            ExprKind::BinaryOp { left, .. } => {
+                assert_eq!(types.expr_type(left.id), Some(&Type::named("i64")))
+            }
+            kind => panic!("Unexpected expression: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_call_errors_point_to_definition() {
+        assert_eq!(
+            check("function add(x:i64, y:i64) -> i64 { x + y }\nprint(add(1));").unwrap_err(),
+            "2:7: Function 'add' takes 2 arguments but 1 was supplied\n1:1: note: 'add' defined here"
+        );
+        assert_eq!(
+            check("function f(s:string) { }\nf(1);").unwrap_err(),
+            "2:3: Type mismatch in argument 1 of 'f': expected 'string', found 'i32'\n1:1: note: 'f' defined here"
+        );
+        assert_eq!(
+            check("function f(x:i64) { }\nf(1, 2, 3);").unwrap_err(),
+            "2:1: Function 'f' takes 1 argument but 3 were supplied\n1:1: note: 'f' defined here"
+        );
+        // i64 から i32 へは狭められない
+        assert_eq!(
+            check("function f(x:i32) { }\nlet n:i64 = 1;\nf(n);").unwrap_err(),
+            "3:3: Type mismatch in argument 1 of 'f': expected 'i32', found 'i64'\n1:1: note: 'f' defined here"
+        );
+    }
+
+    #[test]
+    fn test_builtin_calls() {
+        let program = parse("let x:i64 = 1; print(wrapping_sub(x, 2));");
+        let resolutions = resolve_names(&program).unwrap();
+        let types = check_types(&program, &resolutions).unwrap();
+        let Item::Stmt(Stmt {
+            kind: StmtKind::Print(call),
+            ..
+        }) = &program.items[1]
+        else {
+            panic!("Unexpected item: {:?}", program.items[1]);
+        };
+        assert_eq!(types.expr_type(call.id), Some(&Type::named("i64")));
+        assert_eq!(
+            check("print(wrapping_add(1));").unwrap_err(),
+            "1:7: Builtin function 'wrapping_add' takes 2 arguments but 1 was supplied"
+        );
+        assert_eq!(
+            check("print(wrapping_mul(1, 2, 3));").unwrap_err(),
+            "1:7: Builtin function 'wrapping_mul' takes 2 arguments but 3 were supplied"
+        );
+        assert_eq!(
+            check("let x:i64 = 1;\nlet y:i32 = 2;\nprint(wrapping_sub(x, y));").unwrap_err(),
+            "3:7: Operator '-' cannot be applied to 'i64' and 'i32'"
+        );
+        assert_eq!(
+            check("print(wrapping_add(\"a\", 1));").unwrap_err(),
+            "1:7: Operator '+' cannot be applied to 'string' and 'i32'"
+        );
+    }
+
+    #[test]
+    fn test_i32_argument_is_widened() {
+        let program = parse("function f(x:i64) -> i64 { x }; let n:i32 = 1; print(f(n));");
+        let resolutions = resolve_names(&program).unwrap();
+        let types = check_types(&program, &resolutions).unwrap();
+        let Item::Stmt(Stmt {
+            kind: StmtKind::Print(call),
+            ..
+        }) = &program.items[2]
+        else {
+            panic!("Unexpected item: {:?}", program.items[2]);
+        };
+        let ExprKind::FunctionCall { args, .. } = &call.kind else {
+            panic!("Unexpected expression: {:?}", call.kind);
+        };
+        // 引数は i32 のまま記録し、i64 への拡張は後の段で行う
+        assert_eq!(types.expr_type(args[0].id), Some(&Type::named("i32")));
+        assert_eq!(types.expr_type(call.id), Some(&Type::named("i64")));
+    }
+
+    #[test]
+    fn test_error_reports_position() {
+        assert_eq!(
+            check("let x:i64 = 1;\nlet y:i32 = 2;\nprint(x + y);").unwrap_err(),
+            "3:7: Operator '+' cannot be applied to 'i64' and 'i32'"
+        );
+    }
+}
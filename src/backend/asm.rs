@@ -0,0 +1,197 @@
+use std::fmt;
+
+// CodeGenerator が出力するアセンブリの1行
+// 命令は命令名とオペランドの並びで持ち、のぞき穴最適化で書き換えてから文字列にする
+#[derive(Clone, Debug, PartialEq)]
+pub enum AsmLine {
+    Label(String),
+    Inst(AsmInst),
+    // section や db のように、命令ではない行はそのまま出力する
+    Directive(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmInst {
+    pub opcode: &'static str,
+    pub operands: Vec<Operand>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    Reg(&'static str),
+    Imm(i64),
+    // サイズの指定を含むメモリ参照。"qword [rbp-8]" など
+    Mem(String),
+    // ラベルや '0' のような記号
+    Symbol(String),
+}
+
+// 汎用レジスタの 64/32/8 ビットの名前
+const REGISTERS: [[&str; 3]; 16] = [
+    ["rax", "eax", "al"],
+    ["rbx", "ebx", "bl"],
+    ["rcx", "ecx", "cl"],
+    ["rdx", "edx", "dl"],
+    ["rsi", "esi", "sil"],
+    ["rdi", "edi", "dil"],
+    ["rbp", "ebp", "bpl"],
+    ["rsp", "esp", "spl"],
+    ["r8", "r8d", "r8b"],
+    ["r9", "r9d", "r9b"],
+    ["r10", "r10d", "r10b"],
+    ["r11", "r11d", "r11b"],
+    ["r12", "r12d", "r12b"],
+    ["r13", "r13d", "r13b"],
+    ["r14", "r14d", "r14b"],
+    ["r15", "r15d", "r15b"],
+];
+
+// 同じレジスタの別の幅の名前か
+pub fn same_register(a: &str, b: &str) -> bool {
+    register_index(a).is_some_and(|(family, _)| register_index(b).map(|(f, _)| f) == Some(family))
+}
+
+// レジスタの幅 (ビット)
+pub fn register_width(name: &str) -> Option<u32> {
+    register_index(name).map(|(_, width)| [64, 32, 8][width])
+}
+
+// 同じレジスタの 32 ビットの名前
+pub fn register32(name: &str) -> Option<&'static str> {
+    register_index(name).map(|(family, _)| REGISTERS[family][1])
+}
+
+fn register_index(name: &str) -> Option<(usize, usize)> {
+    REGISTERS.iter().enumerate().find_map(|(family, names)| {
+        names
+            .iter()
+            .position(|n| *n == name)
+            .map(|width| (family, width))
+    })
+}
+
+impl AsmLine {
+    pub fn inst(opcode: &'static str, operands: Vec<Operand>) -> AsmLine {
+        AsmLine::Inst(AsmInst { opcode, operands })
+    }
+
+    pub fn as_inst(&self) -> Option<&AsmInst> {
+        match self {
+            AsmLine::Inst(inst) => Some(inst),
+            _ => None,
+        }
+    }
+}
+
+impl Operand {
+    // レジスタの名前でなければ記号とみなす
+    pub fn reg(name: &str) -> Operand {
+        match register_index(name) {
+            Some((family, width)) => Operand::Reg(REGISTERS[family][width]),
+            None => Operand::Symbol(name.to_string()),
+        }
+    }
+
+    pub fn mem(address: impl Into<String>) -> Operand {
+        Operand::Mem(address.into())
+    }
+
+    pub fn symbol(name: impl Into<String>) -> Operand {
+        Operand::Symbol(name.into())
+    }
+
+    pub fn is_mem(&self) -> bool {
+        matches!(self, Operand::Mem(_))
+    }
+
+    // このオペランドを読み書きするときに reg のいずれかの幅を使うか
+    pub fn mentions(&self, reg: &str) -> bool {
+        match self {
+            Operand::Reg(name) => same_register(name, reg),
+            Operand::Mem(address) => address
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|word| same_register(word, reg)),
+            Operand::Imm(_) | Operand::Symbol(_) => false,
+        }
+    }
+}
+
+// アセンブリのテキストを読む。のぞき穴最適化のテストで使う
+// "name:" はラベル、section などは Directive になる
+pub fn parse_asm(text: &str) -> Vec<AsmLine> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            if let Some(label) = line.strip_suffix(':') {
+                return AsmLine::Label(label.to_string());
+            }
+            let (opcode, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let Some(opcode) = OPCODES.iter().find(|op| **op == opcode) else {
+                return AsmLine::Directive(line.to_string());
+            };
+            let operands = rest
+                .split(',')
+                .map(str::trim)
+                .filter(|operand| !operand.is_empty())
+                .map(|operand| {
+                    if operand.contains('[') {
+                        Operand::mem(operand)
+                    } else if let Ok(value) = operand.parse() {
+                        Operand::Imm(value)
+                    } else {
+                        Operand::reg(operand)
+                    }
+                })
+                .collect();
+            AsmLine::inst(opcode, operands)
+        })
+        .collect()
+}
+
+// CodeGenerator が使う命令
+const OPCODES: [&str; 29] = [
+    "mov", "movzx", "movsxd", "lea", "push", "pop", "add", "sub", "imul", "idiv", "div", "neg",
+    "cmp", "test", "xor", "cqo", "cdq", "setl", "setg", "jmp", "je", "jne", "jz", "jo", "call",
+    "ret", "syscall", "ud2", "nop",
+];
+
+pub fn render(lines: &[AsmLine]) -> String {
+    let mut output = String::new();
+    for line in lines {
+        output.push_str(&line.to_string());
+        output.push('\n');
+    }
+    output
+}
+
+impl fmt::Display for AsmLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmLine::Label(name) => write!(f, "{}:", name),
+            AsmLine::Inst(inst) => write!(f, "    {}", inst),
+            AsmLine::Directive(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl fmt::Display for AsmInst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.opcode)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(name) => write!(f, "{}", name),
+            Operand::Imm(value) => write!(f, "{}", value),
+            Operand::Mem(address) => write!(f, "{}", address),
+            Operand::Symbol(name) => write!(f, "{}", name),
+        }
+    }
+}
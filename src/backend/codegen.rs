@@ -1,617 +1,771 @@
-use crate::parser::ast::{Expr, Literal, Op};
-use std::collections::HashMap;
+use crate::backend::asm::{render, AsmLine, Operand};
+use crate::backend::peephole;
+use crate::backend::regalloc::{allocate_registers, Allocation, Location};
+use crate::mir::{BinOp, BlockId, Function, InstKind, Program, Terminator, Ty, Value};
+use crate::parser::span::Span;
 use std::fs::File;
 use std::io::Write;
 
-pub struct CodeGenerator {
-    output: String,
-    data_section: String,
-    variables: HashMap<String, (String, String)>,
-    scopes: Vec<HashMap<String, (String, String)>>,
+// 生成するコードの設定
+#[derive(Clone, Debug)]
+pub struct CodegenOptions {
+    // 加算、減算、乗算の桁あふれを実行時に検査し、検出したら終了する
+    // 0 除算と MIN / -1 はこの設定によらず常に検査する
+    pub overflow_checks: bool,
+    // 実行時エラーの位置に表示するソースファイル名
+    pub file_name: String,
+    // 出力する命令列にのぞき穴最適化をかける
+    pub peephole: bool,
 }
 
-impl CodeGenerator {
-    pub fn new() -> Self {
-        CodeGenerator {
-            output: String::from(""),
-            data_section: String::from("section .data\n"),
-            variables: HashMap::new(),
-            scopes: vec![HashMap::new()],
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        CodegenOptions {
+            overflow_checks: true,
+            file_name: String::from("<input>"),
+            peephole: false,
         }
     }
+}
 
-    // スコープをプッシュするメソッド
-    fn push_scope(&mut self) {
-        self.scopes.push(HashMap::new());
-    }
+// 実行時エラーで終了するときの終了コード
+const TRAP_EXIT_CODE: i32 = 101;
 
-    // スコープをポップするメソッド
-    fn pop_scope(&mut self) {
-        self.scopes.pop();
+// MIR の命令ごとに x86-64 の命令を選んで NASM のアセンブリを出力する
+// 値はレジスタ割り当てで決めたレジスタかスタックに置き、rax/rcx/rdx を作業用に使う
+// 命令は AsmLine の列に積み、最後に文字列にする
+pub struct CodeGenerator {
+    output: Vec<AsmLine>,
+    options: CodegenOptions,
+    // 実行時エラーの飛び先のラベルとメッセージ。関数の後ろにまとめて出力する
+    traps: Vec<(String, String)>,
+    // 文字列リテラルのラベルと内容
+    strings: Vec<(String, String)>,
+    // 生成中の関数の値の置き場所
+    allocation: Allocation,
+}
+
+impl CodeGenerator {
+    pub fn new() -> Self {
+        Self::with_options(CodegenOptions::default())
     }
 
-    // 現在のスコープを取得するメソッド
-    // fn current_scope(&mut self) -> Option<&mut HashMap<String, (String, String)>> {
-    //     self.scopes.last_mut()
-    // }
+    pub fn with_options(options: CodegenOptions) -> Self {
+        CodeGenerator {
+            output: Vec::new(),
+            options,
+            traps: Vec::new(),
+            strings: Vec::new(),
+            allocation: Allocation::default(),
+        }
+    }
 
-    pub fn generate(&mut self, expr: &Expr) -> Result<String, String> {
-        // データセクション呼び出し前に変数を初期化しておく必要がある
-        self.initialize_variables(expr)?;
-        println!("Before generation: {}", self.data_section);
+    pub fn generate(&mut self, program: &Program) -> Result<String, String> {
         self.output.clear();
-        self.output.push_str("extern printf\n");
-        self.output.push_str("section .bss\nbuffer_0 resb 12\n");
-        self.output.push_str(&self.data_section);
-        self.output
-            .push_str("section .text\nglobal _start, int_to_ascii\n");
+        self.directive("extern printf");
+        self.directive("section .bss\nbuffer_0 resb 12");
+        // トップレベルの変数。初期値は .data に埋め込む
+        self.directive("section .data");
+        for global in &program.globals {
+            let directive = if global.ty == Ty::I64 { "dq" } else { "dd" };
+            self.directive(format!("{}_res {} {}", global.name, directive, global.init));
+        }
+        self.directive("section .text\nglobal _start, int_to_ascii");
 
         self.int_to_ascii();
 
-        self.emit_function_definitions(expr)?;
-
-        self.output.push_str("_start:\n");
-        self.preprocessor(expr)?;
-        self.output.push_str("mov rax, 60\nxor rdi, rdi\nsyscall\n");
-        println!("After generation: {}", self.output);
-        Ok(self.output.clone())
+        for function in &program.functions {
+            self.emit_function(program, function, &function.name);
+        }
+        self.emit_function(program, &program.main, "_start");
+        self.emit_traps();
+        if self.options.peephole {
+            let rewrites = peephole::optimize(&mut self.output);
+            println!("Peephole optimizer applied {} rewrite(s)", rewrites);
+        }
+        let output = render(&self.output);
+        println!("After generation: {}", output);
+        Ok(output)
     }
 
     fn int_to_ascii(&mut self) {
-        self.output.push_str("int_to_ascii:\n");
-        self.output.push_str("    push rbx\n");
-        self.output.push_str("    mov rbx, rdi\n");
-        self.output.push_str("    lea rsi, [rel buffer_0 + 11]\n");
-        self.output.push_str("    mov byte [rsi], 0\n");
-        self.output.push_str("    sub rsi, 1\n");
-        self.output.push_str("    mov rcx, 10\n");
-        self.output.push_str("convert_loop:\n");
-        self.output.push_str("    xor rdx, rdx\n");
-        self.output.push_str("    div rcx\n");
-        self.output.push_str("    add dl, '0'\n");
-        self.output.push_str("    mov [rsi], dl\n");
-        self.output.push_str("    test rax, rax\n");
-        self.output.push_str("    jz convert_end\n");
-        self.output.push_str("    sub rsi, 1\n");
-        self.output.push_str("    mov rbx, rax\n");
-        self.output.push_str("    jmp convert_loop\n");
-        self.output.push_str("convert_end:\n");
-        self.output.push_str("    pop rbx\n");
-        self.output.push_str("    ret\n");
-    }
-
-    pub fn generate_to_file(&mut self, expr: &Expr, file_path: &str) -> Result<(), String> {
-        self.generate(expr)?;
+        self.label("int_to_ascii");
+        self.emit("push", vec![reg("rbx")]);
+        self.emit("mov", vec![reg("rbx"), reg("rdi")]);
+        self.emit("lea", vec![reg("rsi"), Operand::mem("[rel buffer_0 + 11]")]);
+        self.emit("mov", vec![Operand::mem("byte [rsi]"), Operand::Imm(0)]);
+        self.emit("sub", vec![reg("rsi"), Operand::Imm(1)]);
+        self.emit("mov", vec![reg("rcx"), Operand::Imm(10)]);
+        self.label("convert_loop");
+        self.emit("xor", vec![reg("rdx"), reg("rdx")]);
+        self.emit("div", vec![reg("rcx")]);
+        self.emit("add", vec![reg("dl"), Operand::symbol("'0'")]);
+        self.emit("mov", vec![Operand::mem("[rsi]"), reg("dl")]);
+        self.emit("test", vec![reg("rax"), reg("rax")]);
+        self.emit("jz", vec![Operand::symbol("convert_end")]);
+        self.emit("sub", vec![reg("rsi"), Operand::Imm(1)]);
+        self.emit("mov", vec![reg("rbx"), reg("rax")]);
+        self.emit("jmp", vec![Operand::symbol("convert_loop")]);
+        self.label("convert_end");
+        self.emit("pop", vec![reg("rbx")]);
+        self.emit("ret", vec![]);
+    }
+
+    pub fn generate_to_file(&mut self, program: &Program, file_path: &str) -> Result<(), String> {
+        let output = self.generate(program)?;
         let mut file =
             File::create(file_path).map_err(|e| format!("Failed to create file: {}", e))?;
-        file.write_all(self.output.as_bytes())
+        file.write_all(output.as_bytes())
             .map_err(|e| format!("Failed to write to file: {}", e))?;
         Ok(())
     }
 
-    // generate関数で一回しか呼ばれない
-    fn initialize_variables(&mut self, expr: &Expr) -> Result<(), String> {
-        match expr {
-            Expr::Assignment {
-                name,
-                type_decl,
-                value,
-            } => {
-                println!("Initializing variable '{}'", name);
-                let var_address = self.allocate_variable(name, type_decl)?;
-                self.emit_assignment(&var_address, type_decl, value)?;
-            }
-            Expr::Variable(name) => {
-                println!("Referencing variable '{}' during initialization", name);
-                self.emit_variable(name)?;
+    fn emit(&mut self, opcode: &'static str, operands: Vec<Operand>) {
+        self.output.push(AsmLine::inst(opcode, operands));
+    }
+
+    fn label(&mut self, name: impl Into<String>) {
+        self.output.push(AsmLine::Label(name.into()));
+    }
+
+    fn directive(&mut self, text: impl Into<String>) {
+        self.output.push(AsmLine::Directive(text.into()));
+    }
+
+    // 関数は引数を右から順にスタックに積んで呼び、戻り値は rax/eax で返す
+    // 引数は rbp+16 から 8 バイトずつ積まれている。使う callee-saved レジスタは入口で保存する
+    // label が _start ならプログラムの入口で、return でプロセスを終える
+    fn emit_function(&mut self, program: &Program, function: &Function, label: &str) {
+        println!("Generating function '{}'", function.name);
+        let is_main = label == "_start";
+        self.allocation = allocate_registers(function, !is_main);
+
+        self.label(label);
+        if !is_main {
+            self.emit("push", vec![reg("rbp")]);
+        }
+        self.emit("mov", vec![reg("rbp"), reg("rsp")]);
+        for saved in self.allocation.saved.clone() {
+            self.emit("push", vec![Operand::Reg(saved.name(Ty::I64))]);
+        }
+        if self.allocation.frame_size > 0 {
+            self.emit(
+                "sub",
+                vec![reg("rsp"), Operand::Imm(self.allocation.frame_size)],
+            );
+        }
+        // レジスタに割り当てた引数を読み込む
+        for (i, param) in function.params.iter().enumerate() {
+            if let Location::Register(register) = self.allocation.location(*param) {
+                self.emit(
+                    "mov",
+                    vec![
+                        Operand::Reg(register.name(Ty::I64)),
+                        Operand::mem(format!("[rbp+{}]", 16 + 8 * i)),
+                    ],
+                );
             }
-            Expr::Block(expressions) => {
-                println!("Initializing block");
-                for expression in expressions {
-                    self.initialize_variables(expression)?;
+        }
+
+        let targets: Vec<BlockId> = function
+            .blocks
+            .iter()
+            .flat_map(|block| block.terminator.successors())
+            .collect();
+        let last = function.blocks.len() - 1;
+        for (i, block) in function.blocks.iter().enumerate() {
+            if targets.contains(&BlockId(i)) {
+                self.label(format!(".bb{}", i));
+            }
+            for inst in &block.insts {
+                self.emit_inst(program, function, inst.result, &inst.kind);
+            }
+            match &block.terminator {
+                Terminator::Jump(target) => {
+                    self.emit_phi_copies(function, BlockId(i), *target);
+                    self.emit_jump(i, *target);
+                }
+                Terminator::Branch { cond, then, else_ } => {
+                    self.emit_branch(function, i, *cond, *then, *else_);
+                }
+                Terminator::Return(value) => {
+                    if let Some(value) = value {
+                        let reg = register(function.value_type(*value), 'a');
+                        self.load(function, reg, *value);
+                    }
+                    if is_main {
+                        self.emit("mov", vec![reg("rax"), Operand::Imm(60)]);
+                        self.emit("xor", vec![reg("rdi"), reg("rdi")]);
+                        self.emit("syscall", vec![]);
+                    } else if i != last {
+                        self.emit("jmp", vec![Operand::symbol(".epilogue")]);
+                    }
                 }
+                Terminator::TailCall { callee, args } => self.emit_tail_call(callee, args),
+                Terminator::Unreachable => self.emit("ud2", vec![]),
             }
-            _ => {}
         }
-        Ok(())
+
+        // return はここへ飛ぶ
+        if !is_main {
+            self.label(".epilogue");
+            self.emit_frame_teardown();
+            self.emit("ret", vec![]);
+        }
     }
 
-    fn emit_function_definitions(&mut self, expr: &Expr) -> Result<(), String> {
-        if let Expr::Block(expressions) = expr {
-            for expression in expressions {
-                if let Expr::FunctionDef { name, params, body } = expression {
-                    self.emit_function_def(name, params, body)?;
-                }
+    // 保存したレジスタを戻し、rsp と rbp を関数に入る前の値にする。リターンアドレスが rsp の先に残る
+    fn emit_frame_teardown(&mut self) {
+        let saved = self.allocation.saved.clone();
+        if saved.is_empty() {
+            self.emit("mov", vec![reg("rsp"), reg("rbp")]);
+        } else {
+            self.emit(
+                "lea",
+                vec![
+                    reg("rsp"),
+                    Operand::mem(format!("[rbp-{}]", 8 * saved.len())),
+                ],
+            );
+            for saved in saved.iter().rev() {
+                self.emit("pop", vec![Operand::Reg(saved.name(Ty::I64))]);
             }
         }
-        Ok(())
+        self.emit("pop", vec![reg("rbp")]);
     }
 
-    pub fn preprocessor(&mut self, expr: &Expr) -> Result<String, String> {
-        println!("Processing expression: {:?}", expr);
-        match expr {
-            Expr::FunctionCall { name, args } => {
-                self.emit_function_call(name, args)?;
-            }
-            Expr::IfExpr {
-                condition,
-                consequence,
-                alternative,
+    // 末尾呼び出し。引数を呼び出し元が積んだ引数の場所に書き直し、フレームを片付けて callee へ飛ぶ
+    // callee はこの関数の呼び出し元へ直接戻り、積んだ引数は呼び出し元が片付ける
+    // 書き直す場所を後の引数が読むことがあるので、いったんすべてスタックに積んでから書き込む
+    fn emit_tail_call(&mut self, callee: &str, args: &[Value]) {
+        println!("Emitting tail call to '{}'", callee);
+        for arg in args {
+            let operand = self.operand64(*arg);
+            self.emit("push", vec![operand]);
+        }
+        for i in (0..args.len()).rev() {
+            self.emit(
+                "pop",
+                vec![Operand::mem(format!("qword [rbp+{}]", 16 + 8 * i))],
+            );
+        }
+        self.emit_frame_teardown();
+        self.emit("jmp", vec![Operand::symbol(callee)]);
+    }
+
+    fn emit_inst(
+        &mut self,
+        program: &Program,
+        function: &Function,
+        result: Option<Value>,
+        kind: &InstKind,
+    ) {
+        // 結果は rax/eax に求めてから置き場所に書き込む
+        let result_reg = result.map(|result| register(function.value_type(result), 'a'));
+        match kind {
+            InstKind::Const(value) => {
+                let result = result.expect("const has a result");
+                // レジスタには直接書き込める
+                if let Location::Register(_) = self.allocation.location(result) {
+                    let operand = self.operand(function, result);
+                    self.emit("mov", vec![operand, Operand::Imm(*value)]);
+                    return;
+                }
+                let reg = result_reg.expect("const has a result");
+                self.emit("mov", vec![Operand::Reg(reg), Operand::Imm(*value)]);
+            }
+            InstKind::Copy(value) => {
+                self.load(function, result_reg.expect("copy has a result"), *value);
+            }
+            InstKind::Binary {
+                op,
+                lhs,
+                rhs,
+                checked,
+                span,
             } => {
-                self.emit_if_expr(condition, consequence, alternative)?;
+                let checked = *checked && self.options.overflow_checks;
+                self.emit_binary_op(function, *op, *lhs, *rhs, checked, *span);
+            }
+            InstKind::Widen(value) => {
+                let operand = match self.operand(function, *value) {
+                    Operand::Mem(address) => Operand::mem(format!("dword {}", address)),
+                    operand => operand,
+                };
+                self.emit("movsxd", vec![reg("rax"), operand]);
+            }
+            InstKind::Phi(_) => {
+                // 値は合流元のブロックの終わりで書き込んである
+                return;
+            }
+            InstKind::Load(global) => {
+                let reg = result_reg.expect("load has a result");
+                self.emit(
+                    "mov",
+                    vec![
+                        Operand::Reg(reg),
+                        Operand::mem(format!("[{}_res]", program.global(*global).name)),
+                    ],
+                );
             }
-            Expr::WhileLoop { condition, body } => {
-                println!(
-                    "Expr While Debug: Entering while loop with condition: {:?}",
-                    condition
+            InstKind::Store(global, value) => {
+                let reg = register(function.value_type(*value), 'a');
+                self.load(function, reg, *value);
+                self.emit(
+                    "mov",
+                    vec![
+                        Operand::mem(format!("[{}_res]", program.global(*global).name)),
+                        Operand::Reg(reg),
+                    ],
                 );
-                self.emit_while_loop(condition, body)?;
-                println!("Expr While Debug: Exiting while loop");
             }
-            Expr::BinaryOp { left, op, right } => {
-                println!("Processing BinaryOp: {:?} {:?} {:?}", left, op, right);
-                self.emit_binary_op(left, op, right)?;
+            InstKind::Call { callee, args } => {
+                println!("Emitting function call to '{}'", callee);
+                for arg in args.iter().rev() {
+                    let operand = self.operand64(*arg);
+                    self.emit("push", vec![operand]);
+                }
+                self.emit("call", vec![Operand::symbol(callee.clone())]);
+                // 引数は1つにつき8バイト積んでいる
+                if !args.is_empty() {
+                    self.emit("add", vec![reg("rsp"), Operand::Imm(args.len() as i64 * 8)]);
+                }
             }
-            Expr::Literal(lit) => {
-                self.emit_literal(lit)?;
+            InstKind::Print(value) => {
+                self.load(function, register(function.value_type(*value), 'a'), *value);
+                self.emit("lea", vec![reg("rsi"), Operand::mem("[rel buffer_0]")]);
+                self.emit("call", vec![Operand::symbol("int_to_ascii")]);
+                self.emit("lea", vec![reg("rsi"), Operand::mem("[rel buffer_0]")]);
+                self.emit_write(1, 12);
             }
-            Expr::Block(expressions) => {
-                println!(
-                    "Processing block with {} expressions(preprocessor func)",
-                    expressions.len()
+            InstKind::PrintStr(s) => {
+                let label = self.new_label("str");
+                self.emit(
+                    "lea",
+                    vec![reg("rsi"), Operand::mem(format!("[rel {}]", label))],
                 );
-                for expression in expressions {
-                    self.preprocessor(expression)?;
+                self.emit_write(1, s.len() as i64 + 1);
+                self.strings.push((label, s.clone()));
+            }
+        }
+        if let (Some(result), Some(reg)) = (result, result_reg) {
+            let operand = self.operand(function, result);
+            self.emit("mov", vec![operand, Operand::Reg(reg)]);
+        }
+    }
+
+    // rsi から length バイトを fd に書き込む
+    fn emit_write(&mut self, fd: i64, length: i64) {
+        self.emit("mov", vec![reg("edi"), Operand::Imm(fd)]);
+        self.emit("mov", vec![reg("eax"), Operand::Imm(1)]);
+        self.emit("mov", vec![reg("edx"), Operand::Imm(length)]);
+        self.emit("syscall", vec![]);
+    }
+
+    // 結果を rax/eax に求める
+    // checked なら加算、減算、乗算の桁あふれも実行時エラーにする。除算は常に検査する
+    fn emit_binary_op(
+        &mut self,
+        function: &Function,
+        op: BinOp,
+        lhs: Value,
+        rhs: Value,
+        checked: bool,
+        span: Span,
+    ) {
+        let ty = function.value_type(lhs);
+        let (reg_left, reg_right, reg_remainder) =
+            (register(ty, 'a'), register(ty, 'c'), register(ty, 'd'));
+        self.load(function, reg_left, lhs);
+        self.load(function, reg_right, rhs);
+        let (left, right) = (Operand::Reg(reg_left), Operand::Reg(reg_right));
+        match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                let instruction = match op {
+                    BinOp::Add => "add",
+                    BinOp::Sub => "sub",
+                    _ => "imul",
+                };
+                self.emit(instruction, vec![left, right]);
+                if checked {
+                    self.emit_overflow_check(span);
                 }
             }
-            Expr::Return(expr) => {
-                self.emit_return(expr)?;
+            BinOp::Div | BinOp::Rem => {
+                self.emit_division_checks(op, reg_left, reg_right, reg_remainder, span);
+                // 商は eax/rax、余りは edx/rdx に入る
+                self.emit_sign_extend(reg_left);
+                self.emit("idiv", vec![right]);
+                if op == BinOp::Rem {
+                    self.emit("mov", vec![left, Operand::Reg(reg_remainder)]);
+                }
             }
-            Expr::Print(expr) => {
-                println!("Processing print statement.");
-                self.emit_print(expr)?;
+            BinOp::Lt | BinOp::Gt => {
+                self.emit("cmp", vec![left, right]);
+                let set = if op == BinOp::Lt { "setl" } else { "setg" };
+                self.emit(set, vec![reg("al")]);
+                self.emit("movzx", vec![reg("eax"), reg("al")]);
             }
-            _ => println!("Processing other expression types"),
         }
-        Ok(self.output.clone())
     }
 
-    fn emit_function_def(
+    // cond が 0 でなければ then、0 なら else へ進む
+    fn emit_branch(
         &mut self,
-        name: &str,
-        params: &[(String, String)],
-        body: &Expr,
-    ) -> Result<(), String> {
-        self.output.push_str(&format!("{}:\n", name));
-        self.output.push_str("    push rbp\n");
-        self.output.push_str("    mov rbp, rsp\n");
-
-        // 新しいスコープをプッシュし、各パラメータをスタックフレームに割り当てる
-        self.push_scope();
-        let mut offset = 16;
-        for (param_name, param_type) in params.iter() {
-            //let data_type = if param_type == "i64" { 8 } else { 4 };
-            let data_type = 8;
-            let param_label = format!("{}_res", param_name);
-            self.variables
-                .insert(param_label, (format!("rbp+{}", offset), param_type.clone()));
-            offset += data_type;
+        function: &Function,
+        block: usize,
+        cond: Value,
+        then: BlockId,
+        else_: BlockId,
+    ) {
+        let from = BlockId(block);
+        let reg = register(function.value_type(cond), 'a');
+        self.load(function, reg, cond);
+        self.emit("test", vec![Operand::Reg(reg), Operand::Reg(reg)]);
+        if !has_phis(function, else_) {
+            self.emit("je", vec![block_label(else_)]);
+            self.emit_phi_copies(function, from, then);
+            self.emit_jump(block, then);
+        } else if !has_phis(function, then) {
+            self.emit("jne", vec![block_label(then)]);
+            self.emit_phi_copies(function, from, else_);
+            self.emit_jump(block, else_);
+        } else {
+            // どちらの枝も phi の値を書き込んでから飛ぶ
+            let edge = format!(".bb{}_to_bb{}", block, else_.0);
+            self.emit("je", vec![Operand::symbol(edge.clone())]);
+            self.emit_phi_copies(function, from, then);
+            self.emit("jmp", vec![block_label(then)]);
+            self.label(edge);
+            self.emit_phi_copies(function, from, else_);
+            self.emit_jump(block, else_);
         }
-
-        self.preprocessor(body)?;
-
-        self.output.push_str("    pop rbp\n");
-        self.output.push_str("    ret\n");
-
-        self.pop_scope();
-        Ok(())
     }
 
-    fn emit_function_call(&mut self, name: &str, args: &[Expr]) -> Result<(), String> {
-        println!("Emitting function call to '{}'", name); // 関数呼び出しのデバッグ情報
-        for (i, arg) in args.iter().enumerate().rev() {
-            println!("Evaluating argument {}: {:?}", args.len() - i, arg); // 引数評価の前に情報を出力
-            self.preprocessor(arg)?;
-            self.output.push_str("    push rax\n");
-            println!("Pushed argument {} to stack", args.len() - i); // スタックにプッシュ後の情報を出力
+    // 直後のブロックへは飛ばなくてよい
+    fn emit_jump(&mut self, block: usize, target: BlockId) {
+        if target.0 != block + 1 {
+            self.emit("jmp", vec![block_label(target)]);
         }
-        self.output.push_str(&format!("    call {}\n", name));
-        self.output
-            .push_str(&format!("    add esp, {}\n", args.len() * 4));
-
-        println!("Function '{}' called with {} arguments", name, args.len()); // 関数呼び出し後の情報を出力
-        Ok(())
     }
 
-    fn emit_if_expr(
-        &mut self,
-        condition: &Expr,
-        consequence: &Expr,
-        alternative: &Option<Box<Expr>>,
-    ) -> Result<(), String> {
-        let use_64bit = self.use_64bit_regs(condition);
-        let cmp_reg = if use_64bit { "rax" } else { "eax" };
-
-        let label_else = self.new_label("else");
-        let label_end = self.new_label("endif");
-
-        self.preprocessor(condition)?;
-
-        // 結果が0かどうかを確認して適切なブロックへジャンプ
-        self.output
-            .push_str(&format!("    test {}, {}\n", cmp_reg, cmp_reg));
-        self.output.push_str(&format!("    je {}\n", label_else));
-
-        self.preprocessor(consequence)?;
-        self.output.push_str(&format!("    jmp {}\n", label_end));
-
-        self.output.push_str(&format!("{}:\n", label_else));
-        if let Some(alt) = alternative {
-            self.preprocessor(alt)?;
+    // from から target へ進むときの phi の値を書き込む
+    // phi は同時に値を選ぶので、複数あるときはいったんすべてスタックに積んでから書き込む
+    fn emit_phi_copies(&mut self, function: &Function, from: BlockId, target: BlockId) {
+        let copies: Vec<(Value, Value)> = function
+            .block(target)
+            .insts
+            .iter()
+            .filter_map(|inst| match (&inst.kind, inst.result) {
+                (InstKind::Phi(incoming), Some(result)) => incoming
+                    .iter()
+                    .find(|(pred, _)| *pred == from)
+                    .map(|(_, value)| (result, *value)),
+                _ => None,
+            })
+            .collect();
+        if let [(result, value)] = copies.as_slice() {
+            if self.allocation.location(*result) == self.allocation.location(*value) {
+                return;
+            }
+            let (source, target) = (self.operand64(*value), self.operand64(*result));
+            // メモリからメモリへは直接書き込めない
+            if source.is_mem() && target.is_mem() {
+                self.emit("mov", vec![reg("rax"), source]);
+                self.emit("mov", vec![target, reg("rax")]);
+            } else {
+                self.emit("mov", vec![target, source]);
+            }
+            return;
+        }
+        for (_, value) in &copies {
+            let operand = self.operand64(*value);
+            self.emit("push", vec![operand]);
+        }
+        for (result, _) in copies.iter().rev() {
+            let operand = self.operand64(*result);
+            self.emit("pop", vec![operand]);
         }
-
-        self.output.push_str(&format!("{}:\n", label_end));
-        Ok(())
     }
 
-    fn emit_while_loop(&mut self, condition: &Expr, body: &Expr) -> Result<(), String> {
-        let label_start = self.new_label("start");
-        let label_end = self.new_label("end");
-
-        // ループ開始ラベル
-        self.output.push_str(&format!("{}:\n", label_start));
-
-        // 条件式の評価とループ終了のジャンプ
-        self.preprocessor(condition)?;
-
-        self.output.push_str(&format!("    jge {}\n", label_end));
-
-        // ループ本体の処理
-        self.process_loop_body(body)?;
-
-        // ループの開始へ戻る
-        self.output.push_str(&format!("    jmp {}\n", label_start));
+    // 値の置き場所。レジスタは値の型の幅で表す
+    fn operand(&self, function: &Function, value: Value) -> Operand {
+        match self.allocation.location(value) {
+            Location::Register(reg) => Operand::Reg(reg.name(function.value_type(value))),
+            Location::Stack(offset) => Operand::mem(stack_address(offset)),
+        }
+    }
 
-        // ループ終了ラベル
-        self.output.push_str(&format!("{}:\n", label_end));
+    // 8 バイト全体を読み書きするときの置き場所
+    fn operand64(&self, value: Value) -> Operand {
+        match self.allocation.location(value) {
+            Location::Register(reg) => Operand::Reg(reg.name(Ty::I64)),
+            Location::Stack(offset) => Operand::mem(format!("qword {}", stack_address(offset))),
+        }
+    }
 
-        Ok(())
+    fn load(&mut self, function: &Function, reg: &'static str, value: Value) {
+        let operand = self.operand(function, value);
+        self.emit("mov", vec![Operand::Reg(reg), operand]);
     }
 
-    fn process_loop_body(&mut self, body: &Expr) -> Result<(), String> {
-        if let Expr::Block(expressions) = body {
-            for expression in expressions {
-                match expression {
-                    Expr::Assignment { name, value, .. } => {
-                        self.process_assignment_in_loop(name, value)?;
-                    }
-                    _ => {
-                        self.preprocessor(expression)?;
-                    }
-                }
-            }
+    // idiv の前に被除数 eax/rax を edx:eax/rdx:rax に符号拡張する
+    fn emit_sign_extend(&mut self, reg_acc: &str) {
+        if reg_acc == "rax" {
+            self.emit("cqo", vec![]);
+        } else {
+            self.emit("cdq", vec![]);
         }
-        Ok(())
     }
 
-    fn process_assignment_in_loop(&mut self, name: &str, value: &Expr) -> Result<(), String> {
-        match value {
-            Expr::Literal(Literal::I32(i)) => {
-                self.output
-                    .push_str(&format!("    mov eax, [{}_res]\n", name));
-                self.output.push_str(&format!("    add eax, {}\n", i));
-                self.output
-                    .push_str(&format!("    mov [{}_res], eax\n", name));
-            }
-            _ => {
-                self.preprocessor(value)?;
-                self.output
-                    .push_str(&format!("    mov [{}_res], eax\n", name));
-            }
-        }
-        Ok(())
+    // 直前の演算で桁あふれしたら、位置を表示して終了する
+    fn emit_overflow_check(&mut self, span: Span) {
+        self.emit_trap("jo", "integer overflow", span);
     }
 
-    fn allocate_variable(
+    // idiv が例外を起こす 0 除算と MIN / -1 を、位置を表示して終了する実行時エラーにする
+    // 検査に scratch を使う。被除数と除数は壊さない
+    fn emit_division_checks(
         &mut self,
-        name: &str,
-        type_decl: &Option<String>,
-    ) -> Result<String, String> {
-        // 変数名に_resを追加する際に既に_resが含まれているかどうかを確認
-        let var_label = if name.ends_with("_res") {
-            name.to_string()
+        op: BinOp,
+        dividend: &'static str,
+        divisor: &'static str,
+        scratch: &'static str,
+        span: Span,
+    ) {
+        let operation = if op == BinOp::Div {
+            "division"
         } else {
-            format!("{}_res", name)
+            "remainder"
         };
-
-        // variablesにvar_name_resと登録される
-        if !self.variables.contains_key(&var_label) {
-            // 型宣言がない場合はエラーを返す
-            let data_type = match type_decl {
-                Some(t) => t.as_str(),
-                None => {
-                    return Err(format!(
-                        "Error: No type declaration provided for variable '{}'",
-                        name
-                    ))
-                }
-            };
-
-            println!("Allocating new variable '{}'", var_label);
-            self.data_section.push_str(&format!(
-                "{} {} 0\n",
-                var_label,
-                if data_type == "i64" { "dq" } else { "dd" }
-            ));
-            self.variables.insert(
-                var_label.clone(),
-                (var_label.clone(), data_type.to_string()),
-            );
-            println!(
-                "Variable '{}' allocated with type '{}'",
-                var_label, data_type
-            );
-        } else {
-            println!("Variable '{}' already allocated", var_label);
-        }
-
-        // 正常に変数が確保された場合、変数のアドレスを返す
-        Ok(self.variables.get(&var_label).unwrap().0.clone())
+        self.emit("test", vec![Operand::Reg(divisor), Operand::Reg(divisor)]);
+        self.emit_trap("jz", &format!("{} by zero", operation), span);
+        // 除数が -1 のとき、被除数の符号を反転して桁あふれするのは MIN だけ
+        let checked = format!(".{}", self.new_label("divisor_checked"));
+        self.emit("cmp", vec![Operand::Reg(divisor), Operand::Imm(-1)]);
+        self.emit("jne", vec![Operand::symbol(checked.clone())]);
+        self.emit("mov", vec![Operand::Reg(scratch), Operand::Reg(dividend)]);
+        self.emit("neg", vec![Operand::Reg(scratch)]);
+        self.emit_trap("jo", &format!("integer overflow in {}", operation), span);
+        self.label(checked);
     }
 
-    fn emit_assignment(
-        &mut self,
-        name: &str,
-        type_decl: &Option<String>,
-        value: &Expr,
-    ) -> Result<(), String> {
-        let var_address = self.allocate_variable(name, type_decl)?;
-        match value {
-            Expr::Literal(Literal::I32(i)) => {
-                if let Some("i32") = type_decl.as_deref() {
-                    let existing_def = format!("{} dd 0", var_address);
-                    let new_def = format!("{} dd {}", var_address, i);
-                    self.data_section = self.data_section.replace(&existing_def, &new_def);
-                } else {
-                    return Err("Type mismatch: expected i64, found i32".to_string());
-                }
-            }
-            Expr::Literal(Literal::I64(i)) => {
-                if let Some("i64") = type_decl.as_deref() {
-                    let existing_def = format!("{} dq 0", var_address);
-                    let new_def = format!("{} dq {}", var_address, i);
-                    self.data_section = self.data_section.replace(&existing_def, &new_def);
-                } else {
-                    return Err("Type mismatch: expected i32, found i64".to_string());
-                }
-            }
-            _ => return Err("Unsupported assignment type".to_string()),
-        }
-        println!(
-            "Data section after assignment ({}): {}",
-            type_decl.as_ref().unwrap_or(&"i32".to_string()),
-            self.data_section
-        );
-        Ok(())
+    // 条件 jump が成り立てば message を表示して終了する
+    fn emit_trap(&mut self, jump: &'static str, message: &str, span: Span) {
+        let label = self.new_label("trap");
+        self.emit(jump, vec![Operand::symbol(label.clone())]);
+        let message = format!("{} at {}:{}", message, self.options.file_name, span);
+        self.traps.push((label, message));
     }
 
-    fn emit_variable(&mut self, name: &str) -> Result<(), String> {
-        let var_label = format!("{}_res", name);
-        // スコープチェーンを逆順で調べ、最初に見つかった変数のアドレスを使用
-        for scope in self.scopes.iter().rev() {
-            if let Some((address, _)) = scope.get(&var_label) {
-                println!("Variable '{}' found at address '{}'", name, address);
-                self.output.push_str(&format!("mov rax, [{}]\n", address));
-                return Ok(());
-            }
-        }
-        Err(format!("Variable '{}' not defined", name))
-    }
-
-    // 演算時に使用するレジスタのビット幅を決定するための関数
-    // variablesに登録されている変数の名前は変数名_resとなっている
-    fn use_64bit_regs(&self, expr: &Expr) -> bool {
-        match expr {
-            Expr::Variable(name) => {
-                let result = self.variables.get(&format!("{}_res", name)).map_or_else(
-                    || {
-                        println!("Variable '{}' not found in registry.", name);
-                        false
-                    },
-                    |(_, type_decl)| {
-                        println!(
-                            "Checking type for variable '{}': type_decl={}",
-                            name, type_decl
-                        );
-                        type_decl == "i64"
-                    },
+    // 実行時エラーの飛び先。メッセージを標準エラー出力に書いて終了する
+    // 文字列リテラルもここで .data に置く
+    fn emit_traps(&mut self) {
+        let mut data = Vec::new();
+        if !self.traps.is_empty() {
+            for (label, message) in std::mem::take(&mut self.traps) {
+                self.label(label.clone());
+                self.emit(
+                    "lea",
+                    vec![reg("rsi"), Operand::mem(format!("[rel {}_msg]", label))],
                 );
-                println!("Variable '{}' is using 64-bit registers: {}", name, result);
-                result
-            }
-            Expr::Literal(Literal::I64(_)) => {
-                println!("Literal is I64, using 64-bit registers.");
-                true
-            }
-            _ => {
-                println!("Expression is not using 64-bit registers.");
-                false
+                self.emit(
+                    "mov",
+                    vec![reg("edx"), Operand::Imm(message.len() as i64 + 1)],
+                );
+                self.emit("jmp", vec![Operand::symbol("runtime_trap")]);
+                data.push(format!("{}_msg db \"{}\", 10", label, message));
+            }
+            self.label("runtime_trap");
+            self.emit("mov", vec![reg("edi"), Operand::Imm(2)]);
+            self.emit("mov", vec![reg("eax"), Operand::Imm(1)]);
+            self.emit("syscall", vec![]);
+            self.emit("mov", vec![reg("eax"), Operand::Imm(60)]);
+            self.emit("mov", vec![reg("edi"), Operand::Imm(TRAP_EXIT_CODE as i64)]);
+            self.emit("syscall", vec![]);
+        }
+        for (label, s) in std::mem::take(&mut self.strings) {
+            data.push(format!("{} db \"{}\", 0", label, s));
+        }
+        if !data.is_empty() {
+            self.directive("section .data");
+            for line in data {
+                self.directive(line);
             }
         }
     }
 
-    fn emit_binary_op(&mut self, left: &Expr, op: &Op, right: &Expr) -> Result<(), String> {
-        println!(
-            "emit_binary_op called with left: {:?}, op: {:?}, right: {:?}",
-            left, op, right
-        );
-
-        let (reg_left, reg_right, reg_result) =
-            if self.use_64bit_regs(left) || self.use_64bit_regs(right) {
-                println!("Using 64-bit registers for operation");
-                ("rbx", "rcx", "rax")
-            } else {
-                println!("Using 32-bit registers for operation");
-                ("ebx", "ecx", "eax")
-            };
-
-        println!(
-            "Selected registers: reg_left={}, reg_right={}, reg_result={}",
-            reg_left, reg_right, reg_result
-        );
+    fn new_label(&self, base: &str) -> String {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        format!("{}_{}", base, count)
+    }
+}
 
-        // 左辺の評価とレジスタへのロード
-        self.load_expr_to_register(left, reg_left)?;
+impl Default for CodeGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        // 右辺の評価とレジスタへのロード
-        self.load_expr_to_register(right, reg_right)?;
+// 型に合う幅のレジスタ。name は 'a' 'c' 'd'
+fn register(ty: Ty, name: char) -> &'static str {
+    match (ty, name) {
+        (Ty::I64, 'a') => "rax",
+        (Ty::I64, 'c') => "rcx",
+        (Ty::I64, 'd') => "rdx",
+        (Ty::I32, 'a') => "eax",
+        (Ty::I32, 'c') => "ecx",
+        (Ty::I32, 'd') => "edx",
+        _ => unreachable!("unknown register {}", name),
+    }
+}
 
-        // 演算の実行
-        match op {
-            Op::Add | Op::Subtract | Op::Multiply | Op::Divide => {
-                if op == &Op::Add {
-                    self.output
-                        .push_str(&format!("    add {}, {}\n", reg_left, reg_right));
-                } else if op == &Op::Subtract {
-                    self.output
-                        .push_str(&format!("    sub {}, {}\n", reg_left, reg_right));
-                } else if op == &Op::Multiply {
-                    self.output
-                        .push_str(&format!("    imul {}, {}\n", reg_left, reg_right));
-                } else if op == &Op::Divide {
-                    self.output.push_str("    xor edx, edx\n");
-                    self.output.push_str(&format!("    idiv {}\n", reg_right));
-                }
-                // 演算結果を結果用レジスタに格納
-                self.output
-                    .push_str(&format!("    mov {}, {}\n", reg_result, reg_left));
-            }
-            Op::LessThan | Op::GreaterThan => {
-                self.output
-                    .push_str(&format!("    cmp {}, {}\n", reg_left, reg_right));
-                if op == &Op::LessThan {
-                    self.output.push_str("    setl al\n");
-                } else if op == &Op::GreaterThan {
-                    self.output.push_str("    setg al\n");
-                }
-                self.output.push_str("    movzx eax, al\n");
-            } // _ => {
-              //     return Err(format!("Unsupported operation '{:?}'", op));
-              // }
-        }
+fn reg(name: &'static str) -> Operand {
+    Operand::Reg(name)
+}
 
-        Ok(())
-    }
+fn block_label(block: BlockId) -> Operand {
+    Operand::symbol(format!(".bb{}", block.0))
+}
 
-    fn load_expr_to_register(&mut self, expr: &Expr, register: &str) -> Result<(), String> {
-        match expr {
-            Expr::Variable(name) => {
-                let var_name = format!("{}_res", name);
-                if let Some((address, _)) = self.variables.get(&var_name) {
-                    self.output
-                        .push_str(&format!("    mov {}, [{}]\n", register, address));
-                } else {
-                    return Err(format!("Variable '{}' not defined", name));
-                }
-            }
-            Expr::Literal(Literal::I32(i)) => {
-                self.output
-                    .push_str(&format!("    mov {}, {}\n", register, i));
-            }
-            Expr::Literal(Literal::I64(i)) => {
-                self.output
-                    .push_str(&format!("    mov {}, {}\n", register, i));
-            }
-            Expr::BinaryOp { left, op, right } => {
-                self.emit_binary_op(left, op, right)?;
-                self.output
-                    .push_str(&format!("push rax\nmov {}, rax\n", register));
-            }
-            _ => {
-                return Err("Unsupported expression type for loading to register".to_string());
-            }
-        }
-        Ok(())
+fn stack_address(offset: i64) -> String {
+    if offset < 0 {
+        format!("[rbp{}]", offset)
+    } else {
+        format!("[rbp+{}]", offset)
     }
+}
 
-    fn emit_literal(&mut self, lit: &Literal) -> Result<(), String> {
-        match lit {
-            Literal::I32(i) => {
-                self.output.push_str(&format!("    mov eax, {}\n", i));
-            }
-            Literal::I64(i) => {
-                self.output.push_str(&format!("    mov rax, {}\n", i));
-            }
-            Literal::String(s) => {
-                let label = self.new_label("str");
-                self.output.push_str(&format!(
-                    "    .data\n{}:\n    .string \"{}\"\n    .text\n    lea rax, [{}]\n",
-                    label, s, label
-                ));
-            }
-            Literal::Unit => {}
-        }
-        Ok(())
-    }
+fn has_phis(function: &Function, block: BlockId) -> bool {
+    function
+        .block(block)
+        .insts
+        .iter()
+        .any(|inst| matches!(inst.kind, InstKind::Phi(_)))
+}
 
-    fn emit_return(&mut self, expr: &Expr) -> Result<(), String> {
-        self.preprocessor(expr)?;
-        self.output.push_str("    ret\n");
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::asm::AsmInst;
+    use crate::test_util::lower_to_mir;
+
+    // source を MIR まで変換して命令を選ぶ。ファイル名は test.sim
+    fn generate(source: &str, overflow_checks: bool) -> Vec<AsmLine> {
+        let program = lower_to_mir(source);
+        let mut generator = CodeGenerator::with_options(CodegenOptions {
+            overflow_checks,
+            file_name: "test.sim".to_string(),
+            peephole: false,
+        });
+        generator
+            .generate(&program)
+            .expect("Failed to generate code");
+        generator.output
     }
 
-    fn new_label(&self, base: &str) -> String {
-        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
-        let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        format!("{}_{}", base, count)
+    fn position(lines: &[AsmLine], opcode: &str, operands: &[Operand]) -> usize {
+        lines
+            .iter()
+            .position(|line| {
+                line.as_inst()
+                    .is_some_and(|inst| inst.opcode == opcode && inst.operands == operands)
+            })
+            .unwrap_or_else(|| panic!("'{}' is not emitted:\n{}", opcode, render(lines)))
     }
 
-    fn emit_print(&mut self, expr: &Expr) -> Result<(), String> {
-        println!("Emitting print function for expression: {:?}", expr);
-
-        let use_64bit = match expr {
-            Expr::Variable(name) => self.use_64bit_regs(&Expr::Variable(name.clone())),
-            _ => self.use_64bit_regs(expr),
+    // jump の飛び先のラベルが message を表示するか
+    fn assert_traps(lines: &[AsmLine], jump: &AsmLine, message: &str) {
+        let Some(AsmInst { operands, .. }) = jump.as_inst() else {
+            panic!("Not an instruction: {}", jump);
+        };
+        let [Operand::Symbol(label)] = operands.as_slice() else {
+            panic!("Not a jump: {}", jump);
         };
+        let target = lines
+            .iter()
+            .position(|line| *line == AsmLine::Label(label.clone()))
+            .unwrap_or_else(|| panic!("Label '{}' is not emitted", label));
+        assert_eq!(
+            lines[target + 1],
+            AsmLine::inst(
+                "lea",
+                vec![reg("rsi"), Operand::mem(format!("[rel {}_msg]", label))]
+            )
+        );
+        let data = AsmLine::Directive(format!("{}_msg db \"{}\", 10", label, message));
+        assert!(lines.contains(&data), "{}", render(lines));
+    }
 
-        let reg = if use_64bit { "rax" } else { "eax" };
+    #[test]
+    fn test_overflow_checks() {
+        for (op, instruction) in [("+", "add"), ("-", "sub"), ("*", "imul")] {
+            let source = format!("function f(a:i64, b:i64) -> i64 {{ a {} b }}", op);
+            let lines = generate(&source, true);
+            let i = position(&lines, instruction, &[reg("rax"), reg("rcx")]);
+            assert_eq!(lines[i + 1].as_inst().map(|inst| inst.opcode), Some("jo"));
+            assert_traps(&lines, &lines[i + 1], "integer overflow at test.sim:1:35");
+
+            let lines = generate(&source, false);
+            position(&lines, instruction, &[reg("rax"), reg("rcx")]);
+            assert!(!lines
+                .iter()
+                .any(|line| line.as_inst().is_some_and(|inst| inst.opcode == "jo")));
+        }
+    }
 
-        match expr {
-            Expr::Literal(Literal::I32(i)) => {
-                self.output.push_str(&format!("    mov {}, {}\n", reg, i));
-            }
-            Expr::Literal(Literal::I64(i)) => {
-                self.output.push_str(&format!("    mov {}, {}\n", reg, i));
-            }
-            Expr::Literal(Literal::String(s)) => {
-                let label = self.new_label("str");
-                self.output.push_str(&format!(
-                    "    .section .rodata\n{}:\n    .ascii \"{}\\0\"\n",
-                    label, s
-                ));
-                self.output.push_str(&format!("    lea rsi, [rel {}]\n    mov edi, 1\n    mov eax, 1\n    mov edx, {}\n    syscall\n", label, s.len() + 1));
-            }
-            Expr::Variable(name) => {
-                let var_name = format!("{}_res", name);
-                if let Some((var_address, _)) = self.variables.get(&var_name) {
-                    println!(
-                        "Printing variable '{}', found at address '{}'",
-                        name, var_address
-                    );
-                    self.output
-                        .push_str(&format!("    mov {}, [{}]\n", reg, var_address));
-                } else {
-                    println!("Error: Variable '{}' not found during print", name);
-                    return Err(format!("Variable '{}' not defined", name));
-                }
-            }
-            Expr::BinaryOp { left, op, right } => {
-                self.emit_binary_op(left, op, right)?;
-            }
-            _ => println!("Unsupported expression type in print"),
+    #[test]
+    fn test_wrapping_builtins_are_not_checked() {
+        for (builtin, instruction) in [
+            ("wrapping_add", "add"),
+            ("wrapping_sub", "sub"),
+            ("wrapping_mul", "imul"),
+        ] {
+            let source = format!("function f(a:i32, b:i32) -> i32 {{ {}(a, b) }}", builtin);
+            let lines = generate(&source, true);
+            position(&lines, instruction, &[reg("eax"), reg("ecx")]);
+            assert!(
+                !lines
+                    .iter()
+                    .any(|line| line.as_inst().is_some_and(|inst| inst.opcode == "jo")),
+                "{}",
+                render(&lines)
+            );
         }
+    }
 
-        self.output.push_str(&format!("    mov {}, {}\n    lea rsi, [rel buffer_0]\n    call int_to_ascii\n    lea rsi, [rel buffer_0]\n    mov edi, 1\n    mov eax, 1\n    mov edx, 12\n    syscall\n", reg, reg));
-        Ok(())
+    #[test]
+    fn test_division_checks() {
+        for (op, operation) in [("/", "division"), ("%", "remainder")] {
+            let source = format!("function f(a:i64, b:i64) -> i64 {{ a {} b }}", op);
+            // 桁あふれの検査を外しても、除算の検査は残る
+            let lines = generate(&source, false);
+            let divide = position(&lines, "idiv", &[reg("rcx")]);
+            // 除数が 0 なら終了する
+            let zero = position(&lines, "test", &[reg("rcx"), reg("rcx")]);
+            assert_eq!(
+                lines[zero + 1].as_inst().map(|inst| inst.opcode),
+                Some("jz")
+            );
+            assert_traps(
+                &lines,
+                &lines[zero + 1],
+                &format!("{} by zero at test.sim:1:35", operation),
+            );
+            // 除数が -1 なら、被除数の符号を反転して桁あふれするか調べる
+            let minus_one = position(&lines, "cmp", &[reg("rcx"), Operand::Imm(-1)]);
+            let negate = position(&lines, "neg", &[reg("rdx")]);
+            assert_eq!(
+                lines[negate + 1].as_inst().map(|inst| inst.opcode),
+                Some("jo")
+            );
+            assert_traps(
+                &lines,
+                &lines[negate + 1],
+                &format!("integer overflow in {} at test.sim:1:35", operation),
+            );
+            assert!(zero < minus_one && minus_one < negate && negate < divide);
+        }
     }
 }
@@ -1 +1,4 @@
+pub mod asm;
 pub mod codegen;
+pub mod peephole;
+pub mod regalloc;
@@ -0,0 +1,380 @@
+use crate::backend::asm::{register32, register_width, AsmInst, AsmLine, Operand};
+
+// のぞき穴最適化
+// 命令列の各位置で RULES を順に試し、当てはまれば置き換える。何も変わらなくなるまで繰り返す
+// どの書き換えも、レジスタ、メモリ、フラグのうち後で読まれうるものの値を変えない
+
+// 置き換える行数と置き換え後の行
+type Rewrite = (usize, Vec<AsmLine>);
+
+// 書き換えの規則
+// apply は位置 i 以降の命令列を受け取り、当てはまれば置き換えを返す
+pub struct Rule {
+    pub name: &'static str,
+    apply: fn(&[AsmLine]) -> Option<Rewrite>,
+}
+
+pub const RULES: [Rule; 8] = [
+    Rule {
+        name: "self-move",
+        apply: self_move,
+    },
+    Rule {
+        name: "zero-extended-self-move",
+        apply: zero_extended_self_move,
+    },
+    Rule {
+        name: "move-back",
+        apply: move_back,
+    },
+    Rule {
+        name: "zero-extended-move-back",
+        apply: zero_extended_move_back,
+    },
+    Rule {
+        name: "push-pop",
+        apply: push_pop,
+    },
+    Rule {
+        name: "dead-move",
+        apply: dead_move,
+    },
+    Rule {
+        name: "zero-with-xor",
+        apply: zero_with_xor,
+    },
+    Rule {
+        name: "jump-to-next",
+        apply: jump_to_next,
+    },
+];
+
+// 書き換えた回数を返す
+pub fn optimize(lines: &mut Vec<AsmLine>) -> usize {
+    optimize_with(lines, &RULES)
+}
+
+pub fn optimize_with(lines: &mut Vec<AsmLine>, rules: &[Rule]) -> usize {
+    let mut rewrites = 0;
+    let mut i = 0;
+    while i < lines.len() {
+        let Some((count, replacement)) = rules.iter().find_map(|rule| (rule.apply)(&lines[i..]))
+        else {
+            i += 1;
+            continue;
+        };
+        lines.splice(i..i + count, replacement);
+        rewrites += 1;
+        // 置き換えで手前の命令と新しく組になることがある
+        i = i.saturating_sub(2);
+    }
+    rewrites
+}
+
+// mov rax, rax
+fn self_move(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [Operand::Reg(dst), Operand::Reg(src)] = mov(lines.first()?)? else {
+        return None;
+    };
+    // 32 ビットの mov は上位 32 ビットを 0 にするので残す
+    (dst == src && register_width(dst) == Some(64)).then(|| (1, Vec::new()))
+}
+
+// mov eax, ebx; mov eax, eax
+// 32 ビットのレジスタに書き込むと上位は 0 になっているので、2つめは何もしない
+fn zero_extended_self_move(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [first, second, ..] = lines else {
+        return None;
+    };
+    let [Operand::Reg(dst), Operand::Reg(src)] = mov(second)? else {
+        return None;
+    };
+    (dst == src && writes_register32(first.as_inst()?, dst)).then(|| (2, vec![first.clone()]))
+}
+
+// mov r8, rax; mov rax, r8
+// 2つめが書き込む値は既に入っている
+fn move_back(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [first, second, ..] = lines else {
+        return None;
+    };
+    let [a, b] = mov(first)?;
+    if *mov(second)? != [b.clone(), a.clone()] {
+        return None;
+    }
+    // 書き込んだレジスタでアドレスを計算するなら別の場所になる
+    if let (Operand::Reg(reg), mem @ Operand::Mem(_)) | (mem @ Operand::Mem(_), Operand::Reg(reg)) =
+        (a, b)
+    {
+        if mem.mentions(reg) {
+            return None;
+        }
+    }
+    // 32 ビットの mov は書き込み先の上位を 0 にする。もとから 0 と分かるときだけ消せる
+    if let Operand::Reg(reg) = b {
+        if register_width(reg) == Some(32) {
+            return None;
+        }
+    }
+    Some((2, vec![first.clone()]))
+}
+
+// movzx eax, al; mov esi, eax; mov eax, esi
+// eax の上位は既に 0 なので、3つめは 32 ビットでも何もしない
+fn zero_extended_move_back(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [first, second, third, ..] = lines else {
+        return None;
+    };
+    let [Operand::Reg(a), Operand::Reg(b)] = mov(second)? else {
+        return None;
+    };
+    if *mov(third)? != [Operand::Reg(b), Operand::Reg(a)] {
+        return None;
+    }
+    writes_register32(first.as_inst()?, b).then(|| (3, vec![first.clone(), second.clone()]))
+}
+
+// push rax; pop rax は何もしない。push a; pop b は mov b, a
+fn push_pop(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [first, second, ..] = lines else {
+        return None;
+    };
+    let (push, pop) = (first.as_inst()?, second.as_inst()?);
+    if push.opcode != "push" || pop.opcode != "pop" {
+        return None;
+    }
+    let (source, target) = (&push.operands[0], &pop.operands[0]);
+    if source == target {
+        return Some((2, Vec::new()));
+    }
+    // rsp を使うアドレスは push で位置がずれる
+    if (source.is_mem() && target.is_mem()) || source.mentions("rsp") || target.mentions("rsp") {
+        return None;
+    }
+    if let Operand::Imm(_) = source {
+        return None;
+    }
+    Some((
+        2,
+        vec![AsmLine::inst("mov", vec![target.clone(), source.clone()])],
+    ))
+}
+
+// mov r8, rax; mov r8, rcx
+// 1つめの値は読まれないうちに上書きされる
+fn dead_move(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [first, second, ..] = lines else {
+        return None;
+    };
+    let [Operand::Reg(dead), _] = mov(first)? else {
+        return None;
+    };
+    let [Operand::Reg(dst), src] = mov(second)? else {
+        return None;
+    };
+    // 8 ビットの書き込みは残りのビットを変えない
+    let overwrites = register32(dst) == register32(dead) && register_width(dst) != Some(8);
+    (overwrites && !src.mentions(dead)).then(|| (2, vec![second.clone()]))
+}
+
+// mov r8, 0 は xor r8d, r8d のほうが短い。フラグが変わるので、後で読まれないときだけ
+fn zero_with_xor(lines: &[AsmLine]) -> Option<Rewrite> {
+    let [Operand::Reg(reg), Operand::Imm(0)] = mov(lines.first()?)? else {
+        return None;
+    };
+    let reg32 = register32(reg).filter(|_| register_width(reg) != Some(8))?;
+    flags_dead(&lines[1..]).then(|| {
+        (
+            1,
+            vec![AsmLine::inst(
+                "xor",
+                vec![Operand::Reg(reg32), Operand::Reg(reg32)],
+            )],
+        )
+    })
+}
+
+// jmp .bb2 の直後が .bb2 なら飛ばなくてよい
+fn jump_to_next(lines: &[AsmLine]) -> Option<Rewrite> {
+    let inst = lines.first()?.as_inst()?;
+    if !inst.opcode.starts_with('j') {
+        return None;
+    }
+    let [Operand::Symbol(target)] = inst.operands.as_slice() else {
+        return None;
+    };
+    lines[1..]
+        .iter()
+        .map_while(|line| match line {
+            AsmLine::Label(label) => Some(label),
+            _ => None,
+        })
+        .any(|label| label == target)
+        .then(|| (1, Vec::new()))
+}
+
+fn mov(line: &AsmLine) -> Option<&[Operand; 2]> {
+    let inst = line.as_inst()?;
+    if inst.opcode != "mov" {
+        return None;
+    }
+    inst.operands.as_slice().try_into().ok()
+}
+
+// 32 ビットのレジスタ reg に結果を書き込む命令か
+fn writes_register32(inst: &AsmInst, reg: &str) -> bool {
+    let writes = matches!(
+        inst.opcode,
+        "mov" | "movzx" | "add" | "sub" | "imul" | "xor" | "neg"
+    );
+    writes
+        && register_width(reg) == Some(32)
+        && matches!(inst.operands.first(), Some(Operand::Reg(dst)) if *dst == reg)
+}
+
+// 後に続く命令がフラグを読む前に書き換えるか
+// ラベルやジャンプの先で読まれるかは分からないので、そこまで来たら読まれるとみなす
+fn flags_dead(lines: &[AsmLine]) -> bool {
+    for line in lines {
+        let AsmLine::Inst(inst) = line else {
+            return false;
+        };
+        match inst.opcode {
+            "add" | "sub" | "imul" | "idiv" | "div" | "neg" | "cmp" | "test" | "xor" | "call"
+            | "ret" => return true,
+            "jmp" => return false,
+            opcode if opcode.starts_with('j') || opcode.starts_with("set") => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::asm::{parse_asm, render};
+
+    // name の規則だけで書き換える
+    fn rewrite(name: &str, before: &str) -> String {
+        let rule = RULES
+            .into_iter()
+            .find(|rule| rule.name == name)
+            .expect("unknown rule");
+        let mut lines = parse_asm(before);
+        optimize_with(&mut lines, &[rule]);
+        render(&lines)
+    }
+
+    #[test]
+    fn test_self_move() {
+        assert_eq!(rewrite("self-move", "mov rax, rax\nret"), "    ret\n");
+        let zero_extends = "    mov eax, eax\n";
+        assert_eq!(rewrite("self-move", zero_extends), zero_extends);
+    }
+
+    #[test]
+    fn test_zero_extended_self_move() {
+        assert_eq!(
+            rewrite("zero-extended-self-move", "mov eax, ebx\nmov eax, eax"),
+            "    mov eax, ebx\n"
+        );
+        let upper_unknown = "    mov rax, rbx\n    mov eax, eax\n";
+        assert_eq!(
+            rewrite("zero-extended-self-move", upper_unknown),
+            upper_unknown
+        );
+    }
+
+    #[test]
+    fn test_move_back() {
+        assert_eq!(
+            rewrite("move-back", "mov r8, rax\nmov rax, r8"),
+            "    mov r8, rax\n"
+        );
+        assert_eq!(
+            rewrite("move-back", "mov [rbp-8], rax\nmov rax, [rbp-8]"),
+            "    mov [rbp-8], rax\n"
+        );
+        let address_changes = "    mov rax, [rax]\n    mov [rax], rax\n";
+        assert_eq!(rewrite("move-back", address_changes), address_changes);
+    }
+
+    #[test]
+    fn test_zero_extended_move_back() {
+        assert_eq!(
+            rewrite(
+                "zero-extended-move-back",
+                "movzx eax, al\nmov esi, eax\nmov eax, esi"
+            ),
+            "    movzx eax, al\n    mov esi, eax\n"
+        );
+        let upper_unknown = "    mov rax, [rbp-8]\n    mov esi, eax\n    mov eax, esi\n";
+        assert_eq!(
+            rewrite("zero-extended-move-back", upper_unknown),
+            upper_unknown
+        );
+    }
+
+    #[test]
+    fn test_push_pop() {
+        assert_eq!(rewrite("push-pop", "push rax\npop rax\nret"), "    ret\n");
+        assert_eq!(
+            rewrite("push-pop", "push qword [rbp-8]\npop r8"),
+            "    mov r8, qword [rbp-8]\n"
+        );
+        let memory_to_memory = "    push qword [rbp-8]\n    pop qword [rbp-16]\n";
+        assert_eq!(rewrite("push-pop", memory_to_memory), memory_to_memory);
+    }
+
+    #[test]
+    fn test_dead_move() {
+        assert_eq!(
+            rewrite("dead-move", "mov r8, rax\nmov r8d, ecx"),
+            "    mov r8d, ecx\n"
+        );
+        let reads_old_value = "    mov r8, rax\n    mov r8, [r8]\n";
+        assert_eq!(rewrite("dead-move", reads_old_value), reads_old_value);
+    }
+
+    #[test]
+    fn test_zero_with_xor() {
+        assert_eq!(
+            rewrite("zero-with-xor", "mov r8, 0\ncmp rax, rcx"),
+            "    xor r8d, r8d\n    cmp rax, rcx\n"
+        );
+        // 直前の add の桁あふれを jo で読む
+        let flags_live = "    mov r8, 0\n    jo trap_0\n";
+        assert_eq!(rewrite("zero-with-xor", flags_live), flags_live);
+    }
+
+    #[test]
+    fn test_jump_to_next() {
+        assert_eq!(
+            rewrite("jump-to-next", "jmp .bb2\n.bb1:\n.bb2:\nret"),
+            ".bb1:\n.bb2:\n    ret\n"
+        );
+        let other_target = "    je .bb3\n.bb2:\n";
+        assert_eq!(rewrite("jump-to-next", other_target), other_target);
+    }
+
+    #[test]
+    fn test_rules_combine() {
+        // レジスタ割り当て後によく出る列
+        let mut lines = parse_asm(
+            "mov r8, rax\n\
+             mov rax, r8\n\
+             push r8\n\
+             pop r9\n\
+             mov rax, 0\n\
+             test rax, rax\n\
+             jmp .bb1\n\
+             .bb1:",
+        );
+        optimize(&mut lines);
+        assert_eq!(
+            render(&lines),
+            "    mov r8, rax\n    mov r9, r8\n    xor eax, eax\n    test rax, rax\n.bb1:\n"
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use crate::mir::loops::loop_depths;
+use crate::mir::{Function, InstKind, Ty, Value};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+// 値に割り当てられるレジスタ
+// rax/rcx/rdx は命令を組み立てる作業用に空けておく
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reg {
+    R8,
+    R9,
+    R10,
+    R11,
+    Rsi,
+    Rdi,
+    Rbx,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+// 呼び出しで壊れるレジスタ。呼び出しをまたいで生きる値には使わない
+const CALLER_SAVED: [Reg; 6] = [Reg::R8, Reg::R9, Reg::R10, Reg::R11, Reg::Rsi, Reg::Rdi];
+// 呼ばれた関数が保存して戻すレジスタ
+const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+
+impl Reg {
+    pub fn name(self, ty: Ty) -> &'static str {
+        match (self, ty) {
+            (Reg::R8, Ty::I64) => "r8",
+            (Reg::R9, Ty::I64) => "r9",
+            (Reg::R10, Ty::I64) => "r10",
+            (Reg::R11, Ty::I64) => "r11",
+            (Reg::Rsi, Ty::I64) => "rsi",
+            (Reg::Rdi, Ty::I64) => "rdi",
+            (Reg::Rbx, Ty::I64) => "rbx",
+            (Reg::R12, Ty::I64) => "r12",
+            (Reg::R13, Ty::I64) => "r13",
+            (Reg::R14, Ty::I64) => "r14",
+            (Reg::R15, Ty::I64) => "r15",
+            (Reg::R8, Ty::I32) => "r8d",
+            (Reg::R9, Ty::I32) => "r9d",
+            (Reg::R10, Ty::I32) => "r10d",
+            (Reg::R11, Ty::I32) => "r11d",
+            (Reg::Rsi, Ty::I32) => "esi",
+            (Reg::Rdi, Ty::I32) => "edi",
+            (Reg::Rbx, Ty::I32) => "ebx",
+            (Reg::R12, Ty::I32) => "r12d",
+            (Reg::R13, Ty::I32) => "r13d",
+            (Reg::R14, Ty::I32) => "r14d",
+            (Reg::R15, Ty::I32) => "r15d",
+        }
+    }
+
+    pub fn is_callee_saved(self) -> bool {
+        CALLEE_SAVED.contains(&self)
+    }
+}
+
+// 値の置き場所
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Register(Reg),
+    // rbp からの変位。引数は rbp+16 から上、退避した値は保存したレジスタの下
+    Stack(i64),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Allocation {
+    pub locations: HashMap<Value, Location>,
+    // 使うので入口で保存する callee-saved レジスタ。この順に積む
+    pub saved: Vec<Reg>,
+    // 保存したレジスタの下に確保するスタックの大きさ
+    pub frame_size: i64,
+}
+
+impl Allocation {
+    pub fn location(&self, value: Value) -> Location {
+        self.locations[&value]
+    }
+}
+
+// 値の生存区間。命令の位置の番号で [start, end]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub value: Value,
+    pub start: usize,
+    pub end: usize,
+    // 区間の途中に呼び出しがある
+    pub crosses_call: bool,
+}
+
+// 線形走査でレジスタを割り当てる (Poletto と Sarkar の方法)
+// 区間を始まりの順に見て、空いたレジスタを割り当てる。足りなければ退避の重みが最も小さい区間を退避する
+// 重みが同じなら最も遅く終わる区間を選ぶ
+// save_registers が false なら callee-saved レジスタを保存しない (戻らない _start 用)
+pub fn allocate_registers(function: &Function, save_registers: bool) -> Allocation {
+    let intervals = live_intervals(function);
+    let weights = spill_weights(function);
+    let weight = |interval: &Interval| {
+        (
+            weights.get(&interval.value).copied().unwrap_or(0),
+            Reverse(interval.end),
+        )
+    };
+    let mut registers: HashMap<Value, Reg> = HashMap::new();
+    let mut spilled: Vec<Value> = Vec::new();
+    // (終わり, 値)。割り当て済みで生きている区間
+    let mut active: Vec<Interval> = Vec::new();
+    let mut free: Vec<Reg> = CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect();
+
+    for interval in &intervals {
+        active.retain(|other| {
+            if other.end <= interval.start {
+                free.push(registers[&other.value]);
+                false
+            } else {
+                true
+            }
+        });
+        let allowed = |reg: &Reg| !interval.crosses_call || reg.is_callee_saved();
+        // 呼び出しをまたがない値には、なるべく保存の要らないレジスタを使う
+        let choice = CALLER_SAVED
+            .iter()
+            .chain(&CALLEE_SAVED)
+            .find(|reg| allowed(reg) && free.contains(reg))
+            .copied();
+        if let Some(reg) = choice {
+            free.retain(|free| *free != reg);
+            registers.insert(interval.value, reg);
+            active.push(*interval);
+            continue;
+        }
+        let victim = active
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| allowed(&registers[&other.value]))
+            .min_by_key(|(_, other)| (weight(other), Reverse(other.value)))
+            .map(|(i, other)| (i, *other));
+        match victim {
+            Some((i, other)) if weight(&other) < weight(interval) => {
+                let reg = registers
+                    .remove(&other.value)
+                    .expect("active interval has a register");
+                spilled.push(other.value);
+                active.remove(i);
+                registers.insert(interval.value, reg);
+                active.push(*interval);
+            }
+            _ => spilled.push(interval.value),
+        }
+    }
+
+    let mut saved: Vec<Reg> = Vec::new();
+    if save_registers {
+        for reg in CALLEE_SAVED {
+            if registers.values().any(|used| *used == reg) {
+                saved.push(reg);
+            }
+        }
+    }
+    let mut locations: HashMap<Value, Location> = registers
+        .iter()
+        .map(|(value, reg)| (*value, Location::Register(*reg)))
+        .collect();
+    // 退避した引数は呼び出し元が積んだ場所をそのまま使う
+    let mut slots = 0;
+    spilled.sort();
+    for value in spilled {
+        let location = match function.params.iter().position(|param| *param == value) {
+            Some(i) => Location::Stack(16 + 8 * i as i64),
+            None => {
+                slots += 1;
+                Location::Stack(-8 * (saved.len() as i64 + slots))
+            }
+        };
+        locations.insert(value, location);
+    }
+    // rsp を 16 バイト境界に揃えておく
+    let frame_size = (8 * (saved.len() as i64 + slots) + 15) / 16 * 16 - 8 * saved.len() as i64;
+    Allocation {
+        locations,
+        saved,
+        frame_size,
+    }
+}
+
+// 値を退避したときに増えるメモリの読み書きの見積もり
+// 定義と使用を1つ数え、ループの中では深さごとに10倍にする
+fn spill_weights(function: &Function) -> HashMap<Value, usize> {
+    let depths = loop_depths(function);
+    let mut weights = HashMap::new();
+    for (block, depth) in function.blocks.iter().zip(depths) {
+        let weight = 10usize.pow(depth.min(4) as u32);
+        let values = block
+            .insts
+            .iter()
+            .flat_map(|inst| inst.result.into_iter().chain(inst.kind.operands()))
+            .chain(block.terminator.operands());
+        for value in values {
+            *weights.entry(value).or_insert(0) += weight;
+        }
+    }
+    weights
+}
+
+// 値の生存区間を求める
+// ブロックを並べた順に位置を振り、値が生きている位置をすべて含む1つの区間にする
+// phi の値は合流元のブロックの終わりで書き込むので、その位置も区間に含める
+pub fn live_intervals(function: &Function) -> Vec<Interval> {
+    let mut block_start = Vec::new();
+    let mut block_end = Vec::new();
+    let mut inst_positions = Vec::new();
+    let mut calls = Vec::new();
+    let mut position = 0;
+    for block in &function.blocks {
+        block_start.push(position);
+        position += 2;
+        let mut positions = Vec::new();
+        for inst in &block.insts {
+            if matches!(inst.kind, InstKind::Phi(_)) {
+                positions.push(block_start[block_start.len() - 1]);
+                continue;
+            }
+            if matches!(
+                inst.kind,
+                InstKind::Call { .. } | InstKind::Print(_) | InstKind::PrintStr(_)
+            ) {
+                calls.push(position);
+            }
+            positions.push(position);
+            position += 2;
+        }
+        inst_positions.push(positions);
+        block_end.push(position);
+        position += 2;
+    }
+
+    let mut ranges: HashMap<Value, (usize, usize)> = HashMap::new();
+    let mut add = |value: Value, position: usize| {
+        let range = ranges.entry(value).or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+    for param in &function.params {
+        add(*param, 0);
+    }
+    let preds = function.predecessors();
+    for (i, block) in function.blocks.iter().enumerate() {
+        for (inst, position) in block.insts.iter().zip(&inst_positions[i]) {
+            if let Some(result) = inst.result {
+                add(result, *position);
+            }
+            match &inst.kind {
+                InstKind::Phi(incoming) => {
+                    for (pred, value) in incoming {
+                        add(*value, block_end[pred.0]);
+                    }
+                    if let Some(result) = inst.result {
+                        for pred in &preds[i] {
+                            add(result, block_end[pred.0]);
+                        }
+                    }
+                }
+                kind => {
+                    for value in kind.operands() {
+                        add(value, *position);
+                    }
+                }
+            }
+        }
+        for value in block.terminator.operands() {
+            add(value, block_end[i]);
+        }
+    }
+    let (live_in, live_out) = liveness(function);
+    for i in 0..function.blocks.len() {
+        for value in &live_in[i] {
+            add(*value, block_start[i]);
+        }
+        for value in &live_out[i] {
+            add(*value, block_end[i]);
+        }
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(value, (start, end))| Interval {
+            value,
+            start,
+            end,
+            crosses_call: calls.iter().any(|call| start < *call && *call < end),
+        })
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.value));
+    intervals
+}
+
+// ブロックの入口と出口で生きている値
+// phi の引数は合流元の出口で使い、phi の値はブロックの入口で定義するものとして扱う
+fn liveness(function: &Function) -> (Vec<HashSet<Value>>, Vec<HashSet<Value>>) {
+    let blocks = function.blocks.len();
+    let mut uses = vec![HashSet::new(); blocks];
+    let mut defs = vec![HashSet::new(); blocks];
+    let mut phi_defs = vec![HashSet::new(); blocks];
+    // (合流元, 合流先) ごとの phi の引数
+    let mut phi_uses: HashMap<(usize, usize), HashSet<Value>> = HashMap::new();
+    for (i, block) in function.blocks.iter().enumerate() {
+        for inst in &block.insts {
+            if let InstKind::Phi(incoming) = &inst.kind {
+                for (pred, value) in incoming {
+                    phi_uses.entry((pred.0, i)).or_default().insert(*value);
+                }
+                phi_defs[i].extend(inst.result);
+                continue;
+            }
+            for value in inst.kind.operands() {
+                if !defs[i].contains(&value) && !phi_defs[i].contains(&value) {
+                    uses[i].insert(value);
+                }
+            }
+            defs[i].extend(inst.result);
+        }
+        for value in block.terminator.operands() {
+            if !defs[i].contains(&value) && !phi_defs[i].contains(&value) {
+                uses[i].insert(value);
+            }
+        }
+    }
+
+    let mut live_in: Vec<HashSet<Value>> = vec![HashSet::new(); blocks];
+    let mut live_out: Vec<HashSet<Value>> = vec![HashSet::new(); blocks];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..blocks).rev() {
+            let mut out = HashSet::new();
+            for succ in function.blocks[i].terminator.successors() {
+                out.extend(live_in[succ.0].difference(&phi_defs[succ.0]).copied());
+                if let Some(values) = phi_uses.get(&(i, succ.0)) {
+                    out.extend(values.iter().copied());
+                }
+            }
+            let mut input: HashSet<Value> = uses[i].clone();
+            input.extend(
+                out.iter()
+                    .filter(|value| !defs[i].contains(value) && !phi_defs[i].contains(value))
+                    .copied(),
+            );
+            if out != live_out[i] || input != live_in[i] {
+                live_out[i] = out;
+                live_in[i] = input;
+                changed = true;
+            }
+        }
+    }
+    (live_in, live_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::lower_to_mir;
+
+    fn function(source: &str) -> Function {
+        lower_to_mir(source).functions.remove(0)
+    }
+
+    // 生存区間が重なる値は同じレジスタを使わない
+    fn assert_no_conflicts(function: &Function, allocation: &Allocation) {
+        let intervals = live_intervals(function);
+        for a in &intervals {
+            for b in &intervals {
+                if a.value != b.value && a.start < b.end && b.start < a.end {
+                    assert_ne!(
+                        allocation.location(a.value),
+                        allocation.location(b.value),
+                        "{} and {} overlap",
+                        a.value,
+                        b.value
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_expression_uses_registers() {
+        let function =
+            function("function f(a:i64, b:i64, c:i64, d:i64) -> i64 { (a + b) * (c + d) }");
+        let allocation = allocate_registers(&function, true);
+        assert_no_conflicts(&function, &allocation);
+        for value in 0..function.values.len() {
+            assert!(matches!(
+                allocation.location(Value(value)),
+                Location::Register(_)
+            ));
+        }
+        assert!(allocation.saved.is_empty());
+    }
+
+    #[test]
+    fn test_values_live_across_calls_use_callee_saved_registers() {
+        let function = function("function f(a:i64) -> i64 { let b:i64 = a + 1; print(a); b }");
+        let allocation = allocate_registers(&function, true);
+        let crossing: Vec<Interval> = live_intervals(&function)
+            .into_iter()
+            .filter(|interval| interval.crosses_call)
+            .collect();
+        assert_eq!(crossing.len(), 1);
+        let Location::Register(reg) = allocation.location(crossing[0].value) else {
+            panic!("Expected a register");
+        };
+        assert!(reg.is_callee_saved());
+        assert_eq!(allocation.saved, vec![reg]);
+    }
+
+    #[test]
+    fn test_spills_when_registers_run_out() {
+        // 12 個の値が同時に生きる
+        let params: Vec<String> = (0..12).map(|i| format!("p{}:i64", i)).collect();
+        let sum: Vec<String> = (0..12).map(|i| format!("p{}", i)).collect();
+        let function = function(&format!(
+            "function f({}) -> i64 {{ {} }}",
+            params.join(", "),
+            sum.join(" + ")
+        ));
+        let allocation = allocate_registers(&function, true);
+        assert_no_conflicts(&function, &allocation);
+        let spilled = function
+            .params
+            .iter()
+            .filter(|param| matches!(allocation.location(**param), Location::Stack(_)))
+            .count();
+        assert!(spilled >= 1);
+        // 退避した引数は呼び出し元が積んだ場所にある
+        for (i, param) in function.params.iter().enumerate() {
+            if let Location::Stack(offset) = allocation.location(*param) {
+                assert_eq!(offset, 16 + 8 * i as i64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_loop_values_stay_in_registers() {
+        // 12 個の引数はループの後まで生きる。ループの中の値を優先してレジスタに置く
+        let params: Vec<String> = (0..12).map(|i| format!("p{}:i64", i)).collect();
+        let sum: Vec<String> = (0..12).map(|i| format!("p{}", i)).collect();
+        let function = function(&format!(
+            "function f({}) -> i64 {{ let mut i:i64 = 0; while (i < p0) {{ i += 1; }} {} + i }}",
+            params.join(", "),
+            sum.join(" + ")
+        ));
+        let allocation = allocate_registers(&function, true);
+        assert_no_conflicts(&function, &allocation);
+        let depths = loop_depths(&function);
+        for (block, depth) in function.blocks.iter().zip(depths) {
+            if depth == 0 {
+                continue;
+            }
+            for inst in &block.insts {
+                let value = inst.result.into_iter().chain(inst.kind.operands());
+                for value in value {
+                    assert!(
+                        matches!(allocation.location(value), Location::Register(_)),
+                        "{} is spilled",
+                        value
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_loop_phi_covers_back_edge() {
+        let function = function(
+            "function f(n:i64) -> i64 { let mut i:i64 = 0; let mut s:i64 = 0; \
+             while (i < n) { s += i; i += 1; } s }",
+        );
+        let allocation = allocate_registers(&function, true);
+        assert_no_conflicts(&function, &allocation);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::parser::ast::{
+    Attribute, Block, DeclKind, Expr, ExprKind, FunctionDef, Item, Literal, Program, Stmt, StmtKind,
+};
+use crate::parser::lexer::{tokenize_with_comments, Comment};
+use crate::parser::span::Span;
+use crate::parser::Parser;
+
+const INDENT: &str = "    ";
+
+// ソースコードを解析し、決まった書式で出力し直す
+// - インデントは4スペース、文は1行に1つ、`{` は同じ行に置く
+// - コメントと、文の間の空行(1行まで)は残す
+pub fn format_source(source: &str) -> Result<String, String> {
+    let (tokens, spans, comments) = tokenize_with_comments(source)?;
+    let mut parser = Parser::with_spans(tokens, spans);
+    let program = parser.parse_tokens()?;
+
+    let mut formatter = Formatter {
+        out: String::new(),
+        indent: 0,
+        comments,
+        next_comment: 0,
+        source_lines: source.lines().collect(),
+    };
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    out: String,
+    indent: usize,
+    comments: Vec<Comment>,
+    // まだ出力していない最初のコメント
+    next_comment: usize,
+    source_lines: Vec<&'a str>,
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &Program) {
+        for item in &program.items {
+            match item {
+                Item::Function(function) => self.function(function),
+                Item::Stmt(stmt) => self.stmt(stmt),
+            }
+        }
+        // ファイル末尾のコメント
+        self.comments_before(usize::MAX);
+    }
+
+    fn function(&mut self, function: &FunctionDef) {
+        self.attributes(&function.attrs);
+        self.start_line(function.span);
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .map(|param| format!("{}:{}", param.name, param.ty))
+            .collect();
+        self.out.push_str(&format!(
+            "function {}({})",
+            function.name,
+            params.join(", ")
+        ));
+        if let Some(return_type) = &function.return_type {
+            self.out.push_str(&format!(" -> {}", return_type));
+        }
+        self.out.push(' ');
+        self.block(&function.body);
+        self.out.push('\n');
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.attributes(&stmt.attrs);
+        self.start_line(stmt.span);
+        match &stmt.kind {
+            StmtKind::Let {
+                kind,
+                mutable,
+                name,
+                type_decl,
+                value,
+            } => {
+                self.out.push_str(match kind {
+                    DeclKind::Let => "let ",
+                    DeclKind::Const => "const ",
+                });
+                if *mutable {
+                    self.out.push_str("mut ");
+                }
+                self.out.push_str(name);
+                if let Some(type_name) = type_decl {
+                    self.out.push_str(&format!(":{}", type_name));
+                }
+                if let Some(value) = value {
+                    self.out.push_str(" = ");
+                    self.expr(value);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Assignment { name, op, value } => {
+                match op {
+                    Some(op) => self.out.push_str(&format!("{} {}= ", name, op)),
+                    None => self.out.push_str(&format!("{} = ", name)),
+                }
+                self.expr(value);
+                self.out.push(';');
+            }
+            StmtKind::WhileLoop {
+                label,
+                condition,
+                body,
+            } => {
+                if let Some(label) = label {
+                    self.out.push_str(&format!("'{}: ", label));
+                }
+                self.out.push_str("while (");
+                self.expr(condition);
+                self.out.push_str(") ");
+                self.block(body);
+            }
+            StmtKind::Return(value) => {
+                self.out.push_str("return");
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expr(value);
+                }
+                self.out.push(';');
+            }
+            StmtKind::Break(label) => {
+                self.out.push_str("break");
+                self.loop_label(label);
+                self.out.push(';');
+            }
+            StmtKind::Continue(label) => {
+                self.out.push_str("continue");
+                self.loop_label(label);
+                self.out.push(';');
+            }
+            StmtKind::Print(expr) => {
+                self.out.push_str("print(");
+                self.expr(expr);
+                self.out.push_str(");");
+            }
+            StmtKind::Expr(expr) if expr.is_block_like() => self.expr(expr),
+            StmtKind::Expr(expr) => {
+                self.statement_expr(expr);
+                self.out.push(';');
+            }
+        }
+        self.out.push('\n');
+    }
+
+    // 属性は1つずつ別の行に置く
+    fn attributes(&mut self, attrs: &[Attribute]) {
+        for attr in attrs {
+            self.start_line(attr.span);
+            self.out.push_str(&format!("#[{}", attr.name));
+            if !attr.args.is_empty() {
+                self.out.push_str(&format!("({})", attr.args.join(", ")));
+            }
+            self.out.push_str("]\n");
+        }
+    }
+
+    fn loop_label(&mut self, label: &Option<String>) {
+        if let Some(label) = label {
+            self.out.push_str(&format!(" '{}", label));
+        }
+    }
+
+    // 文の先頭が if やブロックだとそこで文が終わってしまうので括弧で囲む
+    fn statement_expr(&mut self, expr: &Expr) {
+        if starts_with_block(expr) && !expr.is_block_like() {
+            self.out.push('(');
+            self.expr(expr);
+            self.out.push(')');
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.out.push('{');
+        let is_empty = block.stmts.is_empty() && block.tail.is_none();
+        if is_empty && !self.has_comment_before(block.close_span.line) {
+            self.out.push('}');
+            return;
+        }
+        self.out.push('\n');
+        self.indent += 1;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.start_line(tail.span);
+            self.statement_expr(tail);
+            self.out.push('\n');
+        }
+        self.comments_before(block.close_span.line);
+        self.indent -= 1;
+        self.push_indent();
+        self.out.push('}');
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::FunctionCall { name, args } => {
+                self.out.push_str(name);
+                self.out.push('(');
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expr(arg);
+                }
+                self.out.push(')');
+            }
+            ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.out.push_str("if (");
+                self.expr(condition);
+                self.out.push_str(") ");
+                self.block(consequence);
+                if let Some(alt) = alternative {
+                    self.out.push_str(" else ");
+                    self.block(alt);
+                }
+            }
+            ExprKind::BinaryOp { left, op, right } => {
+                self.expr(left);
+                self.out.push_str(&format!(" {} ", op));
+                // 演算子は左結合なので、右辺の二項演算は括弧が要る
+                if matches!(right.kind, ExprKind::BinaryOp { .. }) {
+                    self.out.push('(');
+                    self.expr(right);
+                    self.out.push(')');
+                } else {
+                    self.expr(right);
+                }
+            }
+            ExprKind::Literal(Literal::I32(i)) => self.out.push_str(&i.to_string()),
+            ExprKind::Literal(Literal::I64(i)) => self.out.push_str(&i.to_string()),
+            ExprKind::Literal(Literal::String(s)) => self.out.push_str(&format!("\"{}\"", s)),
+            ExprKind::Literal(Literal::Unit) => {}
+            ExprKind::Variable(name) => self.out.push_str(name),
+            ExprKind::Block(block) => self.block(block),
+        }
+    }
+
+    // 新しい行を始める。手前のコメントと空行をここで出力する
+    fn start_line(&mut self, span: Span) {
+        self.comments_before(span.line);
+        if self.blank_line_before(span.line) {
+            self.out.push('\n');
+        }
+        self.push_indent();
+    }
+
+    // line 行より前にあるコメントをすべて出力する
+    fn comments_before(&mut self, line: usize) {
+        while self.has_comment_before(line) {
+            let comment = self.comments[self.next_comment].clone();
+            self.next_comment += 1;
+            if comment.trailing && self.out.ends_with('\n') {
+                // 直前の行の末尾に付け直す
+                self.out.pop();
+                self.out.push_str(&format!(" {}\n", comment.text));
+            } else {
+                if self.blank_line_before(comment.span.line) {
+                    self.out.push('\n');
+                }
+                self.push_indent();
+                self.out.push_str(&comment.text);
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn has_comment_before(&self, line: usize) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.span.line < line)
+    }
+
+    // ソースで直前の行が空行だったか。ブロックやファイルの先頭では空行を入れない
+    fn blank_line_before(&self, line: usize) -> bool {
+        if self.out.is_empty() || self.out.ends_with("{\n") {
+            return false;
+        }
+        line >= 2
+            && self
+                .source_lines
+                .get(line - 2)
+                .is_some_and(|text| text.trim().is_empty())
+    }
+
+    fn push_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+}
+
+fn starts_with_block(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::BinaryOp { left, .. } => starts_with_block(left),
+        _ => expr.is_block_like(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format_source(source).expect("Failed to format");
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).as_deref(), Ok(expected));
+    }
+
+    #[test]
+    fn test_spacing_and_indentation() {
+        assert_formats(
+            "x:i64=0;\nwhile(x<10){x=x+1;x+=1;x%=7;};\nif (x > 5) { print(x); }\nelse {print(1);}",
+            "let mut x:i64 = 0;\nwhile (x < 10) {\n    x = x + 1;\n    x += 1;\n    x %= 7;\n}\nif (x > 5) {\n    print(x);\n} else {\n    print(1);\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_functions_and_tails() {
+        assert_formats(
+            "function add(x:i64,y:i64)->i64{x+y}\n\n\n\nfunction noop(){}\nprint(add(1,2));",
+            "function add(x:i64, y:i64) -> i64 {\n    x + y\n}\n\nfunction noop() {}\nprint(add(1, 2));\n",
+        );
+    }
+
+    #[test]
+    fn test_labels_and_nested_operators() {
+        assert_formats(
+            "'outer: while (i < 10) { if (i > 5) { break 'outer; } continue; }\nprint(1 + (2 * 3));\nlet y = { 1 } + 2;\n({ 1 } + 2);",
+            "'outer: while (i < 10) {\n    if (i > 5) {\n        break 'outer;\n    }\n    continue;\n}\nprint(1 + (2 * 3));\nlet y = {\n    1\n} + 2;\n({\n    1\n} + 2);\n",
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        assert_formats(
+            "#[allow(unused_functions)] function f(){}\n{ #[ deny( shadowing,unused_variables ) ]\nlet x = 1; }",
+            "#[allow(unused_functions)]\nfunction f() {}\n{\n    #[deny(shadowing, unused_variables)]\n    let x = 1;\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_comments_are_preserved() {
+        assert_formats(
+            "// header\n\nx:i64 = 1; // one\nwhile (x < 3) {\n  // body\n  x = x + 1;\n  // end of body\n} // after loop\nprint(x);\n// footer\n",
+            "// header\n\nlet mut x:i64 = 1; // one\nwhile (x < 3) {\n    // body\n    x = x + 1;\n    // end of body\n} // after loop\nprint(x);\n// footer\n",
+        );
+    }
+
+    #[test]
+    fn test_examples_are_formatted_and_idempotent() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).expect("Failed to read examples") {
+            let path = entry.expect("Failed to read entry").path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("sim") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).expect("Failed to read example");
+            let formatted = format_source(&source).expect("Failed to format example");
+            assert_eq!(
+                format_source(&formatted).as_ref(),
+                Ok(&formatted),
+                "Formatting is not idempotent: {}",
+                path.display()
+            );
+            assert_eq!(formatted, source, "Not formatted: {}", path.display());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::analysis::builtins::Builtin;
+use crate::analysis::cfg::ControlFlow;
+use crate::analysis::resolve::{Resolutions, SymbolId};
+use crate::analysis::typeck::TypeTable;
+use crate::hir::{
+    Block, Expr, ExprKind, Function, InlineHint, Item, Param, Program, Stmt, StmtKind,
+};
+use crate::parser::ast::{self, Literal, NodeId, Type};
+
+// 検査を通った AST を HIR に変換する
+// 検査で求めた結果が欠けていればエラーにする (検査の漏れで、ソースの誤りではない)
+pub fn lower_program(
+    program: &ast::Program,
+    resolutions: &Resolutions,
+    types: &TypeTable,
+    control_flow: &ControlFlow,
+) -> Result<Program, String> {
+    let lowerer = Lowerer {
+        resolutions,
+        types,
+        control_flow,
+    };
+    let mut items = Vec::new();
+    for item in &program.items {
+        match item {
+            ast::Item::Function(function) => {
+                items.push(Item::Function(lowerer.function(function)?));
+            }
+            ast::Item::Stmt(stmt) if control_flow.is_reachable(stmt.id) => {
+                items.push(Item::Stmt(lowerer.stmt(stmt)?));
+            }
+            ast::Item::Stmt(_) => {}
+        }
+    }
+    Ok(Program { items })
+}
+
+struct Lowerer<'a> {
+    resolutions: &'a Resolutions,
+    types: &'a TypeTable,
+    control_flow: &'a ControlFlow,
+}
+
+impl Lowerer<'_> {
+    fn function(&self, function: &ast::FunctionDef) -> Result<Function, String> {
+        let params = function
+            .params
+            .iter()
+            .map(|param| {
+                Ok(Param {
+                    symbol: self.symbol(param.id, &param.name)?,
+                    name: param.name.clone(),
+                    ty: param.ty.clone(),
+                    span: param.span,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Function {
+            symbol: self.symbol(function.id, &function.name)?,
+            name: function.name.clone(),
+            params,
+            return_type: function.return_type.clone(),
+            body: self.block(&function.body)?,
+            inline: InlineHint::from_attributes(&function.attrs),
+            span: function.span,
+        })
+    }
+
+    fn block(&self, block: &ast::Block) -> Result<Block, String> {
+        let mut stmts = Vec::new();
+        for stmt in &block.stmts {
+            if self.control_flow.is_reachable(stmt.id) {
+                stmts.push(self.stmt(stmt)?);
+            }
+        }
+        let tail = match &block.tail {
+            Some(tail) if self.control_flow.is_reachable(tail.id) => {
+                Some(Box::new(self.expr(tail)?))
+            }
+            _ => None,
+        };
+        Ok(Block { stmts, tail })
+    }
+
+    fn stmt(&self, stmt: &ast::Stmt) -> Result<Stmt, String> {
+        let kind = match &stmt.kind {
+            ast::StmtKind::Let { name, value, .. } => {
+                let symbol = self.symbol(stmt.id, name)?;
+                StmtKind::Let {
+                    symbol,
+                    name: name.clone(),
+                    ty: self.symbol_type(symbol, name)?,
+                    value: value.as_ref().map(|value| self.expr(value)).transpose()?,
+                }
+            }
+            ast::StmtKind::Assignment { name, op, value } => {
+                let symbol = self.symbol(stmt.id, name)?;
+                let mut value = self.expr(value)?;
+                // x op= v は x = x op v
+                if let Some(op) = op {
+                    let ty = Some(self.symbol_type(symbol, name)?);
+                    let variable = Expr {
+                        kind: ExprKind::Variable {
+                            symbol,
+                            name: name.clone(),
+                        },
+                        ty: ty.clone(),
+                        span: stmt.span,
+                    };
+                    value = Expr {
+                        kind: ExprKind::Binary {
+                            op: op.clone(),
+                            left: Box::new(variable),
+                            right: Box::new(value),
+                            checked: true,
+                        },
+                        ty,
+                        span: stmt.span,
+                    };
+                }
+                StmtKind::Assign {
+                    symbol,
+                    name: name.clone(),
+                    value,
+                }
+            }
+            ast::StmtKind::WhileLoop {
+                label,
+                condition,
+                body,
+            } => StmtKind::While {
+                label: label.clone(),
+                condition: self.expr(condition)?,
+                body: self.block(body)?,
+            },
+            ast::StmtKind::Return(Some(value))
+                if stmt.attrs.iter().any(|attr| attr.name == "tail") =>
+            {
+                // 呼び出しであることは check_tail_calls で検査してある
+                match self.expr(value)?.kind {
+                    ExprKind::Call { symbol, name, args } => {
+                        StmtKind::TailCall { symbol, name, args }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "{}: Tail call of a value that is not a call",
+                            value.span
+                        ))
+                    }
+                }
+            }
+            ast::StmtKind::Return(value) => {
+                StmtKind::Return(value.as_ref().map(|value| self.expr(value)).transpose()?)
+            }
+            ast::StmtKind::Break(label) => StmtKind::Break(label.clone()),
+            ast::StmtKind::Continue(label) => StmtKind::Continue(label.clone()),
+            ast::StmtKind::Print(expr) => StmtKind::Print(self.expr(expr)?),
+            ast::StmtKind::Expr(expr) => StmtKind::Expr(self.expr(expr)?),
+        };
+        Ok(Stmt {
+            kind,
+            span: stmt.span,
+        })
+    }
+
+    fn expr(&self, expr: &ast::Expr) -> Result<Expr, String> {
+        let ty = self.types.expr_type(expr.id).cloned();
+        let kind = match &expr.kind {
+            ast::ExprKind::Literal(Literal::I32(i)) => ExprKind::Int(*i as i64),
+            ast::ExprKind::Literal(Literal::I64(i)) => ExprKind::Int(*i),
+            ast::ExprKind::Literal(Literal::String(s)) => ExprKind::String(s.clone()),
+            ast::ExprKind::Literal(Literal::Unit) => ExprKind::Unit,
+            ast::ExprKind::Variable(name) => ExprKind::Variable {
+                symbol: self.symbol(expr.id, name)?,
+                name: name.clone(),
+            },
+            ast::ExprKind::BinaryOp { left, op, right } => ExprKind::Binary {
+                op: op.clone(),
+                left: Box::new(self.expr(left)?),
+                right: Box::new(self.expr(right)?),
+                checked: true,
+            },
+            ast::ExprKind::FunctionCall { name, args } => return self.call(expr, name, args, ty),
+            ast::ExprKind::IfExpr {
+                condition,
+                consequence,
+                alternative,
+            } => ExprKind::If {
+                condition: Box::new(self.expr(condition)?),
+                consequence: self.block(consequence)?,
+                alternative: alternative
+                    .as_ref()
+                    .map(|alt| self.block(alt))
+                    .transpose()?,
+            },
+            ast::ExprKind::Block(block) => ExprKind::Block(self.block(block)?),
+        };
+        Ok(Expr {
+            kind,
+            ty,
+            span: expr.span,
+        })
+    }
+
+    fn call(
+        &self,
+        call: &ast::Expr,
+        name: &str,
+        args: &[ast::Expr],
+        ty: Option<Type>,
+    ) -> Result<Expr, String> {
+        let symbol = match (self.resolutions.lookup(call.id), Builtin::from_name(name)) {
+            (Some(symbol), _) => symbol,
+            // 組み込み関数は検査しない二項演算になる
+            (None, Some(builtin)) => {
+                let [left, right] = args else {
+                    return Err(format!("Builtin '{}' takes 2 arguments", name));
+                };
+                return Ok(Expr {
+                    kind: ExprKind::Binary {
+                        op: builtin.op(),
+                        left: Box::new(self.expr(left)?),
+                        right: Box::new(self.expr(right)?),
+                        checked: false,
+                    },
+                    ty,
+                    span: call.span,
+                });
+            }
+            (None, None) => return Err(format!("Name '{}' was not resolved", name)),
+        };
+        let params = match self.symbol_type(symbol, name)? {
+            Type::Function { params, .. } => params,
+            _ => return Err(format!("'{}' is not a function", name)),
+        };
+        let args = args
+            .iter()
+            .zip(&params)
+            .map(|(arg, param)| {
+                let arg = self.expr(arg)?;
+                // i64 の引数に渡す i32 の値は符号拡張する
+                if param.is_named("i64") && !arg.is_i64() {
+                    let span = arg.span;
+                    return Ok(Expr {
+                        kind: ExprKind::Widen(Box::new(arg)),
+                        ty: Some(param.clone()),
+                        span,
+                    });
+                }
+                Ok(arg)
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Expr {
+            kind: ExprKind::Call {
+                symbol,
+                name: name.to_string(),
+                args,
+            },
+            ty,
+            span: call.span,
+        })
+    }
+
+    fn symbol(&self, node: NodeId, name: &str) -> Result<SymbolId, String> {
+        self.resolutions
+            .lookup(node)
+            .ok_or_else(|| format!("Name '{}' was not resolved", name))
+    }
+
+    fn symbol_type(&self, symbol: SymbolId, name: &str) -> Result<Type, String> {
+        self.types
+            .symbol_type(symbol)
+            .cloned()
+            .ok_or_else(|| format!("Type of '{}' is not known", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::Op;
+    use crate::test_util::lower_to_hir as lower;
+
+    fn last_stmt(program: &Program) -> &StmtKind {
+        match program.items.last() {
+            Some(Item::Stmt(stmt)) => &stmt.kind,
+            item => panic!("Unexpected item: {:?}", item),
+        }
+    }
+
+    #[test]
+    fn test_compound_assignment_is_desugared() {
+        let program = lower("let mut x:i64 = 1; x *= 2;");
+        let StmtKind::Assign { value, .. } = last_stmt(&program) else {
+            panic!("Expected an assignment");
+        };
+        let ExprKind::Binary {
+            op, left, checked, ..
+        } = &value.kind
+        else {
+            panic!("Expected a binary operation: {:?}", value);
+        };
+        assert_eq!((op, *checked), (&Op::Multiply, true));
+        assert!(matches!(left.kind, ExprKind::Variable { .. }));
+        assert!(value.is_i64() && left.is_i64());
+    }
+
+    #[test]
+    fn test_widening_is_explicit() {
+        let program = lower("function f(x:i64) { print(x); }; let n:i32 = 1; f(n); f(2);");
+        let calls: Vec<&Expr> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Stmt(Stmt {
+                    kind: StmtKind::Expr(call),
+                    ..
+                }) => Some(call),
+                _ => None,
+            })
+            .collect();
+        let args: Vec<&ExprKind> = calls
+            .iter()
+            .map(|call| match &call.kind {
+                ExprKind::Call { args, .. } => &args[0].kind,
+                kind => panic!("Expected a call: {:?}", kind),
+            })
+            .collect();
+        assert!(matches!(args[0], ExprKind::Widen(_)));
+        // リテラルは型検査で i64 になっている
+        assert_eq!(args[1], &ExprKind::Int(2));
+    }
+
+    #[test]
+    fn test_builtins_are_unchecked_operations() {
+        let program = lower("print(wrapping_sub(1, 2));");
+        let StmtKind::Print(value) = last_stmt(&program) else {
+            panic!("Expected a print");
+        };
+        assert!(matches!(
+            value.kind,
+            ExprKind::Binary {
+                op: Op::Subtract,
+                checked: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_unreachable_code_is_dropped() {
+        let program = lower("function f() -> i64 { return 1; print(2); 3 }");
+        let Some(Item::Function(function)) = program.items.first() else {
+            panic!("Expected a function");
+        };
+        assert_eq!(function.body.stmts.len(), 1);
+        assert!(function.body.tail.is_none());
+    }
+
+    #[test]
+    fn test_inline_hints() {
+        let program =
+            lower("#[inline] function f() {}\n#[inline(never)] function g() {}\nfunction h() {}");
+        let hints: Vec<InlineHint> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(function) => Some(function.inline),
+                Item::Stmt(_) => None,
+            })
+            .collect();
+        assert_eq!(
+            hints,
+            vec![InlineHint::Always, InlineHint::Never, InlineHint::Default]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+// 型付きの中間表現 (HIR)
+// 名前解決と型検査の後に AST から作り、バックエンドはこれだけを読む
+// - 変数と関数の参照はシンボルに解決済みで、値を持つ式はすべて型を持つ
+// - 複合代入は通常の代入に、wrapping_add などは検査しない二項演算に、
+//   引数の i32 から i64 への暗黙の変換は Widen に書き換えてある
+// - 到達しない文と式は含まない
+use crate::analysis::resolve::SymbolId;
+use crate::parser::ast::{Attribute, Op, Type};
+use crate::parser::span::Span;
+
+pub mod lower;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Function(Function),
+    Stmt(Stmt),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub symbol: SymbolId,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub body: Block,
+    pub inline: InlineHint,
+    pub span: Span,
+}
+
+// #[inline] と #[inline(never)] で指定するインライン展開の方針
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InlineHint {
+    // 展開するかはコストで決める
+    #[default]
+    Default,
+    Always,
+    Never,
+}
+
+impl InlineHint {
+    // 属性は check_attributes で検査済みなので、inline は高々1つ
+    pub fn from_attributes(attrs: &[Attribute]) -> InlineHint {
+        match attrs.iter().rev().find(|attr| attr.name == "inline") {
+            Some(attr) if attr.args.iter().any(|arg| arg == "never") => InlineHint::Never,
+            Some(_) => InlineHint::Always,
+            None => InlineHint::Default,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub symbol: SymbolId,
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Option<Box<Expr>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StmtKind {
+    // let と const。初期値のない宣言は value が None
+    Let {
+        symbol: SymbolId,
+        name: String,
+        ty: Type,
+        value: Option<Expr>,
+    },
+    Assign {
+        symbol: SymbolId,
+        name: String,
+        value: Expr,
+    },
+    While {
+        label: Option<String>,
+        condition: Expr,
+        body: Block,
+    },
+    Return(Option<Expr>),
+    // #[tail] return f(...)。呼び出し元のフレームを使い回して f へ飛ぶ
+    TailCall {
+        symbol: SymbolId,
+        name: String,
+        args: Vec<Expr>,
+    },
+    Break(Option<String>),
+    Continue(Option<String>),
+    Print(Expr),
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    // 値を持たない式 (戻り値のない関数の呼び出しなど) は None
+    pub ty: Option<Type>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    // 整数リテラル。ビット幅は ty で決まる
+    Int(i64),
+    String(String),
+    Unit,
+    Variable {
+        symbol: SymbolId,
+        name: String,
+    },
+    // checked が false の演算は桁あふれを検査しない (wrapping_add など)
+    // 0 除算と MIN / -1 は checked によらず検査する
+    Binary {
+        op: Op,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        checked: bool,
+    },
+    Call {
+        symbol: SymbolId,
+        name: String,
+        args: Vec<Expr>,
+    },
+    // i32 の値を i64 に符号拡張する
+    Widen(Box<Expr>),
+    If {
+        condition: Box<Expr>,
+        consequence: Block,
+        alternative: Option<Block>,
+    },
+    Block(Block),
+}
+
+impl Expr {
+    pub fn is_i64(&self) -> bool {
+        self.ty.as_ref().is_some_and(|ty| ty.is_named("i64"))
+    }
+}
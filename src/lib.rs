@@ -1,3 +1,9 @@
+pub mod analysis;
 pub mod backend;
+pub mod formatter;
+pub mod hir;
+pub mod mir;
 pub mod parser;
+#[cfg(test)]
+mod test_util;
 pub mod utils;
@@ -1,27 +1,273 @@
-use compiler::backend::codegen::CodeGenerator;
-use compiler::parser::lexer::tokenizer;
+use compiler::analysis::attributes::check_attributes;
+use compiler::analysis::cfg::build_control_flow;
+use compiler::analysis::consteval::fold_constants;
+use compiler::analysis::init::check_initialization;
+use compiler::analysis::lint::{check_lints, Level, Lint, LintConfig};
+use compiler::analysis::mutability::check_mutability;
+use compiler::analysis::resolve::resolve_names;
+use compiler::analysis::returns::check_function_returns;
+use compiler::analysis::tail::check_tail_calls;
+use compiler::analysis::typeck::check_types;
+use compiler::backend::codegen::{CodeGenerator, CodegenOptions};
+use compiler::formatter::format_source;
+use compiler::hir::lower::lower_program;
+use compiler::mir::lower::lower_program as lower_to_mir;
+use compiler::mir::opt::{OptConfig, Pass, PassManager};
+use compiler::mir::verify::verify_program;
+use compiler::parser::lexer::tokenize;
 use compiler::parser::Parser;
 use std::env;
 
+// コンパイルできなかったときは終了コード 1、引数が正しくないときは 2 で終わる
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file_name = &args[1];
+    if args.get(1).map(String::as_str) == Some("fmt") {
+        std::process::exit(run_fmt(&args[2..]));
+    }
+    let options = match parse_options(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: compiler [-A|-W|-D <lint>]... [--deny-warnings] [--overflow-checks|--no-overflow-checks] [-O0|-O1|-O2] [--enable-pass|--disable-pass <pass>]... [--print-passes] [--peephole|--no-peephole] <file>");
+            std::process::exit(2);
+        }
+    };
+    let file_name = &options.codegen.file_name;
     let source_code = std::fs::read_to_string(file_name).expect("Failed to read the source file.");
     println!("compiling source code: \n{}", source_code);
-    let (_, tokens) = tokenizer(&source_code).expect("Failed to tokenize the source code.");
+    let (tokens, spans) = match tokenize(&source_code) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("Failed to tokenize the source code: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let mut parser = Parser { tokens, current: 0 };
-    let ast = match parser.parse_tokens() {
+    let mut parser = Parser::with_spans(tokens, spans);
+    let mut ast = match parser.parse_tokens() {
         Ok(ast) => ast,
         Err(e) => {
             println!("Failed to parse tokens: {}", e);
-            return;
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = check_attributes(&ast) {
+        println!("Failed to check attributes: {}", e);
+        std::process::exit(1);
+    }
+
+    let resolutions = match resolve_names(&ast) {
+        Ok(resolutions) => resolutions,
+        Err(e) => {
+            println!("Failed to resolve names: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = check_mutability(&ast) {
+        println!("Failed to check mutability: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = check_initialization(&ast, &resolutions) {
+        println!("Failed to check initialization: {}", e);
+        std::process::exit(1);
+    }
+
+    let types = match check_types(&ast, &resolutions) {
+        Ok(types) => types,
+        Err(e) => {
+            println!("Failed to check types: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = fold_constants(&mut ast, &types) {
+        println!("Failed to evaluate constants: {}", e);
+        std::process::exit(1);
+    }
+
+    // 畳み込んだ後の条件で作るので、while (1) のような無限ループも分かる
+    let control_flow = build_control_flow(&ast);
+
+    if let Err(e) = check_function_returns(&ast, &types, &control_flow) {
+        println!("Failed to check function returns: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = check_tail_calls(&ast, &resolutions, &types) {
+        println!("Failed to check tail calls: {}", e);
+        std::process::exit(1);
+    }
+
+    let diagnostics = check_lints(&ast, &resolutions, &control_flow, &options.lints);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    if errors > 0 {
+        println!("Aborting due to {} lint error(s)", errors);
+        std::process::exit(1);
+    }
+
+    let hir = match lower_program(&ast, &resolutions, &types, &control_flow) {
+        Ok(hir) => hir,
+        Err(e) => {
+            println!("Failed to lower to HIR: {}", e);
+            std::process::exit(1);
         }
     };
 
-    let mut code_generator = CodeGenerator::new();
-    match code_generator.generate_to_file(&ast, "output.asm") {
+    let mut mir = match lower_to_mir(&hir) {
+        Ok(mir) => mir,
+        Err(e) => {
+            println!("Failed to lower to MIR: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = verify_program(&mir) {
+        println!("Invalid MIR: {}", e);
+        std::process::exit(1);
+    }
+
+    PassManager::new(&options.opt).run(&mut mir);
+    if let Err(e) = verify_program(&mir) {
+        println!("Invalid MIR after optimization: {}", e);
+        std::process::exit(1);
+    }
+
+    let mut code_generator = CodeGenerator::with_options(options.codegen);
+    match code_generator.generate_to_file(&mir, "output.asm") {
         Ok(()) => println!("Assembly code was successfully written to 'output.asm'"),
-        Err(e) => println!("Error generating assembly code: {}", e),
+        Err(e) => {
+            println!("Error generating assembly code: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+struct Options {
+    lints: LintConfig,
+    codegen: CodegenOptions,
+    opt: OptConfig,
+}
+
+// compiler [-A|-W|-D <lint>]... [--deny-warnings] [--overflow-checks|--no-overflow-checks]
+//          [-O0|-O1|-O2] [--enable-pass|--disable-pass <pass>]... [--print-passes]
+//          [--peephole|--no-peephole] <file>
+// -A/-W/-D で lint の水準を allow/warn/deny に変える。後に書いたものが優先される
+// 最適化は既定で -O0 (何もしない)。桁あふれの実行時検査はデバッグ用の -O0 で既定で有効になり、
+// -O1 以上では --overflow-checks を付けたときだけ有効
+// --enable-pass/--disable-pass で -O の水準によらずパスを有効または無効にする
+// --print-passes で MIR を変えたパスを表示する
+// のぞき穴最適化は -O1 以上で有効
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut lints = LintConfig::default();
+    let mut codegen = CodegenOptions::default();
+    let mut opt = OptConfig::default();
+    let mut overflow_checks = None;
+    let mut peephole = None;
+    let mut file_name = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let level = match arg.as_str() {
+            "--deny-warnings" => {
+                lints.deny_warnings = true;
+                continue;
+            }
+            "--overflow-checks" | "--no-overflow-checks" => {
+                overflow_checks = Some(arg == "--overflow-checks");
+                continue;
+            }
+            "--print-passes" => {
+                opt.print_passes = true;
+                continue;
+            }
+            "--peephole" | "--no-peephole" => {
+                peephole = Some(arg == "--peephole");
+                continue;
+            }
+            "-O0" | "-O1" | "-O2" => {
+                opt.level = arg[2..].parse().expect("optimization level");
+                continue;
+            }
+            "--enable-pass" | "--disable-pass" => {
+                let name = args
+                    .next()
+                    .ok_or_else(|| format!("Option '{}' expects a pass name", arg))?;
+                let pass =
+                    Pass::from_name(name).ok_or_else(|| format!("Unknown pass '{}'", name))?;
+                opt.set_enabled(pass, arg == "--enable-pass");
+                continue;
+            }
+            "-A" => Level::Allow,
+            "-W" => Level::Warn,
+            "-D" => Level::Deny,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+            _ => {
+                if file_name.replace(arg.clone()).is_some() {
+                    return Err("Only one source file can be compiled".to_string());
+                }
+                continue;
+            }
+        };
+        let name = args
+            .next()
+            .ok_or_else(|| format!("Option '{}' expects a lint name", arg))?;
+        let lint = Lint::from_name(name).ok_or_else(|| format!("Unknown lint '{}'", name))?;
+        lints.set_level(lint, level);
+    }
+    codegen.overflow_checks = overflow_checks.unwrap_or(opt.level == 0);
+    codegen.peephole = peephole.unwrap_or(opt.level >= 1);
+    codegen.file_name = file_name.ok_or_else(|| "No source file given".to_string())?;
+    Ok(Options {
+        lints,
+        codegen,
+        opt,
+    })
+}
+
+// compiler fmt [--check] <files...>
+// ファイルを整形して上書きする。--check のときは書き換えずに整形済みか確認する
+fn run_fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() {
+        println!("Usage: compiler fmt [--check] <files...>");
+        return 2;
+    }
+
+    let mut status = 0;
+    for file_name in files {
+        let source = match std::fs::read_to_string(file_name) {
+            Ok(source) => source,
+            Err(e) => {
+                println!("Failed to read '{}': {}", file_name, e);
+                status = 1;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                println!("Failed to format '{}': {}", file_name, e);
+                status = 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("'{}' is not formatted", file_name);
+            status = 1;
+        } else if let Err(e) = std::fs::write(file_name, formatted) {
+            println!("Failed to write '{}': {}", file_name, e);
+            status = 1;
+        } else {
+            println!("Formatted '{}'", file_name);
+        }
     }
+    status
 }
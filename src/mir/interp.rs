@@ -0,0 +1,309 @@
+use crate::mir::{BinOp, BlockId, Function, InstKind, Program, Terminator, Ty, Value};
+
+// MIR のインタプリタ
+// 最適化の前後で出力が変わらないことと、実行した命令の数が減ったことを確かめるのに使う
+// 実行時エラーは "{span}: integer overflow" のようなエラーになる
+#[derive(Clone, Debug, PartialEq)]
+pub struct Execution {
+    // print で書き出したもの。整数は1行に1つ
+    pub output: String,
+    // 実行した命令と終端命令の数。phi は合流元でのコピーなので数えない
+    pub steps: usize,
+    // 命令ごとのおおよその実行コストの合計。乗除算とメモリの読み書きを重く数える
+    pub cost: usize,
+}
+
+pub fn interpret(program: &Program, max_steps: usize) -> Result<Execution, String> {
+    let mut interpreter = Interpreter {
+        program,
+        globals: program.globals.iter().map(|global| global.init).collect(),
+        output: String::new(),
+        steps: 0,
+        cost: 0,
+        max_steps,
+    };
+    interpreter.call(&program.main, Vec::new())?;
+    Ok(Execution {
+        output: interpreter.output,
+        steps: interpreter.steps,
+        cost: interpreter.cost,
+    })
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    globals: Vec<i64>,
+    output: String,
+    steps: usize,
+    cost: usize,
+    max_steps: usize,
+}
+
+// 関数の実行の終わり方
+enum Exit<'a> {
+    Return(Option<i64>),
+    TailCall(&'a Function, Vec<i64>),
+}
+
+impl<'a> Interpreter<'a> {
+    // 末尾呼び出しは Rust のスタックを使わず、この関数の中で呼び出し先に移る
+    fn call(&mut self, function: &Function, args: Vec<i64>) -> Result<Option<i64>, String> {
+        let (mut function, mut args) = (function, args);
+        loop {
+            match self.run(function, args)? {
+                Exit::Return(value) => return Ok(value),
+                Exit::TailCall(callee, callee_args) => {
+                    function = callee;
+                    args = callee_args;
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, function: &Function, args: Vec<i64>) -> Result<Exit<'a>, String> {
+        let mut values: Vec<Option<i64>> = vec![None; function.values.len()];
+        for (param, arg) in function.params.iter().zip(args) {
+            values[param.0] = Some(arg);
+        }
+        let get = |values: &[Option<i64>], value: Value| {
+            values[value.0]
+                .ok_or_else(|| format!("function '{}': {} is not defined", function.name, value))
+        };
+        let mut previous: Option<BlockId> = None;
+        let mut current = BlockId(0);
+        loop {
+            let block = function.block(current);
+            // phi は合流元の値をまとめて読んでから書く
+            let mut phis = Vec::new();
+            for inst in &block.insts {
+                if let (Some(result), InstKind::Phi(incoming)) = (inst.result, &inst.kind) {
+                    let Some((_, value)) =
+                        incoming.iter().find(|(pred, _)| Some(*pred) == previous)
+                    else {
+                        return Err(format!(
+                            "function '{}': {} has no value from the previous block",
+                            function.name, result
+                        ));
+                    };
+                    phis.push((result, get(&values, *value)?));
+                }
+            }
+            for (result, value) in phis {
+                values[result.0] = Some(value);
+            }
+            for inst in &block.insts {
+                if matches!(inst.kind, InstKind::Phi(_)) {
+                    continue;
+                }
+                self.step(cost(&inst.kind))?;
+                let result = match &inst.kind {
+                    InstKind::Phi(_) => unreachable!("phis are read on entry"),
+                    InstKind::Const(value) => Some(*value),
+                    InstKind::Copy(value) | InstKind::Widen(value) => Some(get(&values, *value)?),
+                    InstKind::Binary {
+                        op,
+                        lhs,
+                        rhs,
+                        checked,
+                        span,
+                    } => {
+                        let ty = function.value_type(*lhs);
+                        let (lhs, rhs) = (get(&values, *lhs)?, get(&values, *rhs)?);
+                        Some(
+                            binary(*op, ty, lhs, rhs, *checked)
+                                .map_err(|e| format!("{}: {}", span, e))?,
+                        )
+                    }
+                    InstKind::Load(global) => Some(self.globals[global.0]),
+                    InstKind::Store(global, value) => {
+                        self.globals[global.0] = get(&values, *value)?;
+                        None
+                    }
+                    InstKind::Call { callee, args } => {
+                        let callee = self.function(callee)?;
+                        let args = args
+                            .iter()
+                            .map(|arg| get(&values, *arg))
+                            .collect::<Result<_, _>>()?;
+                        self.call(callee, args)?
+                    }
+                    InstKind::Print(value) => {
+                        let value = get(&values, *value)?;
+                        self.output.push_str(&format!("{}\n", value));
+                        None
+                    }
+                    InstKind::PrintStr(s) => {
+                        self.output.push_str(s);
+                        self.output.push('\n');
+                        None
+                    }
+                };
+                if let (Some(result), Some(value)) = (inst.result, result) {
+                    values[result.0] = Some(value);
+                }
+            }
+            let cost = match block.terminator {
+                Terminator::TailCall { .. } => 5,
+                _ => 1,
+            };
+            self.step(cost)?;
+            previous = Some(current);
+            current = match &block.terminator {
+                Terminator::Jump(target) => *target,
+                Terminator::Branch { cond, then, else_ } => {
+                    if get(&values, *cond)? != 0 {
+                        *then
+                    } else {
+                        *else_
+                    }
+                }
+                Terminator::Return(value) => {
+                    let value = value.map(|value| get(&values, value)).transpose()?;
+                    return Ok(Exit::Return(value));
+                }
+                Terminator::TailCall { callee, args } => {
+                    let args = args
+                        .iter()
+                        .map(|arg| get(&values, *arg))
+                        .collect::<Result<_, _>>()?;
+                    return Ok(Exit::TailCall(self.function(callee)?, args));
+                }
+                Terminator::Unreachable => {
+                    return Err(format!("function '{}': reached unreachable", function.name));
+                }
+            };
+        }
+    }
+
+    fn function(&self, name: &str) -> Result<&'a Function, String> {
+        self.program
+            .functions
+            .iter()
+            .find(|function| function.name == name)
+            .ok_or_else(|| format!("Undefined function '{}'", name))
+    }
+
+    fn step(&mut self, cost: usize) -> Result<(), String> {
+        self.steps += 1;
+        self.cost += cost;
+        if self.steps > self.max_steps {
+            return Err(format!("Exceeded {} steps", self.max_steps));
+        }
+        Ok(())
+    }
+}
+
+fn cost(kind: &InstKind) -> usize {
+    match kind {
+        InstKind::Binary {
+            op: BinOp::Div | BinOp::Rem,
+            ..
+        } => 20,
+        InstKind::Binary { op: BinOp::Mul, .. } | InstKind::Load(_) | InstKind::Store(..) => 3,
+        InstKind::Call { .. } => 5,
+        _ => 1,
+    }
+}
+
+// 型の幅で計算する。検査しない演算は折り返す
+fn binary(op: BinOp, ty: Ty, lhs: i64, rhs: i64, checked: bool) -> Result<i64, String> {
+    let wrapped = |value: i128| match ty {
+        Ty::I32 => value as i32 as i64,
+        Ty::I64 => value as i64,
+    };
+    let (min, max) = match ty {
+        Ty::I32 => (i32::MIN as i128, i32::MAX as i128),
+        Ty::I64 => (i64::MIN as i128, i64::MAX as i128),
+    };
+    let (lhs, rhs) = (lhs as i128, rhs as i128);
+    let exact = match op {
+        BinOp::Add => lhs + rhs,
+        BinOp::Sub => lhs - rhs,
+        BinOp::Mul => lhs * rhs,
+        BinOp::Div | BinOp::Rem => {
+            let operation = if op == BinOp::Div {
+                "division"
+            } else {
+                "remainder"
+            };
+            if rhs == 0 {
+                return Err(format!("{} by zero", operation));
+            }
+            if lhs == min && rhs == -1 {
+                return Err(format!("integer overflow in {}", operation));
+            }
+            if op == BinOp::Div {
+                lhs / rhs
+            } else {
+                lhs % rhs
+            }
+        }
+        BinOp::Lt => return Ok((lhs < rhs) as i64),
+        BinOp::Gt => return Ok((lhs > rhs) as i64),
+    };
+    if checked && !(min..=max).contains(&exact) {
+        return Err("integer overflow".to_string());
+    }
+    Ok(wrapped(exact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_program;
+
+    #[test]
+    fn test_loop_and_call() {
+        let program = parse_program(
+            "function double(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = checked add %0, %0 @ 1:1\n    \
+                 return %1\n\
+             }\n\
+             function main() {\n\
+             bb0:\n    \
+                 %0: i64 = const 0\n    \
+                 %1: i64 = const 3\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %2: i64 = phi [bb0: %0], [bb2: %5]\n    \
+                 %3: i32 = lt %2, %1 @ 1:1\n    \
+                 branch %3, bb2, bb3\n\
+             bb2:\n    \
+                 %4: i64 = call double(%2)\n    \
+                 print %4\n    \
+                 %5: i64 = checked add %2, %1 @ 1:1\n    \
+                 jump bb1\n\
+             bb3:\n    \
+                 return\n\
+             }",
+        )
+        .expect("Failed to parse MIR");
+        let execution = interpret(&program, 1000).unwrap();
+        assert_eq!(execution.output, "0\n");
+        assert_eq!(
+            interpret(&program, 10),
+            Err("Exceeded 10 steps".to_string())
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        assert_eq!(
+            binary(BinOp::Add, Ty::I32, i32::MAX as i64, 1, false),
+            Ok(i32::MIN as i64)
+        );
+        assert_eq!(
+            binary(BinOp::Add, Ty::I32, i32::MAX as i64, 1, true),
+            Err("integer overflow".to_string())
+        );
+        assert_eq!(
+            binary(BinOp::Rem, Ty::I64, 1, 0, false),
+            Err("remainder by zero".to_string())
+        );
+        assert_eq!(
+            binary(BinOp::Div, Ty::I64, i64::MIN, -1, false),
+            Err("integer overflow in division".to_string())
+        );
+    }
+}
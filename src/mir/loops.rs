@@ -0,0 +1,213 @@
+use crate::mir::{Block, BlockId, Function, Inst, InstKind, Terminator, Value};
+use std::collections::HashSet;
+
+// CFG の自然ループ
+// ヘッダへ戻る辺 (ヘッダが支配するブロックからの辺) ごとに、戻り辺の元からヘッダを通らずに
+// 逆にたどれるブロックをループとする。ヘッダが同じループは1つにまとめる
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loop {
+    pub header: BlockId,
+    // ヘッダを含むループの中のブロック
+    pub blocks: HashSet<BlockId>,
+    // ヘッダへ戻る辺の元
+    pub latches: Vec<BlockId>,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.contains(&block)
+    }
+}
+
+// 内側のループが先に来る
+pub fn find_loops(function: &Function) -> Vec<Loop> {
+    let doms = function.dominators();
+    let preds = function.predecessors();
+    let reachable = function.reachable_blocks();
+    let mut loops: Vec<Loop> = Vec::new();
+    for (i, block) in function.blocks.iter().enumerate() {
+        let latch = BlockId(i);
+        for header in block.terminator.successors() {
+            if !doms[i].contains(&header) {
+                continue;
+            }
+            let index = match loops.iter().position(|lp| lp.header == header) {
+                Some(index) => index,
+                None => {
+                    loops.push(Loop {
+                        header,
+                        blocks: HashSet::from([header]),
+                        latches: Vec::new(),
+                    });
+                    loops.len() - 1
+                }
+            };
+            let lp = &mut loops[index];
+            lp.latches.push(latch);
+            let mut stack = vec![latch];
+            while let Some(block) = stack.pop() {
+                if lp.blocks.insert(block) {
+                    stack.extend(
+                        preds[block.0]
+                            .iter()
+                            .filter(|pred| reachable.contains(pred)),
+                    );
+                }
+            }
+        }
+    }
+    loops.sort_by_key(|lp| lp.blocks.len());
+    loops
+}
+
+// 各ブロックを含むループの数
+pub fn loop_depths(function: &Function) -> Vec<usize> {
+    let mut depths = vec![0; function.blocks.len()];
+    for lp in find_loops(function) {
+        for block in lp.blocks {
+            depths[block.0] += 1;
+        }
+    }
+    depths
+}
+
+// ループの前に1回だけ通るブロック (プリヘッダ) を返す。なければ作る
+// プリヘッダはループの外からヘッダへ入る唯一の辺の元で、後続はヘッダだけ
+// 外からの合流元が複数あれば、ヘッダの phi のうち外から来る分をプリヘッダの phi に移す
+// ヘッダが入口のブロックなら作れないので None
+pub fn preheader(function: &mut Function, lp: &Loop) -> Option<BlockId> {
+    if lp.header == BlockId(0) {
+        return None;
+    }
+    let outside: Vec<BlockId> = function.predecessors()[lp.header.0]
+        .iter()
+        .filter(|pred| !lp.contains(**pred))
+        .copied()
+        .collect();
+    if let [pred] = outside[..] {
+        if function.block(pred).terminator == Terminator::Jump(lp.header) {
+            return Some(pred);
+        }
+    }
+
+    let preheader = BlockId(function.blocks.len());
+    let mut insts = Vec::new();
+    for i in 0..function.blocks[lp.header.0].insts.len() {
+        let inst = &function.blocks[lp.header.0].insts[i];
+        let (Some(result), InstKind::Phi(incoming)) = (inst.result, &inst.kind) else {
+            continue;
+        };
+        let (from_outside, from_inside): (Vec<_>, Vec<_>) = incoming
+            .iter()
+            .copied()
+            .partition(|(pred, _)| !lp.contains(*pred));
+        let value = match from_outside[..] {
+            [(_, value)] => value,
+            _ => {
+                let value = Value(function.values.len());
+                function.values.push(function.value_type(result));
+                insts.push(Inst {
+                    result: Some(value),
+                    kind: InstKind::Phi(from_outside),
+                });
+                value
+            }
+        };
+        let mut incoming = from_inside;
+        incoming.push((preheader, value));
+        function.blocks[lp.header.0].insts[i].kind = InstKind::Phi(incoming);
+    }
+    for pred in outside {
+        match &mut function.blocks[pred.0].terminator {
+            Terminator::Jump(target) => *target = preheader,
+            Terminator::Branch { then, else_, .. } => {
+                for target in [then, else_] {
+                    if *target == lp.header {
+                        *target = preheader;
+                    }
+                }
+            }
+            Terminator::Return(_) | Terminator::TailCall { .. } | Terminator::Unreachable => {}
+        }
+    }
+    function.blocks.push(Block {
+        insts,
+        terminator: Terminator::Jump(lp.header),
+    });
+    Some(preheader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    #[test]
+    fn test_nested_loops() {
+        // bb1 が外側、bb2 が内側のループのヘッダ
+        let function = parse_function(
+            "function f(%0: i64) {\n\
+             bb0:\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 branch %0, bb2, bb4\n\
+             bb2:\n    \
+                 branch %0, bb3, bb1\n\
+             bb3:\n    \
+                 jump bb2\n\
+             bb4:\n    \
+                 return\n\
+             }",
+        )
+        .expect("Failed to parse MIR");
+        let loops = find_loops(&function);
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].header, BlockId(2));
+        assert_eq!(loops[0].blocks, HashSet::from([BlockId(2), BlockId(3)]));
+        assert_eq!(loops[1].header, BlockId(1));
+        assert_eq!(loops[1].latches, vec![BlockId(2)]);
+        assert_eq!(loop_depths(&function), vec![0, 1, 2, 2, 0]);
+    }
+
+    #[test]
+    fn test_create_preheader() {
+        // ループへは bb0 と bb1 の2か所から入る
+        let mut function = parse_function(
+            "function f(%0: i64) {\n\
+             bb0:\n    \
+                 %1: i64 = const 1\n    \
+                 branch %0, bb1, bb2\n\
+             bb1:\n    \
+                 jump bb2\n\
+             bb2:\n    \
+                 %2: i64 = phi [bb0: %1], [bb1: %0], [bb2: %2]\n    \
+                 branch %2, bb2, bb3\n\
+             bb3:\n    \
+                 return\n\
+             }",
+        )
+        .expect("Failed to parse MIR");
+        let lp = find_loops(&function).remove(0);
+        assert_eq!(preheader(&mut function, &lp), Some(BlockId(4)));
+        assert_eq!(
+            function.to_string(),
+            "function f(%0: i64) {\n\
+             bb0:\n    \
+                 %1: i64 = const 1\n    \
+                 branch %0, bb1, bb4\n\
+             bb1:\n    \
+                 jump bb4\n\
+             bb2:\n    \
+                 %2: i64 = phi [bb2: %2], [bb4: %3]\n    \
+                 branch %2, bb2, bb3\n\
+             bb3:\n    \
+                 return\n\
+             bb4:\n    \
+                 %3: i64 = phi [bb0: %1], [bb1: %0]\n    \
+                 jump bb2\n\
+             }\n"
+        );
+        let lp = find_loops(&function).remove(0);
+        assert_eq!(preheader(&mut function, &lp), Some(BlockId(4)));
+    }
+}
@@ -0,0 +1,899 @@
+use crate::analysis::resolve::SymbolId;
+use crate::hir;
+use crate::mir::{
+    BinOp, Block, BlockId, Function, Global, GlobalId, Inst, InstKind, Program, Terminator, Ty,
+    Value,
+};
+use crate::parser::span::Span;
+use std::collections::{HashMap, HashSet};
+
+// HIR を MIR に変換する
+// 関数の中の変数は SSA の値にする (Braun らの方法で、ブロックを作りながら phi を置く)
+// トップレベルの変数は関数からも見えるのでグローバルにする
+pub fn lower_program(program: &hir::Program) -> Result<Program, String> {
+    let mut globals = Vec::new();
+    let mut global_ids = HashMap::new();
+    let mut names = HashSet::new();
+    for item in &program.items {
+        if let hir::Item::Stmt(hir::Stmt {
+            kind:
+                hir::StmtKind::Let {
+                    symbol,
+                    name,
+                    ty,
+                    value,
+                },
+            span,
+        }) = item
+        {
+            let ty = Ty::from_type(ty).ok_or_else(|| unsupported_type(*span, &ty.to_string()))?;
+            // 同じ名前のグローバルが複数あれば2つめ以降にシンボル番号を付ける
+            let mut unique = name.clone();
+            if !names.insert(unique.clone()) {
+                unique = format!("{}_{}", name, symbol.0);
+                names.insert(unique.clone());
+            }
+            // リテラルの初期値は .data に埋め込む
+            let init = match value {
+                Some(hir::Expr {
+                    kind: hir::ExprKind::Int(i),
+                    ..
+                }) => *i,
+                _ => 0,
+            };
+            global_ids.insert(*symbol, GlobalId(globals.len()));
+            globals.push(Global {
+                name: unique,
+                ty,
+                init,
+            });
+        }
+    }
+
+    let mut functions = Vec::new();
+    let mut stmts = Vec::new();
+    for item in &program.items {
+        match item {
+            hir::Item::Function(function) => {
+                functions.push(lower_function(function, &global_ids)?);
+            }
+            hir::Item::Stmt(stmt) => stmts.push(stmt),
+        }
+    }
+
+    let mut builder = Builder::new("main", None, &global_ids);
+    for stmt in stmts {
+        match &stmt.kind {
+            // 初期値を .data に埋め込んだグローバルは代入しなくてよい
+            hir::StmtKind::Let {
+                value:
+                    Some(hir::Expr {
+                        kind: hir::ExprKind::Int(_),
+                        ..
+                    }),
+                ..
+            } => {}
+            _ => builder.stmt(stmt)?,
+        }
+    }
+    builder.terminate(Terminator::Return(None));
+    Ok(Program {
+        globals,
+        functions,
+        main: builder.finish(),
+    })
+}
+
+fn lower_function(
+    function: &hir::Function,
+    globals: &HashMap<SymbolId, GlobalId>,
+) -> Result<Function, String> {
+    let return_type = function
+        .return_type
+        .as_ref()
+        .map(|ty| Ty::from_type(ty).ok_or_else(|| unsupported_type(function.span, &ty.to_string())))
+        .transpose()?;
+    let mut builder = Builder::new(&function.name, return_type, globals);
+    builder.function.inline = function.inline;
+    for param in &function.params {
+        let ty = Ty::from_type(&param.ty)
+            .ok_or_else(|| unsupported_type(param.span, &param.ty.to_string()))?;
+        let value = builder.new_value(ty);
+        builder.function.params.push(value);
+        builder.write_variable(param.symbol, BlockId(0), value);
+    }
+    let value = builder.block(&function.body)?;
+    let value = if return_type.is_some() { value } else { None };
+    builder.terminate(Terminator::Return(value));
+    Ok(builder.finish())
+}
+
+fn unsupported_type(span: Span, ty: &str) -> String {
+    format!(
+        "{}: Values of type '{}' are not supported by the code generator",
+        span, ty
+    )
+}
+
+struct Builder<'a> {
+    function: Function,
+    globals: &'a HashMap<SymbolId, GlobalId>,
+    // 終端命令を設定済みのブロック
+    terminated: Vec<bool>,
+    preds: Vec<Vec<BlockId>>,
+    // 合流元がすべて分かったブロック
+    sealed: HashSet<BlockId>,
+    current: BlockId,
+    // ブロックの終わりでの変数の値
+    defs: HashMap<(SymbolId, BlockId), Value>,
+    // 封じる前のブロックに置いた、合流元の値がまだ分からない phi
+    incomplete: HashMap<BlockId, Vec<(SymbolId, Value)>>,
+    // 不要になった phi と、その代わりの値
+    aliases: HashMap<Value, Value>,
+    // 囲んでいるループ (ラベル, 条件のブロック, 出口のブロック)。内側が末尾
+    loops: Vec<(Option<String>, BlockId, BlockId)>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(name: &str, return_type: Option<Ty>, globals: &'a HashMap<SymbolId, GlobalId>) -> Self {
+        let mut builder = Builder {
+            function: Function {
+                name: name.to_string(),
+                params: Vec::new(),
+                return_type,
+                values: Vec::new(),
+                blocks: Vec::new(),
+                inline: hir::InlineHint::Default,
+            },
+            globals,
+            terminated: Vec::new(),
+            preds: Vec::new(),
+            sealed: HashSet::new(),
+            current: BlockId(0),
+            defs: HashMap::new(),
+            incomplete: HashMap::new(),
+            aliases: HashMap::new(),
+            loops: Vec::new(),
+        };
+        let entry = builder.new_block();
+        builder.seal(entry);
+        builder
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block {
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        self.terminated.push(false);
+        self.preds.push(Vec::new());
+        BlockId(self.function.blocks.len() - 1)
+    }
+
+    fn new_value(&mut self, ty: Ty) -> Value {
+        self.function.values.push(ty);
+        Value(self.function.values.len() - 1)
+    }
+
+    // 入口以外で合流元のないブロック。ここに置いた命令は実行されない
+    fn is_dead(&self, block: BlockId) -> bool {
+        block != BlockId(0) && self.sealed.contains(&block) && self.preds[block.0].is_empty()
+    }
+
+    fn emit(&mut self, kind: InstKind, ty: Option<Ty>) -> Option<Value> {
+        let result = ty.map(|ty| self.new_value(ty));
+        self.function.blocks[self.current.0]
+            .insts
+            .push(Inst { result, kind });
+        result
+    }
+
+    fn emit_value(&mut self, kind: InstKind, ty: Ty) -> Value {
+        let value = self.new_value(ty);
+        self.function.blocks[self.current.0].insts.push(Inst {
+            result: Some(value),
+            kind,
+        });
+        value
+    }
+
+    // 現在のブロックを終える。return などの後は、続きを合流元のないブロックに置く
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current;
+        if self.terminated[block.0] {
+            return;
+        }
+        if !self.is_dead(block) {
+            for succ in terminator.successors() {
+                self.preds[succ.0].push(block);
+            }
+        }
+        self.function.blocks[block.0].terminator = terminator;
+        self.terminated[block.0] = true;
+    }
+
+    fn terminate_and_continue(&mut self, terminator: Terminator) {
+        self.terminate(terminator);
+        let dead = self.new_block();
+        self.seal(dead);
+        self.current = dead;
+    }
+
+    fn write_variable(&mut self, symbol: SymbolId, block: BlockId, value: Value) {
+        self.defs.insert((symbol, block), value);
+    }
+
+    fn read_variable(&mut self, symbol: SymbolId, ty: Ty, block: BlockId) -> Value {
+        if let Some(value) = self.defs.get(&(symbol, block)) {
+            return self.resolve(*value);
+        }
+        let value = if !self.sealed.contains(&block) {
+            let phi = self.new_phi(block, ty);
+            self.incomplete
+                .entry(block)
+                .or_default()
+                .push((symbol, phi));
+            phi
+        } else if self.preds[block.0].len() == 1 {
+            let pred = self.preds[block.0][0];
+            self.read_variable(symbol, ty, pred)
+        } else if self.preds[block.0].is_empty() {
+            // 実行されないブロック。値は何でもよい
+            let value = self.new_value(ty);
+            self.function.blocks[block.0].insts.insert(
+                0,
+                Inst {
+                    result: Some(value),
+                    kind: InstKind::Const(0),
+                },
+            );
+            value
+        } else {
+            // ループで同じ変数を読んでも止まるように、先に phi を変数の値にしておく
+            let phi = self.new_phi(block, ty);
+            self.write_variable(symbol, block, phi);
+            self.add_phi_operands(symbol, ty, phi, block)
+        };
+        self.write_variable(symbol, block, value);
+        value
+    }
+
+    fn new_phi(&mut self, block: BlockId, ty: Ty) -> Value {
+        let value = self.new_value(ty);
+        let insts = &mut self.function.blocks[block.0].insts;
+        let position = insts
+            .iter()
+            .take_while(|inst| matches!(inst.kind, InstKind::Phi(_)))
+            .count();
+        insts.insert(
+            position,
+            Inst {
+                result: Some(value),
+                kind: InstKind::Phi(Vec::new()),
+            },
+        );
+        value
+    }
+
+    fn add_phi_operands(&mut self, symbol: SymbolId, ty: Ty, phi: Value, block: BlockId) -> Value {
+        let mut incoming = Vec::new();
+        for pred in self.preds[block.0].clone() {
+            incoming.push((pred, self.read_variable(symbol, ty, pred)));
+        }
+        let inst = self.function.blocks[block.0]
+            .insts
+            .iter_mut()
+            .find(|inst| inst.result == Some(phi))
+            .expect("phi must exist");
+        inst.kind = InstKind::Phi(incoming);
+        self.remove_trivial_phi(phi, block)
+    }
+
+    // すべての合流元で同じ値を選ぶ phi は、その値で置き換える
+    fn remove_trivial_phi(&mut self, phi: Value, block: BlockId) -> Value {
+        let insts = &self.function.blocks[block.0].insts;
+        let Some(index) = insts.iter().position(|inst| inst.result == Some(phi)) else {
+            return phi;
+        };
+        let InstKind::Phi(incoming) = &insts[index].kind else {
+            return phi;
+        };
+        let mut same = None;
+        for (_, value) in incoming {
+            let value = self.resolve(*value);
+            if Some(value) == same || value == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(value);
+        }
+        let Some(same) = same else {
+            return phi;
+        };
+        self.function.blocks[block.0].insts.remove(index);
+        self.aliases.insert(phi, same);
+        same
+    }
+
+    fn resolve(&self, mut value: Value) -> Value {
+        while let Some(alias) = self.aliases.get(&value) {
+            value = *alias;
+        }
+        value
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        for (symbol, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            let ty = self.function.value_type(phi);
+            self.add_phi_operands(symbol, ty, phi, block);
+        }
+        self.sealed.insert(block);
+    }
+
+    fn block(&mut self, block: &hir::Block) -> Result<Option<Value>, String> {
+        for stmt in &block.stmts {
+            self.stmt(stmt)?;
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => Ok(None),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &hir::Stmt) -> Result<(), String> {
+        match &stmt.kind {
+            hir::StmtKind::Let {
+                symbol,
+                value: Some(value),
+                ..
+            }
+            | hir::StmtKind::Assign { symbol, value, .. } => {
+                let value = self.value(value)?;
+                self.assign(*symbol, value);
+            }
+            hir::StmtKind::Let { value: None, .. } => {}
+            hir::StmtKind::While {
+                label,
+                condition,
+                body,
+            } => {
+                let header = self.new_block();
+                self.terminate(Terminator::Jump(header));
+                self.current = header;
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                // 条件が定数なら片方にだけ進む。while (1) の出口は break でしか届かない
+                match condition.kind {
+                    hir::ExprKind::Int(c) => {
+                        self.terminate(Terminator::Jump(if c != 0 { body_block } else { exit }));
+                    }
+                    _ => {
+                        let cond = self.value(condition)?;
+                        self.terminate(Terminator::Branch {
+                            cond,
+                            then: body_block,
+                            else_: exit,
+                        });
+                    }
+                }
+                self.seal(body_block);
+                self.current = body_block;
+                self.loops.push((label.clone(), header, exit));
+                let result = self.block(body);
+                self.loops.pop();
+                result?;
+                self.terminate(Terminator::Jump(header));
+                self.seal(header);
+                self.seal(exit);
+                self.current = exit;
+            }
+            hir::StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value)?,
+                    None => None,
+                };
+                self.terminate_and_continue(Terminator::Return(value));
+            }
+            hir::StmtKind::TailCall { name, args, .. } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.value(arg))
+                    .collect::<Result<_, String>>()?;
+                self.terminate_and_continue(Terminator::TailCall {
+                    callee: name.clone(),
+                    args,
+                });
+            }
+            hir::StmtKind::Break(label) => {
+                let (_, exit) = self.loop_target(label, "break")?;
+                self.terminate_and_continue(Terminator::Jump(exit));
+            }
+            hir::StmtKind::Continue(label) => {
+                let (header, _) = self.loop_target(label, "continue")?;
+                self.terminate_and_continue(Terminator::Jump(header));
+            }
+            hir::StmtKind::Print(hir::Expr {
+                kind: hir::ExprKind::String(s),
+                ..
+            }) => {
+                self.emit(InstKind::PrintStr(s.clone()), None);
+            }
+            hir::StmtKind::Print(expr) => {
+                let value = self.value(expr)?;
+                self.emit(InstKind::Print(value), None);
+            }
+            hir::StmtKind::Expr(expr) => {
+                self.expr(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, symbol: SymbolId, value: Value) {
+        match self.globals.get(&symbol) {
+            Some(global) => {
+                self.emit(InstKind::Store(*global, value), None);
+            }
+            None => self.write_variable(symbol, self.current, value),
+        }
+    }
+
+    // break/continue のジャンプ先 (条件のブロック, 出口のブロック) を探す
+    fn loop_target(
+        &self,
+        label: &Option<String>,
+        keyword: &str,
+    ) -> Result<(BlockId, BlockId), String> {
+        let target = match label {
+            Some(name) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|(loop_label, _, _)| loop_label.as_deref() == Some(name.as_str())),
+            None => self.loops.last(),
+        };
+        match target {
+            Some((_, header, exit)) => Ok((*header, *exit)),
+            None => Err(format!("'{}' outside of a loop", keyword)),
+        }
+    }
+
+    // 値を持つ式
+    fn value(&mut self, expr: &hir::Expr) -> Result<Value, String> {
+        match self.expr(expr)? {
+            Some(value) => Ok(value),
+            // return などで抜けるブロックは値を持たない。続きは実行されないので仮の値を置く
+            None if self.is_dead(self.current) => {
+                let ty = self.ty(expr)?;
+                Ok(self.emit_value(InstKind::Const(0), ty))
+            }
+            None => Err(format!(
+                "{}: Expected an expression with a value",
+                expr.span
+            )),
+        }
+    }
+
+    fn ty(&self, expr: &hir::Expr) -> Result<Ty, String> {
+        match &expr.ty {
+            Some(ty) => {
+                Ty::from_type(ty).ok_or_else(|| unsupported_type(expr.span, &ty.to_string()))
+            }
+            None => Err(format!(
+                "{}: Expected an expression with a value",
+                expr.span
+            )),
+        }
+    }
+
+    fn expr(&mut self, expr: &hir::Expr) -> Result<Option<Value>, String> {
+        let value = match &expr.kind {
+            hir::ExprKind::Int(i) => {
+                let ty = self.ty(expr)?;
+                self.emit_value(InstKind::Const(*i), ty)
+            }
+            hir::ExprKind::String(_) => {
+                return Err(unsupported_type(expr.span, "string"));
+            }
+            hir::ExprKind::Unit => return Ok(None),
+            hir::ExprKind::Variable { symbol, .. } => {
+                let ty = self.ty(expr)?;
+                match self.globals.get(symbol) {
+                    Some(global) => self.emit_value(InstKind::Load(*global), ty),
+                    None => self.read_variable(*symbol, ty, self.current),
+                }
+            }
+            hir::ExprKind::Binary {
+                op,
+                left,
+                right,
+                checked,
+            } => {
+                let lhs = self.value(left)?;
+                let rhs = self.value(right)?;
+                let ty = self.ty(expr)?;
+                let op = BinOp::from_op(op);
+                self.emit_value(
+                    InstKind::Binary {
+                        op,
+                        lhs,
+                        rhs,
+                        checked: *checked && op.can_overflow(),
+                        span: expr.span,
+                    },
+                    ty,
+                )
+            }
+            hir::ExprKind::Call { name, args, .. } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.value(arg))
+                    .collect::<Result<_, String>>()?;
+                let ty = match &expr.ty {
+                    Some(_) => Some(self.ty(expr)?),
+                    None => None,
+                };
+                let call = InstKind::Call {
+                    callee: name.clone(),
+                    args,
+                };
+                return Ok(self.emit(call, ty));
+            }
+            hir::ExprKind::Widen(value) => {
+                let value = self.value(value)?;
+                self.emit_value(InstKind::Widen(value), Ty::I64)
+            }
+            hir::ExprKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => return self.if_expr(expr, condition, consequence, alternative.as_ref()),
+            hir::ExprKind::Block(block) => return self.block(block),
+        };
+        Ok(Some(value))
+    }
+
+    fn if_expr(
+        &mut self,
+        expr: &hir::Expr,
+        condition: &hir::Expr,
+        consequence: &hir::Block,
+        alternative: Option<&hir::Block>,
+    ) -> Result<Option<Value>, String> {
+        // 条件が定数なら届く枝だけを下ろす。届かない枝は HIR で空にしてあり、値を持たない
+        if let hir::ExprKind::Int(c) = condition.kind {
+            let value = match if c != 0 {
+                Some(consequence)
+            } else {
+                alternative
+            } {
+                Some(body) => self.block(body)?,
+                None => None,
+            };
+            return Ok(value.filter(|_| expr.ty.is_some()));
+        }
+        let cond = self.value(condition)?;
+        let then = self.new_block();
+        let else_ = self.new_block();
+        let join = self.new_block();
+        self.terminate(Terminator::Branch { cond, then, else_ });
+        self.seal(then);
+        self.seal(else_);
+
+        // 合流先に届く枝の (ブロック, 値)
+        let mut incoming = Vec::new();
+        for (block, body) in [(then, Some(consequence)), (else_, alternative)] {
+            self.current = block;
+            let value = match body {
+                Some(body) => self.block(body)?,
+                None => None,
+            };
+            if !self.is_dead(self.current) && !self.terminated[self.current.0] {
+                incoming.push((self.current, value));
+            }
+            self.terminate(Terminator::Jump(join));
+        }
+        self.seal(join);
+        self.current = join;
+
+        if expr.ty.is_none() {
+            return Ok(None);
+        }
+        let incoming: Vec<(BlockId, Value)> = incoming
+            .into_iter()
+            .filter_map(|(block, value)| Some((block, value?)))
+            .collect();
+        match incoming.as_slice() {
+            [] => Ok(None),
+            [(_, value)] => Ok(Some(*value)),
+            _ => {
+                let ty = self.ty(expr)?;
+                let phi = self.new_phi(join, ty);
+                let inst = self.function.blocks[join.0]
+                    .insts
+                    .iter_mut()
+                    .find(|inst| inst.result == Some(phi))
+                    .expect("phi must exist");
+                inst.kind = InstKind::Phi(incoming);
+                Ok(Some(self.remove_trivial_phi(phi, join)))
+            }
+        }
+    }
+
+    // 不要な phi の置き換えを反映し、到達しないブロックを除いて番号を詰める
+    fn finish(mut self) -> Function {
+        let aliases = std::mem::take(&mut self.aliases);
+        let resolve = |mut value: Value| {
+            while let Some(alias) = aliases.get(&value) {
+                value = *alias;
+            }
+            value
+        };
+        for block in &mut self.function.blocks {
+            for inst in &mut block.insts {
+                map_operands(&mut inst.kind, resolve);
+            }
+            map_terminator_operands(&mut block.terminator, resolve);
+        }
+        remove_unreachable_blocks(&mut self.function);
+        renumber_values(&mut self.function);
+        self.function
+    }
+}
+
+pub(crate) fn map_operands(kind: &mut InstKind, mut f: impl FnMut(Value) -> Value) {
+    match kind {
+        InstKind::Const(_) | InstKind::Load(_) | InstKind::PrintStr(_) => {}
+        InstKind::Copy(value)
+        | InstKind::Widen(value)
+        | InstKind::Store(_, value)
+        | InstKind::Print(value) => *value = f(*value),
+        InstKind::Binary { lhs, rhs, .. } => {
+            *lhs = f(*lhs);
+            *rhs = f(*rhs);
+        }
+        InstKind::Phi(incoming) => {
+            for (_, value) in incoming {
+                *value = f(*value);
+            }
+        }
+        InstKind::Call { args, .. } => {
+            for arg in args {
+                *arg = f(*arg);
+            }
+        }
+    }
+}
+
+pub(crate) fn map_terminator_operands(
+    terminator: &mut Terminator,
+    mut f: impl FnMut(Value) -> Value,
+) {
+    match terminator {
+        Terminator::Branch { cond, .. } => *cond = f(*cond),
+        Terminator::Return(Some(value)) => *value = f(*value),
+        Terminator::TailCall { args, .. } => {
+            for arg in args {
+                *arg = f(*arg);
+            }
+        }
+        _ => {}
+    }
+}
+
+// 入口から届かないブロックを除き、残ったブロックの番号を詰める
+pub(crate) fn remove_unreachable_blocks(function: &mut Function) {
+    let mut reachable = vec![false; function.blocks.len()];
+    let mut stack = vec![BlockId(0)];
+    while let Some(block) = stack.pop() {
+        if std::mem::replace(&mut reachable[block.0], true) {
+            continue;
+        }
+        stack.extend(function.block(block).terminator.successors());
+    }
+    let mut mapping = vec![None; function.blocks.len()];
+    let mut next = 0;
+    for (i, reachable) in reachable.iter().enumerate() {
+        if *reachable {
+            mapping[i] = Some(BlockId(next));
+            next += 1;
+        }
+    }
+    let blocks = std::mem::take(&mut function.blocks);
+    for (i, mut block) in blocks.into_iter().enumerate() {
+        if !reachable[i] {
+            continue;
+        }
+        for inst in &mut block.insts {
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                incoming.retain(|(pred, _)| reachable[pred.0]);
+                for (pred, _) in incoming {
+                    *pred = mapping[pred.0].expect("reachable block");
+                }
+            }
+        }
+        match &mut block.terminator {
+            Terminator::Jump(target) => *target = mapping[target.0].expect("reachable block"),
+            Terminator::Branch { then, else_, .. } => {
+                *then = mapping[then.0].expect("reachable block");
+                *else_ = mapping[else_.0].expect("reachable block");
+            }
+            Terminator::Return(_) | Terminator::TailCall { .. } | Terminator::Unreachable => {}
+        }
+        function.blocks.push(block);
+    }
+}
+
+// 値の番号を、引数から定義の順に振り直す
+pub(crate) fn renumber_values(function: &mut Function) {
+    let mut mapping = HashMap::new();
+    let mut values = Vec::new();
+    let mut define = |value: Value, values: &mut Vec<Ty>, types: &[Ty]| {
+        mapping.insert(value, Value(values.len()));
+        values.push(types[value.0]);
+    };
+    for param in &function.params {
+        define(*param, &mut values, &function.values);
+    }
+    for block in &function.blocks {
+        for inst in &block.insts {
+            if let Some(result) = inst.result {
+                define(result, &mut values, &function.values);
+            }
+        }
+    }
+    let map = |value: Value| mapping.get(&value).copied().unwrap_or(value);
+    for param in &mut function.params {
+        *param = map(*param);
+    }
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            inst.result = inst.result.map(map);
+            map_operands(&mut inst.kind, map);
+        }
+        map_terminator_operands(&mut block.terminator, map);
+    }
+    function.values = values;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::lower_to_mir as lower;
+
+    #[test]
+    fn test_function_body() {
+        let program = lower("function add(x:i64, y:i64) -> i64 { x + y }");
+        assert_eq!(
+            program.functions[0].to_string(),
+            "function add(%0: i64, %1: i64) -> i64 {\n\
+             bb0:\n    \
+                 %2: i64 = checked add %0, %1 @ 1:37\n    \
+                 return %2\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_loop_variables_become_phis() {
+        let program =
+            lower("function f(n:i64) -> i64 { let mut i:i64 = 0; while (i < n) { i += 1; } i }");
+        assert_eq!(
+            program.functions[0].to_string(),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = const 0\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %2: i64 = phi [bb0: %1], [bb2: %5]\n    \
+                 %3: i32 = lt %2, %0 @ 1:54\n    \
+                 branch %3, bb2, bb3\n\
+             bb2:\n    \
+                 %4: i64 = const 1\n    \
+                 %5: i64 = checked add %2, %4 @ 1:63\n    \
+                 jump bb1\n\
+             bb3:\n    \
+                 return %2\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_if_expression_value() {
+        let program = lower("function f(x:i32) -> i32 { if (x < 0) { 0 } else { x } }");
+        let text = program.functions[0].to_string();
+        assert!(text.contains("phi [bb1: %3], [bb2: %0]"), "{}", text);
+    }
+
+    #[test]
+    fn test_unchanged_variable_needs_no_phi() {
+        let program = lower(
+            "function f(x:i32) -> i32 { let y:i32 = 1; if (x < 0) { print(x); } else { } y }",
+        );
+        let text = program.functions[0].to_string();
+        assert!(!text.contains("phi"), "{}", text);
+    }
+
+    #[test]
+    fn test_globals_live_in_memory() {
+        let program = lower("let mut x:i64 = 1; let y:i64 = x; x += y; print(x);");
+        assert_eq!(
+            program.to_string(),
+            "global @x: i64 = 1\n\
+             global @y: i64 = 0\n\
+             function main() {\n\
+             bb0:\n    \
+                 %0: i64 = load @x\n    \
+                 store @y, %0\n    \
+                 %1: i64 = load @x\n    \
+                 %2: i64 = load @y\n    \
+                 %3: i64 = checked add %1, %2 @ 1:35\n    \
+                 store @x, %3\n    \
+                 %4: i64 = load @x\n    \
+                 print %4\n    \
+                 return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_early_return_drops_dead_blocks() {
+        let program = lower(
+            "function f(x:i32) -> i32 { if (x < 0) { return 0; } else { } let y:i32 = x; y }",
+        );
+        let function = &program.functions[0];
+        let text = function.to_string();
+        assert_eq!(function.blocks.len(), 4, "{}", text);
+        assert!(!text.contains("unreachable"), "{}", text);
+    }
+
+    #[test]
+    fn test_value_after_return() {
+        // print の引数は return で抜けるので、print も 2 も実行されない
+        let program = lower("function f(a:i64) -> i64 { print({ return a; 1 }); 2 }");
+        assert_eq!(
+            program.functions[0].to_string(),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 return %0\n\
+             }\n"
+        );
+        let program = lower("function f(a:i64) -> i64 { let b:i64 = 1 + { return a; 2 }; b }");
+        assert_eq!(program.functions[0].blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_constant_conditions() {
+        // 届かない枝の値は使わない
+        let program = lower("function f() -> i64 { if (1 < 2) { 3 } else { 4 } }");
+        assert_eq!(
+            program.functions[0].to_string(),
+            "function f() -> i64 {\n\
+             bb0:\n    \
+                 %0: i64 = const 3\n    \
+                 return %0\n\
+             }\n"
+        );
+        let program = lower("let x:i64 = if (2 < 1) { 3 } else { 4 }; print(x);");
+        let text = program.main.to_string();
+        assert!(
+            text.contains("const 4") && !text.contains("const 3"),
+            "{}",
+            text
+        );
+        // while (1) の出口には届かないので、値のない return を置かない
+        let program = lower("function f(n:i64) -> i64 { while (1 < 2) { return n; } }");
+        assert_eq!(
+            program.functions[0].to_string(),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 jump bb2\n\
+             bb2:\n    \
+                 return %0\n\
+             }\n"
+        );
+    }
+}
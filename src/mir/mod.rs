@@ -0,0 +1,452 @@
+// SSA 形式の中間表現 (MIR)
+// 関数は基本ブロックの並びで、ブロックは命令の列と終端命令からなる
+// - 値 (%n) は型付きの仮想レジスタで、定義は一度だけ。合流点では phi で選ぶ
+// - 関数の中の変数と引数は値になり、トップレベルの変数はグローバル (@name) としてメモリに置く
+// - 命令は三番地形式で、オペランドはすべて値
+use crate::hir::InlineHint;
+use crate::parser::ast::{Op, Type};
+use crate::parser::span::Span;
+use std::collections::HashSet;
+use std::fmt;
+
+pub mod interp;
+pub mod loops;
+pub mod lower;
+pub mod opt;
+pub mod parse;
+pub mod verify;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+    // トップレベルの文
+    pub main: Function,
+}
+
+// トップレベルの変数。init は .data に置く初期値
+#[derive(Clone, Debug, PartialEq)]
+pub struct Global {
+    // 同じ名前のグローバルが複数あれば2つめ以降に番号が付く
+    pub name: String,
+    pub ty: Ty,
+    pub init: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GlobalId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ty {
+    I32,
+    I64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Value>,
+    pub return_type: Option<Ty>,
+    // 値ごとの型。Value(n) の型は values[n]
+    pub values: Vec<Ty>,
+    // blocks[0] が入口
+    pub blocks: Vec<Block>,
+    pub inline: InlineHint,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    // phi はブロックの先頭にまとめて置く
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inst {
+    pub result: Option<Value>,
+    pub kind: InstKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstKind {
+    Const(i64),
+    Copy(Value),
+    // 比較の結果は i32 の 0 か 1
+    // checked なら加算、減算、乗算の桁あふれを実行時エラーにする。除算の検査は常に行う
+    // span は実行時エラーで表示する位置
+    Binary {
+        op: BinOp,
+        lhs: Value,
+        rhs: Value,
+        checked: bool,
+        span: Span,
+    },
+    // i32 を i64 に符号拡張する
+    Widen(Value),
+    // 合流元のブロックごとの値
+    Phi(Vec<(BlockId, Value)>),
+    Load(GlobalId),
+    Store(GlobalId, Value),
+    Call {
+        callee: String,
+        args: Vec<Value>,
+    },
+    Print(Value),
+    PrintStr(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Gt,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    // cond が 0 でなければ then、0 なら else
+    Branch {
+        cond: Value,
+        then: BlockId,
+        else_: BlockId,
+    },
+    Return(Option<Value>),
+    // 呼び出し元のフレームを使い回して callee へ飛び、callee の戻り値をそのまま返す
+    // 引数は呼び出し元の引数の場所に書き直すので、呼び出し元の引数より多くは渡せない
+    TailCall {
+        callee: String,
+        args: Vec<Value>,
+    },
+    Unreachable,
+}
+
+impl Function {
+    pub fn value_type(&self, value: Value) -> Ty {
+        self.values[value.0]
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    // 各ブロックの合流元のブロック
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                if !preds[succ.0].contains(&BlockId(i)) {
+                    preds[succ.0].push(BlockId(i));
+                }
+            }
+        }
+        preds
+    }
+
+    // 各ブロックを支配するブロックの集合。入口から届かないブロックは空
+    pub fn dominators(&self) -> Vec<HashSet<BlockId>> {
+        let preds = self.predecessors();
+        let all: HashSet<BlockId> = (0..self.blocks.len()).map(BlockId).collect();
+        let reachable = self.reachable_blocks();
+        let mut doms: Vec<HashSet<BlockId>> = (0..self.blocks.len())
+            .map(|i| {
+                if i == 0 {
+                    HashSet::from([BlockId(0)])
+                } else if reachable.contains(&BlockId(i)) {
+                    all.clone()
+                } else {
+                    HashSet::new()
+                }
+            })
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 1..self.blocks.len() {
+                if !reachable.contains(&BlockId(i)) {
+                    continue;
+                }
+                let mut dom: Option<HashSet<BlockId>> = None;
+                for pred in preds[i].iter().filter(|pred| reachable.contains(pred)) {
+                    dom = Some(match dom {
+                        Some(dom) => dom.intersection(&doms[pred.0]).copied().collect(),
+                        None => doms[pred.0].clone(),
+                    });
+                }
+                let mut dom = dom.unwrap_or_default();
+                dom.insert(BlockId(i));
+                if dom != doms[i] {
+                    doms[i] = dom;
+                    changed = true;
+                }
+            }
+        }
+        doms
+    }
+
+    // 入口から届くブロック
+    pub fn reachable_blocks(&self) -> HashSet<BlockId> {
+        let mut reachable = HashSet::new();
+        let mut stack = vec![BlockId(0)];
+        while let Some(block) = stack.pop() {
+            if reachable.insert(block) {
+                stack.extend(self.block(block).terminator.successors());
+            }
+        }
+        reachable
+    }
+}
+
+impl Program {
+    pub fn global(&self, id: GlobalId) -> &Global {
+        &self.globals[id.0]
+    }
+}
+
+impl Ty {
+    pub fn from_type(ty: &Type) -> Option<Ty> {
+        if ty.is_named("i32") {
+            Some(Ty::I32)
+        } else if ty.is_named("i64") {
+            Some(Ty::I64)
+        } else {
+            None
+        }
+    }
+}
+
+impl BinOp {
+    pub fn from_op(op: &Op) -> BinOp {
+        match op {
+            Op::Add => BinOp::Add,
+            Op::Subtract => BinOp::Sub,
+            Op::Multiply => BinOp::Mul,
+            Op::Divide => BinOp::Div,
+            Op::Modulo => BinOp::Rem,
+            Op::LessThan => BinOp::Lt,
+            Op::GreaterThan => BinOp::Gt,
+        }
+    }
+
+    // 桁あふれを検査できる演算。除算の検査は checked によらない
+    pub fn can_overflow(self) -> bool {
+        matches!(self, BinOp::Add | BinOp::Sub | BinOp::Mul)
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinOp::Lt | BinOp::Gt)
+    }
+
+    pub fn from_name(name: &str) -> Option<BinOp> {
+        [
+            BinOp::Add,
+            BinOp::Sub,
+            BinOp::Mul,
+            BinOp::Div,
+            BinOp::Rem,
+            BinOp::Lt,
+            BinOp::Gt,
+        ]
+        .into_iter()
+        .find(|op| op.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Rem => "rem",
+            BinOp::Lt => "lt",
+            BinOp::Gt => "gt",
+        }
+    }
+}
+
+impl InstKind {
+    // 命令が読む値
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            InstKind::Const(_) | InstKind::Load(_) | InstKind::PrintStr(_) => Vec::new(),
+            InstKind::Copy(value)
+            | InstKind::Widen(value)
+            | InstKind::Store(_, value)
+            | InstKind::Print(value) => vec![*value],
+            InstKind::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            InstKind::Phi(incoming) => incoming.iter().map(|(_, value)| *value).collect(),
+            InstKind::Call { args, .. } => args.clone(),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then, else_, .. } => vec![*then, *else_],
+            Terminator::Return(_) | Terminator::TailCall { .. } | Terminator::Unreachable => {
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![*cond],
+            Terminator::Return(Some(value)) => vec![*value],
+            Terminator::TailCall { args, .. } => args.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// テキスト形式
+// #[inline]
+// function add(%0: i64, %1: i64) -> i64 {
+// bb0:
+//     %2: i64 = checked add %0, %1 @ 2:5
+//     return %2
+// }
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for global in &self.globals {
+            writeln!(
+                f,
+                "global @{}: {} = {}",
+                global.name, global.ty, global.init
+            )?;
+        }
+        for function in &self.functions {
+            function.fmt_with_globals(f, &self.globals)?;
+        }
+        self.main.fmt_with_globals(f, &self.globals)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with_globals(f, &[])
+    }
+}
+
+impl Function {
+    fn fmt_with_globals(&self, f: &mut fmt::Formatter<'_>, globals: &[Global]) -> fmt::Result {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|param| format!("{}: {}", param, self.value_type(*param)))
+            .collect();
+        match self.inline {
+            InlineHint::Default => {}
+            InlineHint::Always => writeln!(f, "#[inline]")?,
+            InlineHint::Never => writeln!(f, "#[inline(never)]")?,
+        }
+        write!(f, "function {}({})", self.name, params.join(", "))?;
+        if let Some(ty) = self.return_type {
+            write!(f, " -> {}", ty)?;
+        }
+        writeln!(f, " {{")?;
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for inst in &block.insts {
+                write!(f, "    ")?;
+                if let Some(result) = inst.result {
+                    write!(f, "{}: {} = ", result, self.value_type(result))?;
+                }
+                fmt_inst(f, &inst.kind, globals)?;
+                writeln!(f)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn fmt_inst(f: &mut fmt::Formatter<'_>, kind: &InstKind, globals: &[Global]) -> fmt::Result {
+    let global = |id: &GlobalId| match globals.get(id.0) {
+        Some(global) => format!("@{}", global.name),
+        None => format!("@{}", id.0),
+    };
+    match kind {
+        InstKind::Const(value) => write!(f, "const {}", value),
+        InstKind::Copy(value) => write!(f, "copy {}", value),
+        InstKind::Binary {
+            op,
+            lhs,
+            rhs,
+            checked,
+            span,
+        } => {
+            if *checked {
+                write!(f, "checked ")?;
+            }
+            write!(f, "{} {}, {} @ {}", op.name(), lhs, rhs, span)
+        }
+        InstKind::Widen(value) => write!(f, "widen {}", value),
+        InstKind::Phi(incoming) => {
+            let incoming: Vec<String> = incoming
+                .iter()
+                .map(|(block, value)| format!("[{}: {}]", block, value))
+                .collect();
+            write!(f, "phi {}", incoming.join(", "))
+        }
+        InstKind::Load(id) => write!(f, "load {}", global(id)),
+        InstKind::Store(id, value) => write!(f, "store {}, {}", global(id), value),
+        InstKind::Call { callee, args } => {
+            let args: Vec<String> = args.iter().map(Value::to_string).collect();
+            write!(f, "call {}({})", callee, args.join(", "))
+        }
+        InstKind::Print(value) => write!(f, "print {}", value),
+        InstKind::PrintStr(s) => write!(f, "print {:?}", s),
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch { cond, then, else_ } => {
+                write!(f, "branch {}, {}, {}", cond, then, else_)
+            }
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::TailCall { callee, args } => {
+                let args: Vec<String> = args.iter().map(Value::to_string).collect();
+                write!(f, "tail call {}({})", callee, args.join(", "))
+            }
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::I32 => write!(f, "i32"),
+            Ty::I64 => write!(f, "i64"),
+        }
+    }
+}
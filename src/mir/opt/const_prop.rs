@@ -0,0 +1,181 @@
+use crate::mir::opt::constants;
+use crate::mir::{BinOp, BlockId, Function, InstKind, Terminator, Ty};
+
+// 定数の伝播
+// - オペランドがすべて定数の命令を const にする。実行時エラーになる演算はそのまま残す
+// - 条件が定数の分岐をジャンプにし、通らなくなった辺の phi のオペランドを除く
+// 使われなくなった命令は dce、届かなくなったブロックは simplify-cfg が消す
+pub fn propagate_constants(function: &mut Function) -> bool {
+    let mut changed = false;
+    loop {
+        let consts = constants(function);
+        let mut progress = false;
+        for i in 0..function.blocks.len() {
+            for j in 0..function.blocks[i].insts.len() {
+                let inst = &function.blocks[i].insts[j];
+                if inst.result.is_none() {
+                    continue;
+                }
+                let folded = match &inst.kind {
+                    InstKind::Copy(value) | InstKind::Widen(value) => consts.get(value).copied(),
+                    InstKind::Binary {
+                        op,
+                        lhs,
+                        rhs,
+                        checked,
+                        ..
+                    } => match (consts.get(lhs), consts.get(rhs)) {
+                        (Some(l), Some(r)) => {
+                            evaluate(*op, function.value_type(*lhs), *l, *r, *checked)
+                        }
+                        _ => None,
+                    },
+                    // どの合流元からも同じ定数が来る
+                    InstKind::Phi(incoming) => {
+                        let values: Option<Vec<i64>> = incoming
+                            .iter()
+                            .map(|(_, value)| consts.get(value).copied())
+                            .collect();
+                        values.and_then(|values| match values.split_first() {
+                            Some((first, rest)) if rest.iter().all(|v| v == first) => Some(*first),
+                            _ => None,
+                        })
+                    }
+                    _ => None,
+                };
+                if let Some(value) = folded {
+                    function.blocks[i].insts[j].kind = InstKind::Const(value);
+                    progress = true;
+                }
+            }
+
+            if let Terminator::Branch { cond, then, else_ } = function.blocks[i].terminator {
+                let Some(cond) = consts.get(&cond) else {
+                    continue;
+                };
+                let (taken, dropped) = if *cond != 0 {
+                    (then, else_)
+                } else {
+                    (else_, then)
+                };
+                function.blocks[i].terminator = Terminator::Jump(taken);
+                if dropped != taken {
+                    for inst in &mut function.blocks[dropped.0].insts {
+                        if let InstKind::Phi(incoming) = &mut inst.kind {
+                            incoming.retain(|(pred, _)| *pred != BlockId(i));
+                        }
+                    }
+                }
+                progress = true;
+            }
+        }
+        if !progress {
+            break;
+        }
+        changed = true;
+    }
+    changed
+}
+
+// 定数どうしの演算。実行時エラーになるなら None
+fn evaluate(op: BinOp, ty: Ty, lhs: i64, rhs: i64, checked: bool) -> Option<i64> {
+    let (value, overflowed) = match op {
+        BinOp::Add => lhs.overflowing_add(rhs),
+        BinOp::Sub => lhs.overflowing_sub(rhs),
+        BinOp::Mul => lhs.overflowing_mul(rhs),
+        BinOp::Div => (lhs.checked_div(rhs)?, false),
+        BinOp::Rem => (lhs.checked_rem(rhs)?, false),
+        BinOp::Lt => ((lhs < rhs) as i64, false),
+        BinOp::Gt => ((lhs > rhs) as i64, false),
+    };
+    // i32 どうしの演算は i64 では桁あふれしないので、結果が i32 に収まるかを見る
+    let (value, overflowed) = match ty {
+        Ty::I32 => (value as i32 as i64, value as i32 as i64 != value),
+        Ty::I64 => (value, overflowed),
+    };
+    // 除算の桁あふれ (MIN / -1) は常に実行時エラー
+    if overflowed && (checked || !op.can_overflow()) {
+        return None;
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    fn run(text: &str) -> String {
+        let mut function = parse_function(text).expect("Failed to parse MIR");
+        propagate_constants(&mut function);
+        function.to_string()
+    }
+
+    #[test]
+    fn test_fold_arithmetic() {
+        assert_eq!(
+            run("function f() -> i32 {\n\
+                 bb0:\n    \
+                     %0: i32 = const 6\n    \
+                     %1: i32 = const 7\n    \
+                     %2: i32 = checked mul %0, %1 @ 1:1\n    \
+                     %3: i64 = widen %2\n    \
+                     %4: i32 = lt %0, %1 @ 1:1\n    \
+                     return %2\n\
+                 }"),
+            "function f() -> i32 {\n\
+             bb0:\n    \
+                 %0: i32 = const 6\n    \
+                 %1: i32 = const 7\n    \
+                 %2: i32 = const 42\n    \
+                 %3: i64 = const 42\n    \
+                 %4: i32 = const 1\n    \
+                 return %2\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_keep_runtime_errors() {
+        // 桁あふれする checked の演算と 0 除算は実行時に検査させる。wrapping は畳む
+        let text = run("function f() -> i32 {\n\
+                        bb0:\n    \
+                            %0: i32 = const 2147483647\n    \
+                            %1: i32 = const 1\n    \
+                            %2: i32 = checked add %0, %1 @ 1:1\n    \
+                            %3: i32 = add %0, %1 @ 1:1\n    \
+                            %4: i32 = const 0\n    \
+                            %5: i32 = div %1, %4 @ 1:1\n    \
+                            return %2\n\
+                        }");
+        assert!(text.contains("%2: i32 = checked add %0, %1"), "{}", text);
+        assert!(text.contains("%3: i32 = const -2147483648"), "{}", text);
+        assert!(text.contains("%5: i32 = div %1, %4"), "{}", text);
+    }
+
+    #[test]
+    fn test_fold_branch() {
+        assert_eq!(
+            run("function f(%0: i64) -> i64 {\n\
+                 bb0:\n    \
+                     %1: i32 = const 1\n    \
+                     branch %1, bb1, bb2\n\
+                 bb1:\n    \
+                     jump bb2\n\
+                 bb2:\n    \
+                     %2: i64 = phi [bb0: %0], [bb1: %0]\n    \
+                     return %2\n\
+                 }"),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i32 = const 1\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 jump bb2\n\
+             bb2:\n    \
+                 %2: i64 = phi [bb1: %0]\n    \
+                 return %2\n\
+             }\n"
+        );
+    }
+}
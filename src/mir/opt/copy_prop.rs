@@ -0,0 +1,123 @@
+use crate::mir::opt::replace_values;
+use crate::mir::{Function, InstKind, Value};
+use std::collections::HashMap;
+
+// コピーの伝播
+// copy と、自分自身を除くとどの合流元からも同じ値が来る phi を消し、使う側を元の値に置き換える
+pub fn propagate_copies(function: &mut Function) -> bool {
+    let mut aliases: HashMap<Value, Value> = HashMap::new();
+    // phi を消すと別の phi が自明になることがあるので繰り返す
+    loop {
+        let resolve = |mut value: Value, aliases: &HashMap<Value, Value>| {
+            while let Some(alias) = aliases.get(&value) {
+                value = *alias;
+            }
+            value
+        };
+        let mut progress = false;
+        for block in &function.blocks {
+            for inst in &block.insts {
+                let Some(result) = inst.result else {
+                    continue;
+                };
+                if aliases.contains_key(&result) {
+                    continue;
+                }
+                let source = match &inst.kind {
+                    InstKind::Copy(value) => Some(resolve(*value, &aliases)),
+                    InstKind::Phi(incoming) => {
+                        let mut sources = incoming
+                            .iter()
+                            .map(|(_, value)| resolve(*value, &aliases))
+                            .filter(|value| *value != result);
+                        match sources.next() {
+                            Some(first) if sources.all(|value| value == first) => Some(first),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                if let Some(source) = source {
+                    aliases.insert(result, source);
+                    progress = true;
+                }
+            }
+        }
+        if !progress {
+            break;
+        }
+    }
+    if aliases.is_empty() {
+        return false;
+    }
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| {
+            !inst
+                .result
+                .is_some_and(|result| aliases.contains_key(&result))
+        });
+    }
+    replace_values(function, &aliases);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    fn run(text: &str) -> String {
+        let mut function = parse_function(text).expect("Failed to parse MIR");
+        propagate_copies(&mut function);
+        function.to_string()
+    }
+
+    #[test]
+    fn test_copies() {
+        assert_eq!(
+            run("function f(%0: i64) -> i64 {\n\
+                 bb0:\n    \
+                     %1: i64 = copy %0\n    \
+                     %2: i64 = copy %1\n    \
+                     %3: i64 = checked add %2, %1 @ 1:1\n    \
+                     return %3\n\
+                 }"),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %3: i64 = checked add %0, %0 @ 1:1\n    \
+                 return %3\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_loop_phi_of_one_value() {
+        // ループで書き換えない変数の phi は入口の値そのもの
+        assert_eq!(
+            run("function f(%0: i64) -> i64 {\n\
+                 bb0:\n    \
+                     jump bb1\n\
+                 bb1:\n    \
+                     %1: i64 = phi [bb0: %0], [bb2: %2]\n    \
+                     %3: i32 = lt %1, %0 @ 1:1\n    \
+                     branch %3, bb2, bb3\n\
+                 bb2:\n    \
+                     %2: i64 = phi [bb1: %1]\n    \
+                     jump bb1\n\
+                 bb3:\n    \
+                     return %1\n\
+                 }"),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %3: i32 = lt %0, %0 @ 1:1\n    \
+                 branch %3, bb2, bb3\n\
+             bb2:\n    \
+                 jump bb1\n\
+             bb3:\n    \
+                 return %0\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::mir::lower::map_operands;
+use crate::mir::opt::replace_values;
+use crate::mir::{BinOp, BlockId, Function, GlobalId, InstKind, Ty, Value};
+use std::collections::{HashMap, HashSet};
+
+// 共通部分式の削除
+// - 同じ演算や同じ型の定数を支配するブロックで計算済みなら、その値を使う。add と mul はオペランドの順を問わない
+// - ブロックの中で、直前に読み書きしたグローバルの load はその値を使う。呼び出しを挟むと忘れる
+pub fn eliminate_common_subexpressions(function: &mut Function) -> bool {
+    let doms = function.dominators();
+    let mut available: HashMap<Key, Vec<(BlockId, Value)>> = HashMap::new();
+    let mut aliases: HashMap<Value, Value> = HashMap::new();
+    for block in reverse_postorder(function) {
+        let mut memory: HashMap<GlobalId, Value> = HashMap::new();
+        let Function { blocks, values, .. } = &mut *function;
+        for inst in &mut blocks[block.0].insts {
+            map_operands(&mut inst.kind, |mut value| {
+                while let Some(alias) = aliases.get(&value) {
+                    value = *alias;
+                }
+                value
+            });
+            match (&inst.kind, inst.result) {
+                (InstKind::Load(global), Some(result)) => match memory.get(global) {
+                    Some(value) => {
+                        aliases.insert(result, *value);
+                    }
+                    None => {
+                        memory.insert(*global, result);
+                    }
+                },
+                (InstKind::Store(global, value), _) => {
+                    memory.insert(*global, *value);
+                }
+                (InstKind::Call { .. }, _) => memory.clear(),
+                (kind, Some(result)) => {
+                    let Some(key) = Key::new(kind, values[result.0]) else {
+                        continue;
+                    };
+                    // checked の演算は同じ unchecked の演算の代わりにもなる
+                    let found = key.candidates().into_iter().find_map(|key| {
+                        available.get(&key).and_then(|defs| {
+                            defs.iter()
+                                .find(|(def, _)| doms[block.0].contains(def))
+                                .map(|(_, value)| *value)
+                        })
+                    });
+                    match found {
+                        Some(value) => {
+                            aliases.insert(result, value);
+                        }
+                        None => available.entry(key).or_default().push((block, result)),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if aliases.is_empty() {
+        return false;
+    }
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| {
+            !inst
+                .result
+                .is_some_and(|result| aliases.contains_key(&result))
+        });
+    }
+    replace_values(function, &aliases);
+    true
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Binary(BinOp, Value, Value, bool),
+    Widen(Value),
+    Const(i64, Ty),
+}
+
+impl Key {
+    // ty は結果の型
+    fn new(kind: &InstKind, ty: Ty) -> Option<Key> {
+        match kind {
+            InstKind::Binary {
+                op,
+                lhs,
+                rhs,
+                checked,
+                ..
+            } => {
+                let (lhs, rhs) = match op {
+                    BinOp::Add | BinOp::Mul if rhs < lhs => (*rhs, *lhs),
+                    _ => (*lhs, *rhs),
+                };
+                Some(Key::Binary(*op, lhs, rhs, *checked))
+            }
+            InstKind::Widen(value) => Some(Key::Widen(*value)),
+            InstKind::Const(value) => Some(Key::Const(*value, ty)),
+            _ => None,
+        }
+    }
+
+    fn candidates(self) -> Vec<Key> {
+        match self {
+            Key::Binary(op, lhs, rhs, false) => vec![self, Key::Binary(op, lhs, rhs, true)],
+            _ => vec![self],
+        }
+    }
+}
+
+// 入口から届くブロックを、支配するブロックが先に来る順に並べる
+pub(crate) fn reverse_postorder(function: &Function) -> Vec<BlockId> {
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    // (ブロック, 後続を積み終えたか)
+    let mut stack = vec![(BlockId(0), false)];
+    while let Some((block, done)) = stack.pop() {
+        if done {
+            order.push(block);
+            continue;
+        }
+        if !visited.insert(block) {
+            continue;
+        }
+        stack.push((block, true));
+        for succ in function
+            .block(block)
+            .terminator
+            .successors()
+            .into_iter()
+            .rev()
+        {
+            if !visited.contains(&succ) {
+                stack.push((succ, false));
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    fn run(text: &str) -> String {
+        let mut function = parse_function(text).expect("Failed to parse MIR");
+        eliminate_common_subexpressions(&mut function);
+        function.to_string()
+    }
+
+    #[test]
+    fn test_dominating_expression() {
+        assert_eq!(
+            run("function f(%0: i64, %1: i64) -> i64 {\n\
+                 bb0:\n    \
+                     %2: i64 = checked add %0, %1 @ 1:1\n    \
+                     branch %2, bb1, bb2\n\
+                 bb1:\n    \
+                     %3: i64 = add %1, %0 @ 1:1\n    \
+                     return %3\n\
+                 bb2:\n    \
+                     %4: i64 = sub %0, %1 @ 1:1\n    \
+                     %5: i64 = sub %1, %0 @ 1:1\n    \
+                     %6: i64 = sub %0, %1 @ 1:1\n    \
+                     %7: i64 = add %5, %6 @ 1:1\n    \
+                     return %7\n\
+                 }"),
+            "function f(%0: i64, %1: i64) -> i64 {\n\
+             bb0:\n    \
+                 %2: i64 = checked add %0, %1 @ 1:1\n    \
+                 branch %2, bb1, bb2\n\
+             bb1:\n    \
+                 return %2\n\
+             bb2:\n    \
+                 %4: i64 = sub %0, %1 @ 1:1\n    \
+                 %5: i64 = sub %1, %0 @ 1:1\n    \
+                 %7: i64 = add %5, %4 @ 1:1\n    \
+                 return %7\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_sibling_blocks_do_not_share() {
+        let text = "function f(%0: i64) -> i64 {\n\
+                    bb0:\n    \
+                        branch %0, bb1, bb2\n\
+                    bb1:\n    \
+                        %1: i64 = checked mul %0, %0 @ 1:1\n    \
+                        return %1\n\
+                    bb2:\n    \
+                        %2: i64 = checked mul %0, %0 @ 1:1\n    \
+                        return %2\n\
+                    }\n";
+        assert_eq!(run(text), text);
+    }
+
+    #[test]
+    fn test_redundant_loads() {
+        assert_eq!(
+            run("function main() {\n\
+                 bb0:\n    \
+                     %0: i64 = load @0\n    \
+                     %1: i64 = load @0\n    \
+                     store @1, %1\n    \
+                     %2: i64 = load @1\n    \
+                     call g()\n    \
+                     %3: i64 = load @1\n    \
+                     print %2\n    \
+                     print %3\n    \
+                     return\n\
+                 }"),
+            "function main() {\n\
+             bb0:\n    \
+                 %0: i64 = load @0\n    \
+                 store @1, %0\n    \
+                 call g()\n    \
+                 %3: i64 = load @1\n    \
+                 print %0\n    \
+                 print %3\n    \
+                 return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(
+            run("function f() -> i64 {\n\
+                 bb0:\n    \
+                     %0: i64 = const 1\n    \
+                     %1: i32 = const 1\n    \
+                     %2: i64 = const 1\n    \
+                     %3: i64 = widen %1\n    \
+                     %4: i64 = add %0, %2 @ 1:1\n    \
+                     %5: i64 = add %4, %3 @ 1:1\n    \
+                     return %5\n\
+                 }"),
+            "function f() -> i64 {\n\
+             bb0:\n    \
+                 %0: i64 = const 1\n    \
+                 %1: i32 = const 1\n    \
+                 %3: i64 = widen %1\n    \
+                 %4: i64 = add %0, %0 @ 1:1\n    \
+                 %5: i64 = add %4, %3 @ 1:1\n    \
+                 return %5\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::mir::opt::constants;
+use crate::mir::{BinOp, Function, InstKind, Value};
+use std::collections::{HashMap, HashSet};
+
+// 不要な命令の削除
+// 副作用のある命令と終端命令が使う値から定義をたどり、たどれなかった命令を消す
+// ループの phi が互いを使うだけの場合も消える。結果を使わない呼び出しは結果だけ外す
+pub fn eliminate_dead_code(function: &mut Function) -> bool {
+    let consts = constants(function);
+    let mut defs = HashMap::new();
+    let mut live: HashSet<Value> = HashSet::new();
+    let mut worklist = Vec::new();
+    for block in &function.blocks {
+        for inst in &block.insts {
+            if let Some(result) = inst.result {
+                defs.insert(result, &inst.kind);
+            }
+            if has_side_effects(&inst.kind, &consts) {
+                worklist.extend(inst.kind.operands());
+            }
+        }
+        worklist.extend(block.terminator.operands());
+    }
+    while let Some(value) = worklist.pop() {
+        if live.insert(value) {
+            if let Some(kind) = defs.get(&value) {
+                worklist.extend(kind.operands());
+            }
+        }
+    }
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let before = block.insts.len();
+        block.insts.retain(|inst| match inst.result {
+            Some(result) => live.contains(&result) || has_side_effects(&inst.kind, &consts),
+            None => true,
+        });
+        changed |= block.insts.len() != before;
+        for inst in &mut block.insts {
+            if matches!(inst.kind, InstKind::Call { .. })
+                && inst.result.is_some_and(|result| !live.contains(&result))
+            {
+                inst.result = None;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// 消すと振る舞いが変わる命令。実行時エラーになりうる演算も含む
+pub(crate) fn has_side_effects(kind: &InstKind, consts: &HashMap<Value, i64>) -> bool {
+    match kind {
+        InstKind::Store(..)
+        | InstKind::Call { .. }
+        | InstKind::Print(_)
+        | InstKind::PrintStr(_) => true,
+        InstKind::Binary {
+            op: BinOp::Div | BinOp::Rem,
+            rhs,
+            ..
+        } => !matches!(consts.get(rhs), Some(divisor) if *divisor != 0 && *divisor != -1),
+        InstKind::Binary { op, checked, .. } => *checked && op.can_overflow(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    fn run(text: &str) -> String {
+        let mut function = parse_function(text).expect("Failed to parse MIR");
+        eliminate_dead_code(&mut function);
+        function.to_string()
+    }
+
+    #[test]
+    fn test_remove_unused_values() {
+        assert_eq!(
+            run("function f(%0: i64) -> i64 {\n\
+                 bb0:\n    \
+                     %1: i64 = const 2\n    \
+                     %2: i64 = add %0, %1 @ 1:1\n    \
+                     %3: i64 = checked add %0, %1 @ 1:1\n    \
+                     %4: i64 = div %0, %1 @ 1:1\n    \
+                     %5: i64 = div %1, %0 @ 1:1\n    \
+                     %6: i64 = call f(%0)\n    \
+                     return %0\n\
+                 }"),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = const 2\n    \
+                 %3: i64 = checked add %0, %1 @ 1:1\n    \
+                 %5: i64 = div %1, %0 @ 1:1\n    \
+                 call f(%0)\n    \
+                 return %0\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_dead_loop_phis() {
+        let text = run("function f(%0: i64) {\n\
+                        bb0:\n    \
+                            %1: i64 = const 1\n    \
+                            jump bb1\n\
+                        bb1:\n    \
+                            %2: i64 = phi [bb0: %0], [bb1: %3]\n    \
+                            %3: i64 = add %2, %1 @ 1:1\n    \
+                            jump bb1\n\
+                        }");
+        assert!(!text.contains("phi") && !text.contains("add"), "{}", text);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::hir::InlineHint;
+use crate::mir::lower::{
+    map_operands, map_terminator_operands, remove_unreachable_blocks, renumber_values,
+};
+use crate::mir::{Block, BlockId, Function, Inst, InstKind, Program, Terminator, Value};
+use std::collections::{HashMap, HashSet};
+
+// 関数のインライン展開
+// 呼び出し先の本体を呼び出し元に写す。引数は実引数に、値とブロックは呼び出し元の新しい番号にする
+// return は呼び出しの後ろを切り出したブロックへのジャンプになり、戻り値はそこで phi で選ぶ
+// - #[inline(never)] の関数と再帰する関数は展開しない。#[inline] の関数は常に展開する
+// - それ以外は命令の数が threshold 以下なら展開する
+// 呼び出される側から順に処理するので、写す本体は展開を済ませたものになる
+pub fn inline_calls(program: &mut Program, threshold: usize) -> bool {
+    let recursive = recursive_functions(program);
+    let inlinable = |callee: &Function| {
+        let entry_has_preds = !callee.predecessors()[0].is_empty();
+        match callee.inline {
+            _ if entry_has_preds || recursive.contains(&callee.name) => false,
+            InlineHint::Never => false,
+            InlineHint::Always => true,
+            InlineHint::Default => cost(callee) <= threshold,
+        }
+    };
+    let mut changed = false;
+    for index in callees_first(program) {
+        let mut caller = program.functions[index].clone();
+        if inline_into(&mut caller, &program.functions, &inlinable) {
+            program.functions[index] = caller;
+            changed = true;
+        }
+    }
+    changed |= inline_into(&mut program.main, &program.functions, &inlinable);
+    changed
+}
+
+// 展開したときに増える命令の数の見積もり。phi は展開後に消えることが多いので数えない
+fn cost(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .map(|block| {
+            let insts = block
+                .insts
+                .iter()
+                .filter(|inst| !matches!(inst.kind, InstKind::Phi(_)))
+                .count();
+            insts + 1
+        })
+        .sum()
+}
+
+fn callees(function: &Function) -> impl Iterator<Item = &str> {
+    function.blocks.iter().flat_map(|block| {
+        let tail_call = match &block.terminator {
+            Terminator::TailCall { callee, .. } => Some(callee.as_str()),
+            _ => None,
+        };
+        block
+            .insts
+            .iter()
+            .filter_map(|inst| match &inst.kind {
+                InstKind::Call { callee, .. } => Some(callee.as_str()),
+                _ => None,
+            })
+            .chain(tail_call)
+    })
+}
+
+// 呼び出しをたどって自分に戻れる関数
+fn recursive_functions(program: &Program) -> HashSet<String> {
+    let by_name: HashMap<&str, &Function> = program
+        .functions
+        .iter()
+        .map(|function| (function.name.as_str(), function))
+        .collect();
+    let mut recursive = HashSet::new();
+    for function in &program.functions {
+        let mut visited = HashSet::new();
+        let mut stack: Vec<&str> = callees(function).collect();
+        while let Some(name) = stack.pop() {
+            if name == function.name {
+                recursive.insert(function.name.clone());
+                break;
+            }
+            if visited.insert(name) {
+                if let Some(callee) = by_name.get(name) {
+                    stack.extend(callees(callee));
+                }
+            }
+        }
+    }
+    recursive
+}
+
+// 関数の番号を、呼び出される側が先に来る順に並べる
+fn callees_first(program: &Program) -> Vec<usize> {
+    let index: HashMap<&str, usize> = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(i, function)| (function.name.as_str(), i))
+        .collect();
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    for root in 0..program.functions.len() {
+        // (関数, 呼び出し先を積み終えたか)
+        let mut stack = vec![(root, false)];
+        while let Some((function, done)) = stack.pop() {
+            if done {
+                order.push(function);
+                continue;
+            }
+            if !visited.insert(function) {
+                continue;
+            }
+            stack.push((function, true));
+            for callee in callees(&program.functions[function]) {
+                if let Some(callee) = index.get(callee) {
+                    if !visited.contains(callee) {
+                        stack.push((*callee, false));
+                    }
+                }
+            }
+        }
+    }
+    order
+}
+
+fn inline_into(
+    caller: &mut Function,
+    functions: &[Function],
+    inlinable: &impl Fn(&Function) -> bool,
+) -> bool {
+    // 写したブロック。中の呼び出しは呼び出し先を処理したときに検討済み
+    let mut copied = HashSet::new();
+    let mut changed = false;
+    let mut block = 0;
+    while block < caller.blocks.len() {
+        let found = if copied.contains(&block) {
+            None
+        } else {
+            caller.blocks[block]
+                .insts
+                .iter()
+                .enumerate()
+                .find_map(|(i, inst)| match &inst.kind {
+                    InstKind::Call { callee, .. } => functions
+                        .iter()
+                        .find(|function| function.name == *callee && inlinable(function))
+                        .map(|function| (i, function)),
+                    _ => None,
+                })
+        };
+        // 呼び出しの後ろは新しいブロックに移るので、このブロックはもう見なくてよい
+        if let Some((index, callee)) = found {
+            copied.extend(splice(caller, BlockId(block), index, callee));
+            changed = true;
+        }
+        block += 1;
+    }
+    if changed {
+        remove_unreachable_blocks(caller);
+        renumber_values(caller);
+    }
+    changed
+}
+
+// block の index 番目の呼び出しを callee の本体で置き換え、写したブロックの範囲を返す
+fn splice(
+    caller: &mut Function,
+    block: BlockId,
+    index: usize,
+    callee: &Function,
+) -> std::ops::Range<usize> {
+    let mut rest = caller.blocks[block.0].insts.split_off(index);
+    let call = rest.remove(0);
+    let InstKind::Call { args, .. } = call.kind else {
+        unreachable!("inlining a non-call instruction");
+    };
+    let base = caller.blocks.len();
+    let after = BlockId(base + callee.blocks.len());
+    let terminator = std::mem::replace(
+        &mut caller.blocks[block.0].terminator,
+        Terminator::Jump(BlockId(base)),
+    );
+    // 後続の phi には、呼び出しの後ろのブロックから来るようになる
+    for succ in terminator.successors() {
+        for inst in &mut caller.blocks[succ.0].insts {
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (pred, _) in incoming {
+                    if *pred == block {
+                        *pred = after;
+                    }
+                }
+            }
+        }
+    }
+
+    // 引数は実引数に、ほかの値は新しい値にする
+    let mut values: HashMap<Value, Value> = callee.params.iter().copied().zip(args).collect();
+    for (i, ty) in callee.values.iter().enumerate() {
+        values.entry(Value(i)).or_insert_with(|| {
+            caller.values.push(*ty);
+            Value(caller.values.len() - 1)
+        });
+    }
+    let value = |value: Value| values[&value];
+    let target = |target: BlockId| BlockId(base + target.0);
+    // (return したブロック, 戻り値)
+    let mut returns = Vec::new();
+    for (i, callee_block) in callee.blocks.iter().enumerate() {
+        let mut copy = callee_block.clone();
+        for inst in &mut copy.insts {
+            inst.result = inst.result.map(value);
+            map_operands(&mut inst.kind, value);
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (pred, _) in incoming {
+                    *pred = target(*pred);
+                }
+            }
+        }
+        map_terminator_operands(&mut copy.terminator, value);
+        copy.terminator = match copy.terminator {
+            Terminator::Jump(to) => Terminator::Jump(target(to)),
+            Terminator::Branch { cond, then, else_ } => Terminator::Branch {
+                cond,
+                then: target(then),
+                else_: target(else_),
+            },
+            Terminator::Return(result) => {
+                returns.extend(result.map(|result| (target(BlockId(i)), result)));
+                Terminator::Jump(after)
+            }
+            // 展開した先では末尾ではないので、普通の呼び出しにして戻り値を返す
+            Terminator::TailCall {
+                callee: tail_callee,
+                args,
+            } => {
+                let result = callee.return_type.map(|ty| {
+                    caller.values.push(ty);
+                    Value(caller.values.len() - 1)
+                });
+                copy.insts.push(Inst {
+                    result,
+                    kind: InstKind::Call {
+                        callee: tail_callee,
+                        args,
+                    },
+                });
+                returns.extend(result.map(|result| (target(BlockId(i)), result)));
+                Terminator::Jump(after)
+            }
+            Terminator::Unreachable => Terminator::Unreachable,
+        };
+        caller.blocks.push(copy);
+    }
+
+    // 戻り値が呼び出しの結果になる。return がなければ後ろのブロックには届かない
+    let mut insts = Vec::new();
+    if let Some(result) = call.result {
+        let kind = match returns.as_slice() {
+            [] => None,
+            [(_, value)] => Some(InstKind::Copy(*value)),
+            _ => Some(InstKind::Phi(returns)),
+        };
+        insts.extend(kind.map(|kind| Inst {
+            result: Some(result),
+            kind,
+        }));
+    }
+    insts.extend(rest);
+    caller.blocks.push(Block { insts, terminator });
+    base..after.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_program;
+    use crate::mir::verify::verify_program;
+
+    fn run(text: &str, threshold: usize) -> Program {
+        let mut program = parse_program(text).expect("Failed to parse MIR");
+        inline_calls(&mut program, threshold);
+        assert_eq!(verify_program(&program), Ok(()));
+        program
+    }
+
+    #[test]
+    fn test_inline_small_function() {
+        let program = run(
+            "function add(%0: i64, %1: i64) -> i64 {\n\
+             bb0:\n    \
+                 %2: i64 = checked add %0, %1 @ 2:5\n    \
+                 return %2\n\
+             }\n\
+             function main() {\n\
+             bb0:\n    \
+                 %0: i64 = const 1\n    \
+                 %1: i64 = call add(%0, %0)\n    \
+                 print %1\n    \
+                 return\n\
+             }",
+            4,
+        );
+        assert_eq!(
+            program.main.to_string(),
+            "function main() {\n\
+             bb0:\n    \
+                 %0: i64 = const 1\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %1: i64 = checked add %0, %0 @ 2:5\n    \
+                 jump bb2\n\
+             bb2:\n    \
+                 %2: i64 = copy %1\n    \
+                 print %2\n    \
+                 return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_early_return_and_loop_in_caller() {
+        // abs の2つの return が phi で合流し、ループの phi は呼び出しの後ろのブロックから来る
+        let program = run(
+            "function abs(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = const 0\n    \
+                 %2: i32 = lt %0, %1 @ 1:1\n    \
+                 branch %2, bb1, bb2\n\
+             bb1:\n    \
+                 %3: i64 = checked sub %1, %0 @ 1:1\n    \
+                 return %3\n\
+             bb2:\n    \
+                 return %0\n\
+             }\n\
+             function main() {\n\
+             bb0:\n    \
+                 %0: i64 = const 0\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %1: i64 = phi [bb0: %0], [bb1: %2]\n    \
+                 %2: i64 = call abs(%1)\n    \
+                 branch %2, bb1, bb2\n\
+             bb2:\n    \
+                 return\n\
+             }",
+            8,
+        );
+        assert_eq!(
+            program.main.to_string(),
+            "function main() {\n\
+             bb0:\n    \
+                 %0: i64 = const 0\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %1: i64 = phi [bb0: %0], [bb6: %5]\n    \
+                 jump bb3\n\
+             bb2:\n    \
+                 return\n\
+             bb3:\n    \
+                 %2: i64 = const 0\n    \
+                 %3: i32 = lt %1, %2 @ 1:1\n    \
+                 branch %3, bb4, bb5\n\
+             bb4:\n    \
+                 %4: i64 = checked sub %2, %1 @ 1:1\n    \
+                 jump bb6\n\
+             bb5:\n    \
+                 jump bb6\n\
+             bb6:\n    \
+                 %5: i64 = phi [bb4: %4], [bb5: %1]\n    \
+                 branch %5, bb1, bb2\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_hints_and_recursion() {
+        let text = "function fact(%0: i64) -> i64 {\n\
+                    bb0:\n    \
+                        %1: i64 = call fact(%0)\n    \
+                        return %1\n\
+                    }\n\
+                    #[inline(never)]\n\
+                    function id(%0: i64) -> i64 {\n\
+                    bb0:\n    \
+                        return %0\n\
+                    }\n\
+                    #[inline]\n\
+                    function twice(%0: i64) -> i64 {\n\
+                    bb0:\n    \
+                        %1: i64 = call id(%0)\n    \
+                        %2: i64 = checked add %1, %1 @ 1:1\n    \
+                        return %2\n\
+                    }\n\
+                    function main() {\n\
+                    bb0:\n    \
+                        %0: i64 = const 1\n    \
+                        %1: i64 = call fact(%0)\n    \
+                        %2: i64 = call twice(%1)\n    \
+                        print %2\n    \
+                        return\n\
+                    }";
+        let program = run(text, 0);
+        let main = program.main.to_string();
+        assert!(main.contains("call fact(%0)"), "{}", main);
+        assert!(!main.contains("call twice"), "{}", main);
+        assert!(main.contains("call id("), "{}", main);
+    }
+}
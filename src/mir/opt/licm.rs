@@ -0,0 +1,195 @@
+use crate::mir::loops::{find_loops, preheader, Loop};
+use crate::mir::opt::constants;
+use crate::mir::opt::dce::has_side_effects;
+use crate::mir::{BlockId, Function, InstKind, Value};
+use std::collections::HashSet;
+
+// ループ不変式の移動
+// ループの中で毎回同じ値になる命令をプリヘッダに移す
+// - オペランドがすべてループの外か、移す命令で定義されていれば不変
+// - ループが1回も回らないと実行されない命令を前に出すことになるので、実行時エラーになりうる命令は移さない
+// - load は、ループの中に同じグローバルへの store も呼び出しもなければ移す
+// - const は、ループの外に置くとループの間ずっとレジスタを1つ使うので、移す命令が使うときだけ移す
+// 内側のループから順に処理するので、移した命令がさらに外側のループから出ることもある
+pub fn hoist_loop_invariants(function: &mut Function) -> bool {
+    let headers: Vec<BlockId> = find_loops(function)
+        .into_iter()
+        .map(|lp| lp.header)
+        .collect();
+    let mut changed = false;
+    for header in headers {
+        // 前のループでプリヘッダを作るとブロックが増えるので、ループを求め直す
+        let Some(lp) = find_loops(function)
+            .into_iter()
+            .find(|lp| lp.header == header)
+        else {
+            continue;
+        };
+        changed |= hoist(function, &lp);
+    }
+    changed
+}
+
+fn hoist(function: &mut Function, lp: &Loop) -> bool {
+    let consts = constants(function);
+    let mut blocks: Vec<BlockId> = lp.blocks.iter().copied().collect();
+    blocks.sort();
+    let mut defined_inside: HashSet<Value> = HashSet::new();
+    let mut stored = HashSet::new();
+    let mut calls = false;
+    for block in &blocks {
+        for inst in &function.block(*block).insts {
+            defined_inside.extend(inst.result);
+            match &inst.kind {
+                InstKind::Store(global, _) => {
+                    stored.insert(*global);
+                }
+                InstKind::Call { .. } => calls = true,
+                _ => {}
+            }
+        }
+    }
+    let movable = |kind: &InstKind| match kind {
+        InstKind::Phi(_) => false,
+        InstKind::Load(global) => !calls && !stored.contains(global),
+        kind => !has_side_effects(kind, &consts),
+    };
+
+    // 移す命令 (ブロック, 位置)。使う命令より定義する命令が先に来る
+    let mut hoisted: Vec<(BlockId, usize)> = Vec::new();
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for block in &blocks {
+            for (i, inst) in function.block(*block).insts.iter().enumerate() {
+                let Some(result) = inst.result else {
+                    continue;
+                };
+                if defined_inside.contains(&result)
+                    && movable(&inst.kind)
+                    && inst
+                        .kind
+                        .operands()
+                        .iter()
+                        .all(|operand| !defined_inside.contains(operand))
+                {
+                    defined_inside.remove(&result);
+                    hoisted.push((*block, i));
+                    progress = true;
+                }
+            }
+        }
+    }
+    let is_const = |(block, i): &(BlockId, usize)| {
+        matches!(function.block(*block).insts[*i].kind, InstKind::Const(_))
+    };
+    let needed: HashSet<Value> = hoisted
+        .iter()
+        .filter(|position| !is_const(position))
+        .flat_map(|(block, i)| function.block(*block).insts[*i].kind.operands())
+        .collect();
+    hoisted.retain(|position| {
+        !is_const(position)
+            || function.block(position.0).insts[position.1]
+                .result
+                .is_some_and(|result| needed.contains(&result))
+    });
+    if hoisted.is_empty() {
+        return false;
+    }
+    let Some(preheader) = preheader(function, lp) else {
+        return false;
+    };
+    let insts: Vec<_> = hoisted
+        .iter()
+        .map(|(block, i)| function.block(*block).insts[*i].clone())
+        .collect();
+    for block in &blocks {
+        let mut i = 0;
+        function.blocks[block.0].insts.retain(|_| {
+            i += 1;
+            !hoisted.contains(&(*block, i - 1))
+        });
+    }
+    function.blocks[preheader.0].insts.extend(insts);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    fn run(text: &str) -> String {
+        let mut function = parse_function(text).expect("Failed to parse MIR");
+        hoist_loop_invariants(&mut function);
+        function.to_string()
+    }
+
+    #[test]
+    fn test_hoist_invariants() {
+        // %3 と %4 は不変。%5 は桁あふれしうるので残し、%6 はループの値を使うので残す
+        assert_eq!(
+            run("function f(%0: i64, %1: i64) -> i64 {\n\
+                 bb0:\n    \
+                     jump bb1\n\
+                 bb1:\n    \
+                     %2: i64 = phi [bb0: %0], [bb1: %6]\n    \
+                     %3: i64 = const 2\n    \
+                     %4: i64 = mul %1, %3 @ 1:1\n    \
+                     %5: i64 = checked add %1, %1 @ 1:1\n    \
+                     %6: i64 = add %2, %4 @ 1:1\n    \
+                     %7: i32 = lt %6, %5 @ 1:1\n    \
+                     branch %7, bb1, bb2\n\
+                 bb2:\n    \
+                     return %6\n\
+                 }"),
+            "function f(%0: i64, %1: i64) -> i64 {\n\
+             bb0:\n    \
+                 %3: i64 = const 2\n    \
+                 %4: i64 = mul %1, %3 @ 1:1\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %2: i64 = phi [bb0: %0], [bb1: %6]\n    \
+                 %5: i64 = checked add %1, %1 @ 1:1\n    \
+                 %6: i64 = add %2, %4 @ 1:1\n    \
+                 %7: i32 = lt %6, %5 @ 1:1\n    \
+                 branch %7, bb1, bb2\n\
+             bb2:\n    \
+                 return %6\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_loads_and_nested_loops() {
+        // @0 はループの中で書かないので、内側と外側のループの両方から出る。@1 は書くので残る
+        let text = run("function main() {\n\
+                        bb0:\n    \
+                            jump bb1\n\
+                        bb1:\n    \
+                            %0: i64 = load @1\n    \
+                            branch %0, bb2, bb4\n\
+                        bb2:\n    \
+                            %1: i64 = load @0\n    \
+                            store @1, %1\n    \
+                            branch %1, bb2, bb3\n\
+                        bb3:\n    \
+                            jump bb1\n\
+                        bb4:\n    \
+                            return\n\
+                        }");
+        assert!(
+            text.starts_with(
+                "function main() {\n\
+                 bb0:\n    \
+                     %1: i64 = load @0\n    \
+                     jump bb1\n\
+                 bb1:\n    \
+                     %0: i64 = load @1\n"
+            ),
+            "{}",
+            text
+        );
+    }
+}
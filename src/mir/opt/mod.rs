@@ -0,0 +1,345 @@
+// MIR の最適化
+// パスは関数ごとに MIR を書き換え、変えたかどうかを返す。どのパスも SSA の形を保つ
+// インライン展開、グローバルの昇格、末尾呼び出しの最適化はプログラム全体を見るので、ほかのパスの前に1回だけ実行する
+// -O1 は有効なパスを1回ずつ、-O2 は何も変わらなくなるまで繰り返して実行する
+use crate::mir::lower::{map_operands, map_terminator_operands, renumber_values};
+use crate::mir::{Function, InstKind, Program, Value};
+use std::collections::HashMap;
+
+pub mod const_prop;
+pub mod copy_prop;
+pub mod cse;
+pub mod dce;
+pub mod inline;
+pub mod licm;
+pub mod promote_globals;
+pub mod simplify_cfg;
+pub mod strength_reduce;
+pub mod tail_calls;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pass {
+    Inline,
+    PromoteGlobals,
+    TailCalls,
+    ConstProp,
+    CopyProp,
+    Cse,
+    Licm,
+    StrengthReduce,
+    Dce,
+    SimplifyCfg,
+}
+
+impl Pass {
+    // 実行する順
+    pub const ALL: [Pass; 10] = [
+        Pass::Inline,
+        Pass::PromoteGlobals,
+        Pass::TailCalls,
+        Pass::ConstProp,
+        Pass::CopyProp,
+        Pass::Cse,
+        Pass::Licm,
+        Pass::StrengthReduce,
+        Pass::Dce,
+        Pass::SimplifyCfg,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Inline => "inline",
+            Pass::PromoteGlobals => "promote-globals",
+            Pass::TailCalls => "tail-calls",
+            Pass::ConstProp => "const-prop",
+            Pass::CopyProp => "copy-prop",
+            Pass::Cse => "cse",
+            Pass::Licm => "licm",
+            Pass::StrengthReduce => "strength-reduce",
+            Pass::Dce => "dce",
+            Pass::SimplifyCfg => "simplify-cfg",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    pub fn run(self, function: &mut Function) -> bool {
+        match self {
+            // PassManager::run でプログラム全体に対して実行する
+            Pass::Inline | Pass::PromoteGlobals | Pass::TailCalls => false,
+            Pass::ConstProp => const_prop::propagate_constants(function),
+            Pass::CopyProp => copy_prop::propagate_copies(function),
+            Pass::Cse => cse::eliminate_common_subexpressions(function),
+            Pass::Licm => licm::hoist_loop_invariants(function),
+            Pass::StrengthReduce => strength_reduce::reduce_induction_variables(function),
+            Pass::Dce => dce::eliminate_dead_code(function),
+            Pass::SimplifyCfg => simplify_cfg::simplify_cfg(function),
+        }
+    }
+
+    // この水準で既定で有効か
+    fn default_enabled(self, level: u8) -> bool {
+        match self {
+            Pass::Cse | Pass::StrengthReduce => level >= 2,
+            _ => level >= 1,
+        }
+    }
+}
+
+// コマンドラインで指定された最適化の設定
+#[derive(Clone, Debug, Default)]
+pub struct OptConfig {
+    // -O0, -O1, -O2
+    pub level: u8,
+    // 水準によらず有効または無効にしたパス
+    overrides: HashMap<Pass, bool>,
+    // MIR を変えたパスを表示する (--print-passes)
+    pub print_passes: bool,
+}
+
+impl OptConfig {
+    pub fn set_enabled(&mut self, pass: Pass, enabled: bool) {
+        self.overrides.insert(pass, enabled);
+    }
+
+    pub fn is_enabled(&self, pass: Pass) -> bool {
+        self.overrides
+            .get(&pass)
+            .copied()
+            .unwrap_or_else(|| pass.default_enabled(self.level))
+    }
+}
+
+// 何も変わらなくなるまで繰り返すときの上限
+const MAX_ROUNDS: usize = 16;
+
+// 既定の方針の関数を展開する、命令の数の上限
+const INLINE_THRESHOLD: usize = 12;
+const INLINE_THRESHOLD_O2: usize = 40;
+
+pub struct PassManager {
+    passes: Vec<Pass>,
+    rounds: usize,
+    inline_threshold: usize,
+    print_passes: bool,
+}
+
+impl PassManager {
+    pub fn new(config: &OptConfig) -> Self {
+        PassManager {
+            passes: Pass::ALL
+                .into_iter()
+                .filter(|pass| config.is_enabled(*pass))
+                .collect(),
+            rounds: if config.level >= 2 { MAX_ROUNDS } else { 1 },
+            inline_threshold: if config.level >= 2 {
+                INLINE_THRESHOLD_O2
+            } else {
+                INLINE_THRESHOLD
+            },
+            print_passes: config.print_passes,
+        }
+    }
+
+    // passes を1回ずつ実行する
+    pub fn with_passes(passes: Vec<Pass>) -> Self {
+        PassManager {
+            passes,
+            rounds: 1,
+            inline_threshold: INLINE_THRESHOLD,
+            print_passes: false,
+        }
+    }
+
+    pub fn run(&self, program: &mut Program) {
+        if self.passes.contains(&Pass::Inline) {
+            let changed = inline::inline_calls(program, self.inline_threshold);
+            self.report(Pass::Inline, changed, "the program");
+        }
+        if self.passes.contains(&Pass::PromoteGlobals) {
+            let changed = promote_globals::promote_globals(program);
+            self.report(Pass::PromoteGlobals, changed, "function 'main'");
+        }
+        if self.passes.contains(&Pass::TailCalls) {
+            let changed = tail_calls::eliminate_tail_calls(program);
+            self.report(Pass::TailCalls, changed, "the program");
+        }
+        for function in program.functions.iter_mut().chain([&mut program.main]) {
+            self.run_function(function);
+        }
+    }
+
+    pub fn run_function(&self, function: &mut Function) {
+        let mut changed_any = false;
+        for _ in 0..self.rounds {
+            let mut changed = false;
+            for pass in &self.passes {
+                if pass.run(function) {
+                    self.report(*pass, true, &format!("function '{}'", function.name));
+                    changed = true;
+                }
+            }
+            changed_any |= changed;
+            if !changed {
+                break;
+            }
+        }
+        // 消した値の番号を詰める
+        if changed_any {
+            renumber_values(function);
+        }
+    }
+
+    fn report(&self, pass: Pass, changed: bool, target: &str) {
+        if changed && self.print_passes {
+            println!("Pass '{}' changed {}", pass.name(), target);
+        }
+    }
+}
+
+// 値の使用を aliases の行き先に置き換える。行き先がさらに置き換わっていればたどる
+pub(crate) fn replace_values(function: &mut Function, aliases: &HashMap<Value, Value>) {
+    if aliases.is_empty() {
+        return;
+    }
+    let resolve = |mut value: Value| {
+        while let Some(alias) = aliases.get(&value) {
+            value = *alias;
+        }
+        value
+    };
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            map_operands(&mut inst.kind, resolve);
+        }
+        map_terminator_operands(&mut block.terminator, resolve);
+    }
+}
+
+// const で定義された値
+pub(crate) fn constants(function: &Function) -> HashMap<Value, i64> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.insts)
+        .filter_map(|inst| match (inst.result, &inst.kind) {
+            (Some(result), InstKind::Const(value)) => Some((result, *value)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::interp::interpret;
+    use crate::mir::parse::parse_function;
+    use crate::mir::verify::verify_program;
+    use crate::test_util::lower_to_mir as compile;
+
+    #[test]
+    fn test_levels() {
+        let mut config = OptConfig::default();
+        assert!(!Pass::ALL.iter().any(|pass| config.is_enabled(*pass)));
+        config.level = 1;
+        assert!(config.is_enabled(Pass::Dce) && !config.is_enabled(Pass::Cse));
+        config.set_enabled(Pass::Cse, true);
+        config.set_enabled(Pass::Dce, false);
+        assert!(!config.is_enabled(Pass::Dce) && config.is_enabled(Pass::Cse));
+    }
+
+    #[test]
+    fn test_o2_pipeline() {
+        // if (1 < 2) { a + a } else { 0 } のように、分岐が畳まれて1つのブロックになる
+        let mut function = parse_function(
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = const 1\n    \
+                 %2: i64 = const 2\n    \
+                 %3: i32 = lt %1, %2\n    \
+                 branch %3, bb1, bb2\n\
+             bb1:\n    \
+                 %4: i64 = checked add %0, %0\n    \
+                 %5: i64 = checked add %0, %0\n    \
+                 %6: i64 = copy %5\n    \
+                 %7: i64 = checked mul %4, %6\n    \
+                 jump bb3\n\
+             bb2:\n    \
+                 %8: i64 = const 0\n    \
+                 jump bb3\n\
+             bb3:\n    \
+                 %9: i64 = phi [bb1: %7], [bb2: %8]\n    \
+                 return %9\n\
+             }",
+        )
+        .expect("Failed to parse MIR");
+        let config = OptConfig {
+            level: 2,
+            ..OptConfig::default()
+        };
+        PassManager::new(&config).run_function(&mut function);
+        assert_eq!(
+            function.to_string(),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = checked add %0, %0 @ 1:1\n    \
+                 %2: i64 = checked mul %1, %1 @ 1:1\n    \
+                 return %2\n\
+             }\n"
+        );
+        let program = Program {
+            globals: Vec::new(),
+            functions: vec![function],
+            main: parse_function("function main() {\nbb0:\n    return\n}").unwrap(),
+        };
+        assert_eq!(verify_program(&program), Ok(()));
+    }
+
+    #[test]
+    fn test_loop_benchmarks() {
+        // bench/ のプログラムは -O2 で出力を変えずに、実行のコストが 3/4 より小さくなる
+        let benches = [
+            ("sum_loop", include_str!("../../../bench/sum_loop.sim")),
+            ("invariant", include_str!("../../../bench/invariant.sim")),
+            ("strength", include_str!("../../../bench/strength.sim")),
+            ("nested", include_str!("../../../bench/nested.sim")),
+        ];
+        for (name, source) in benches {
+            let mut program = compile(source);
+            let before = interpret(&program, 10_000_000).expect("Failed to run at -O0");
+            let config = OptConfig {
+                level: 2,
+                ..OptConfig::default()
+            };
+            PassManager::new(&config).run(&mut program);
+            assert_eq!(verify_program(&program), Ok(()), "{}", name);
+            let after = interpret(&program, 10_000_000).expect("Failed to run at -O2");
+            assert_eq!(after.output, before.output, "{}", name);
+            println!(
+                "{}: {} -> {} steps, cost {} -> {}",
+                name, before.steps, after.steps, before.cost, after.cost
+            );
+            assert!(after.cost * 4 < before.cost * 3, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        // 100 万段の再帰も、末尾呼び出しにすればフレームが積み上がらない
+        let mut program = compile(include_str!("../../../examples/tail_call.sim"));
+        let config = OptConfig {
+            level: 1,
+            ..OptConfig::default()
+        };
+        PassManager::new(&config).run(&mut program);
+        assert_eq!(verify_program(&program), Ok(()));
+        for function in &program.functions {
+            let text = function.to_string();
+            assert!(!text.contains(" = call "), "{}", text);
+        }
+        let execution = interpret(&program, 100_000_000).expect("Failed to run");
+        assert_eq!(execution.output, "500000500000\n0\n");
+    }
+}
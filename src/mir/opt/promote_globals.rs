@@ -0,0 +1,238 @@
+use crate::mir::lower::remove_unreachable_blocks;
+use crate::mir::opt::cse::reverse_postorder;
+use crate::mir::opt::replace_values;
+use crate::mir::{BlockId, Function, GlobalId, Inst, InstKind, Program, Value};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+// グローバルのレジスタへの昇格
+// トップレベルの変数はグローバルとしてメモリに置くので、main のループのカウンタも毎回 load と store をする
+// どの関数も読み書きしないグローバルは main だけのものなので、main の中の値 (SSA の変数) にする
+// - 初期値は入口の const にし、合流点には支配境界に phi を置く (Cytron らの方法)
+// - load は直前に書いた値に、store は消す。main が終わった後にグローバルを読むものはない
+pub fn promote_globals(program: &mut Program) -> bool {
+    let mut shared = HashSet::new();
+    for function in &program.functions {
+        for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+            if let InstKind::Load(global) | InstKind::Store(global, _) = inst.kind {
+                shared.insert(global);
+            }
+        }
+    }
+    let mut promoted: Vec<GlobalId> = Vec::new();
+    for inst in program.main.blocks.iter().flat_map(|block| &block.insts) {
+        if let InstKind::Load(global) | InstKind::Store(global, _) = inst.kind {
+            if !shared.contains(&global) && !promoted.contains(&global) {
+                promoted.push(global);
+            }
+        }
+    }
+    let main = &mut program.main;
+    // 入口に合流があると初期値を置く場所がない
+    if promoted.is_empty() || !main.predecessors()[0].is_empty() {
+        return false;
+    }
+    promoted.sort_by_key(|global| global.0);
+    remove_unreachable_blocks(main);
+
+    let preds = main.predecessors();
+    let frontiers = dominance_frontiers(main);
+    // (ブロック, グローバル) ごとに置いた phi の値
+    let mut phis: HashMap<(BlockId, GlobalId), Value> = HashMap::new();
+    let mut initial = Vec::new();
+    for global in &promoted {
+        let ty = program.globals[global.0].ty;
+        let new_value = |main: &mut Function| {
+            main.values.push(ty);
+            Value(main.values.len() - 1)
+        };
+        let value = new_value(main);
+        initial.push(Inst {
+            result: Some(value),
+            kind: InstKind::Const(program.globals[global.0].init),
+        });
+        // 書き込むブロックの支配境界に、繰り返し phi を置く
+        let mut worklist: Vec<BlockId> = (0..main.blocks.len())
+            .map(BlockId)
+            .filter(|block| {
+                main.block(*block)
+                    .insts
+                    .iter()
+                    .any(|inst| matches!(inst.kind, InstKind::Store(g, _) if g == *global))
+            })
+            .collect();
+        while let Some(block) = worklist.pop() {
+            for frontier in &frontiers[block.0] {
+                if let Entry::Vacant(entry) = phis.entry((*frontier, *global)) {
+                    entry.insert(new_value(main));
+                    worklist.push(*frontier);
+                }
+            }
+        }
+    }
+
+    // 支配するブロックが先に来る順に、各ブロックの終わりでのグローバルの値を求める
+    let idoms = immediate_dominators(main);
+    let mut exits: Vec<HashMap<GlobalId, Value>> = vec![HashMap::new(); main.blocks.len()];
+    let mut aliases = HashMap::new();
+    for block in reverse_postorder(main) {
+        let mut current: HashMap<GlobalId, Value> = match idoms[block.0] {
+            Some(idom) => exits[idom.0].clone(),
+            None => promoted
+                .iter()
+                .zip(&initial)
+                .map(|(global, inst)| (*global, inst.result.expect("constant has a result")))
+                .collect(),
+        };
+        for global in &promoted {
+            if let Some(phi) = phis.get(&(block, *global)) {
+                current.insert(*global, *phi);
+            }
+        }
+        for inst in &main.block(block).insts {
+            match (&inst.kind, inst.result) {
+                (InstKind::Load(global), Some(result)) if current.contains_key(global) => {
+                    aliases.insert(result, current[global]);
+                }
+                (InstKind::Store(global, value), _) if current.contains_key(global) => {
+                    current.insert(*global, *value);
+                }
+                _ => {}
+            }
+        }
+        exits[block.0] = current;
+    }
+
+    for block in &mut main.blocks {
+        block.insts.retain(|inst| match inst.kind {
+            InstKind::Load(global) | InstKind::Store(global, _) => !promoted.contains(&global),
+            _ => true,
+        });
+    }
+    let mut phis: Vec<_> = phis.into_iter().collect();
+    phis.sort_by_key(|((block, _), value)| (*block, *value));
+    for ((block, global), value) in phis.into_iter().rev() {
+        let incoming = preds[block.0]
+            .iter()
+            .map(|pred| (*pred, exits[pred.0][&global]))
+            .collect();
+        main.blocks[block.0].insts.insert(
+            0,
+            Inst {
+                result: Some(value),
+                kind: InstKind::Phi(incoming),
+            },
+        );
+    }
+    main.blocks[0].insts.splice(0..0, initial);
+    replace_values(main, &aliases);
+    true
+}
+
+// 各ブロックの直近の支配ブロック。入口は None
+// 支配ブロックの集合が自分より1つだけ小さいブロックが直近のもの
+fn immediate_dominators(function: &Function) -> Vec<Option<BlockId>> {
+    let doms = function.dominators();
+    doms.iter()
+        .enumerate()
+        .map(|(i, dom)| {
+            dom.iter()
+                .find(|d| d.0 != i && doms[d.0].len() + 1 == dom.len())
+                .copied()
+        })
+        .collect()
+}
+
+// 各ブロックの支配境界 (Cooper らの方法)
+fn dominance_frontiers(function: &Function) -> Vec<HashSet<BlockId>> {
+    let idoms = immediate_dominators(function);
+    let mut frontiers = vec![HashSet::new(); function.blocks.len()];
+    for (block, preds) in function.predecessors().iter().enumerate() {
+        if preds.len() < 2 {
+            continue;
+        }
+        for pred in preds {
+            let mut runner = Some(*pred);
+            while let Some(current) = runner {
+                if Some(current) == idoms[block] {
+                    break;
+                }
+                frontiers[current.0].insert(BlockId(block));
+                runner = idoms[current.0];
+            }
+        }
+    }
+    frontiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::interp::interpret;
+    use crate::mir::parse::parse_program;
+    use crate::mir::verify::verify_program;
+
+    #[test]
+    fn test_promote_loop_counter() {
+        // let mut i = 0; while (i < 3) { i += 1; } print(i); と、関数が読む @g
+        let mut program = parse_program(
+            "global @i: i64 = 0\n\
+             global @g: i64 = 5\n\
+             function f() -> i64 {\n\
+             bb0:\n    \
+                 %0: i64 = load @g\n    \
+                 return %0\n\
+             }\n\
+             function main() {\n\
+             bb0:\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %0: i64 = load @i\n    \
+                 %1: i64 = const 3\n    \
+                 %2: i32 = lt %0, %1 @ 1:1\n    \
+                 branch %2, bb2, bb3\n\
+             bb2:\n    \
+                 %3: i64 = load @i\n    \
+                 %4: i64 = const 1\n    \
+                 %5: i64 = checked add %3, %4 @ 1:1\n    \
+                 store @i, %5\n    \
+                 store @g, %5\n    \
+                 jump bb1\n\
+             bb3:\n    \
+                 %6: i64 = load @i\n    \
+                 print %6\n    \
+                 %7: i64 = call f()\n    \
+                 print %7\n    \
+                 return\n\
+             }",
+        )
+        .expect("Failed to parse MIR");
+        let before = interpret(&program, 1000).unwrap();
+        assert!(promote_globals(&mut program));
+        assert_eq!(verify_program(&program), Ok(()));
+        assert_eq!(
+            program.main.to_string(),
+            "function main() {\n\
+             bb0:\n    \
+                 %8: i64 = const 0\n    \
+                 jump bb1\n\
+             bb1:\n    \
+                 %9: i64 = phi [bb0: %8], [bb2: %5]\n    \
+                 %1: i64 = const 3\n    \
+                 %2: i32 = lt %9, %1 @ 1:1\n    \
+                 branch %2, bb2, bb3\n\
+             bb2:\n    \
+                 %4: i64 = const 1\n    \
+                 %5: i64 = checked add %9, %4 @ 1:1\n    \
+                 store @1, %5\n    \
+                 jump bb1\n\
+             bb3:\n    \
+                 print %9\n    \
+                 %7: i64 = call f()\n    \
+                 print %7\n    \
+                 return\n\
+             }\n"
+        );
+        assert_eq!(interpret(&program, 1000).unwrap().output, before.output);
+    }
+}
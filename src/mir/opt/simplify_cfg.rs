@@ -0,0 +1,213 @@
+use crate::mir::lower::remove_unreachable_blocks;
+use crate::mir::opt::replace_values;
+use crate::mir::{Block, BlockId, Function, Inst, InstKind, Terminator, Value};
+use std::collections::HashMap;
+
+// 制御フローグラフの整理
+// - 両方の行き先が同じ分岐をジャンプにする
+// - 命令のないブロックを素通りするジャンプを、phi のない行き先へ直接つなぐ
+// - 合流元が1つしかないブロックを、そこへジャンプするだけの前のブロックにつなげる
+// - 入口から届かないブロックを消す
+pub fn simplify_cfg(function: &mut Function) -> bool {
+    let mut changed = false;
+    let mut aliases: HashMap<Value, Value> = HashMap::new();
+    loop {
+        let mut progress = false;
+        for block in &mut function.blocks {
+            if let Terminator::Branch { then, else_, .. } = block.terminator {
+                if then == else_ {
+                    block.terminator = Terminator::Jump(then);
+                    progress = true;
+                }
+            }
+        }
+        progress |= thread_jumps(function);
+        progress |= merge_blocks(function, &mut aliases);
+        let blocks = function.blocks.len();
+        remove_unreachable_blocks(function);
+        progress |= function.blocks.len() != blocks;
+        if !progress {
+            break;
+        }
+        changed = true;
+    }
+    replace_values(function, &aliases);
+    changed
+}
+
+// 空のブロック e へのジャンプを e の行き先へのジャンプにする
+// 行き先に phi があると合流元が変わるので、そのときはしない
+fn thread_jumps(function: &mut Function) -> bool {
+    let forward: Vec<Option<BlockId>> = function
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| match block.terminator {
+            Terminator::Jump(target)
+                if i != 0
+                    && target.0 != i
+                    && block.insts.is_empty()
+                    && !has_phis(function, target) =>
+            {
+                Some(target)
+            }
+            _ => None,
+        })
+        .collect();
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let targets: Vec<&mut BlockId> = match &mut block.terminator {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch { then, else_, .. } => vec![then, else_],
+            Terminator::Return(_) | Terminator::TailCall { .. } | Terminator::Unreachable => {
+                Vec::new()
+            }
+        };
+        for target in targets {
+            if let Some(next) = follow(&forward, *target) {
+                *target = next;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+// b がジャンプする先 t の合流元が b だけなら、t の命令を b の後ろにつなげる
+// t の phi は b から来る値そのものになる。t は届かなくなり、後で消える
+fn merge_blocks(function: &mut Function, aliases: &mut HashMap<Value, Value>) -> bool {
+    let mut changed = false;
+    for i in 0..function.blocks.len() {
+        while let Terminator::Jump(target) = function.blocks[i].terminator {
+            let preds = function.predecessors();
+            if target.0 == 0 || target.0 == i || preds[target.0] != [BlockId(i)] {
+                break;
+            }
+            let merged = std::mem::replace(
+                &mut function.blocks[target.0],
+                Block {
+                    insts: Vec::new(),
+                    terminator: Terminator::Unreachable,
+                },
+            );
+            for inst in merged.insts {
+                match (inst.kind, inst.result) {
+                    (InstKind::Phi(incoming), Some(result)) => {
+                        aliases.insert(result, incoming[0].1);
+                    }
+                    (kind, result) => function.blocks[i].insts.push(Inst { result, kind }),
+                }
+            }
+            // t の後続の phi は b から来るようになる
+            for succ in merged.terminator.successors() {
+                for inst in &mut function.blocks[succ.0].insts {
+                    if let InstKind::Phi(incoming) = &mut inst.kind {
+                        for (pred, _) in incoming {
+                            if *pred == target {
+                                *pred = BlockId(i);
+                            }
+                        }
+                    }
+                }
+            }
+            function.blocks[i].terminator = merged.terminator;
+            changed = true;
+        }
+    }
+    changed
+}
+
+// 空のブロックをたどった行き先。空のブロックだけの輪になっていればたどらない
+fn follow(forward: &[Option<BlockId>], start: BlockId) -> Option<BlockId> {
+    let mut block = forward[start.0]?;
+    let mut steps = 0;
+    while let Some(next) = forward[block.0] {
+        if next == start || steps > forward.len() {
+            return None;
+        }
+        block = next;
+        steps += 1;
+    }
+    Some(block)
+}
+
+fn has_phis(function: &Function, block: BlockId) -> bool {
+    function
+        .block(block)
+        .insts
+        .iter()
+        .any(|inst| matches!(inst.kind, InstKind::Phi(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::parse::parse_function;
+
+    fn run(text: &str) -> String {
+        let mut function = parse_function(text).expect("Failed to parse MIR");
+        simplify_cfg(&mut function);
+        function.to_string()
+    }
+
+    #[test]
+    fn test_merge_straight_line() {
+        assert_eq!(
+            run("function f(%0: i64) -> i64 {\n\
+                 bb0:\n    \
+                     jump bb2\n\
+                 bb1:\n    \
+                     %3: i64 = checked add %2, %2 @ 1:1\n    \
+                     return %3\n\
+                 bb2:\n    \
+                     %1: i64 = phi [bb0: %0]\n    \
+                     %2: i64 = checked add %1, %1 @ 1:1\n    \
+                     jump bb1\n\
+                 }"),
+            "function f(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %2: i64 = checked add %0, %0 @ 1:1\n    \
+                 %3: i64 = checked add %2, %2 @ 1:1\n    \
+                 return %3\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_thread_empty_blocks() {
+        // if の両方の枝が空なら分岐は要らない
+        assert_eq!(
+            run("function f(%0: i32) {\n\
+                 bb0:\n    \
+                     branch %0, bb1, bb2\n\
+                 bb1:\n    \
+                     jump bb3\n\
+                 bb2:\n    \
+                     jump bb3\n\
+                 bb3:\n    \
+                     print %0\n    \
+                     return\n\
+                 }"),
+            "function f(%0: i32) {\n\
+             bb0:\n    \
+                 print %0\n    \
+                 return\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_keep_loops() {
+        let text = "function f(%0: i64) {\n\
+                    bb0:\n    \
+                        jump bb1\n\
+                    bb1:\n    \
+                        %1: i64 = phi [bb0: %0], [bb1: %2]\n    \
+                        %2: i64 = checked sub %1, %0 @ 1:1\n    \
+                        branch %2, bb1, bb2\n\
+                    bb2:\n    \
+                        return\n\
+                    }\n";
+        assert_eq!(run(text), text);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::mir::loops::{find_loops, preheader, Loop};
+use crate::mir::opt::{constants, replace_values};
+use crate::mir::{BinOp, BlockId, Function, Inst, InstKind, Ty, Value};
+use crate::parser::span::Span;
+use std::collections::{HashMap, HashSet};
+
+// 帰納変数の強さの軽減
+// ループのたびに定数 c ずつ増える phi (基本帰納変数) i に対し、ループの中の mul i, k (k は定数) を
+// i と一緒に c * k ずつ増える新しい phi に置き換え、乗算を加算にする
+// - 戻り辺が1つのループだけを扱う
+// - 桁あふれを検査しない乗算だけを置き換える。検査する乗算は、最後に足した値が使われずに
+//   ループを抜けるときにも桁あふれを報告してしまう
+// - 結果をループの外で使う乗算は置き換えない
+pub fn reduce_induction_variables(function: &mut Function) -> bool {
+    let headers: Vec<BlockId> = find_loops(function)
+        .into_iter()
+        .map(|lp| lp.header)
+        .collect();
+    let mut changed = false;
+    for header in headers {
+        let Some(lp) = find_loops(function)
+            .into_iter()
+            .find(|lp| lp.header == header)
+        else {
+            continue;
+        };
+        changed |= reduce(function, &lp);
+    }
+    changed
+}
+
+// 置き換える乗算 result = variable * factor
+struct Candidate {
+    result: Value,
+    variable: Value,
+    factor: i64,
+    span: Span,
+}
+
+fn reduce(function: &mut Function, lp: &Loop) -> bool {
+    let [latch] = lp.latches[..] else {
+        return false;
+    };
+    let consts = constants(function);
+    let steps = induction_variables(function, lp, latch, &consts);
+    let used_outside: HashSet<Value> = function
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !lp.contains(BlockId(*i)))
+        .flat_map(|(_, block)| {
+            block
+                .insts
+                .iter()
+                .flat_map(|inst| inst.kind.operands())
+                .chain(block.terminator.operands())
+        })
+        .collect();
+    let mut blocks: Vec<BlockId> = lp.blocks.iter().copied().collect();
+    blocks.sort();
+    let mut candidates = Vec::new();
+    for block in blocks {
+        for inst in &function.block(block).insts {
+            let (
+                Some(result),
+                InstKind::Binary {
+                    op: BinOp::Mul,
+                    lhs,
+                    rhs,
+                    checked: false,
+                    span,
+                },
+            ) = (inst.result, &inst.kind)
+            else {
+                continue;
+            };
+            if used_outside.contains(&result) {
+                continue;
+            }
+            let found = [(*lhs, *rhs), (*rhs, *lhs)]
+                .into_iter()
+                .find_map(|(variable, factor)| {
+                    steps.contains_key(&variable).then_some(())?;
+                    consts.get(&factor).map(|factor| (variable, *factor))
+                });
+            if let Some((variable, factor)) = found {
+                candidates.push(Candidate {
+                    result,
+                    variable,
+                    factor,
+                    span: *span,
+                });
+            }
+        }
+    }
+    if candidates.is_empty() {
+        return false;
+    }
+    let Some(preheader) = preheader(function, lp) else {
+        return false;
+    };
+
+    let mut aliases = HashMap::new();
+    for candidate in candidates {
+        let ty = function.value_type(candidate.variable);
+        let step = wrap(
+            ty,
+            steps[&candidate.variable].wrapping_mul(candidate.factor),
+        );
+        // 帰納変数の初期値はプリヘッダから来る
+        let init = function
+            .block(lp.header)
+            .insts
+            .iter()
+            .find_map(|inst| match &inst.kind {
+                InstKind::Phi(incoming) if inst.result == Some(candidate.variable) => incoming
+                    .iter()
+                    .find(|(pred, _)| *pred == preheader)
+                    .map(|(_, value)| *value),
+                _ => None,
+            })
+            .expect("induction variable has a value from the preheader");
+        let mut new_value = || {
+            function.values.push(ty);
+            Value(function.values.len() - 1)
+        };
+        let (factor, start, step_value, phi, next) = (
+            new_value(),
+            new_value(),
+            new_value(),
+            new_value(),
+            new_value(),
+        );
+        let binary = |op, lhs, rhs| InstKind::Binary {
+            op,
+            lhs,
+            rhs,
+            checked: false,
+            span: candidate.span,
+        };
+        function.blocks[preheader.0].insts.extend([
+            Inst {
+                result: Some(factor),
+                kind: InstKind::Const(candidate.factor),
+            },
+            Inst {
+                result: Some(start),
+                kind: binary(BinOp::Mul, init, factor),
+            },
+            Inst {
+                result: Some(step_value),
+                kind: InstKind::Const(step),
+            },
+        ]);
+        function.blocks[lp.header.0].insts.insert(
+            0,
+            Inst {
+                result: Some(phi),
+                kind: InstKind::Phi(vec![(preheader, start), (latch, next)]),
+            },
+        );
+        function.blocks[latch.0].insts.push(Inst {
+            result: Some(next),
+            kind: binary(BinOp::Add, phi, step_value),
+        });
+        aliases.insert(candidate.result, phi);
+    }
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| {
+            !inst
+                .result
+                .is_some_and(|result| aliases.contains_key(&result))
+        });
+    }
+    replace_values(function, &aliases);
+    true
+}
+
+// ヘッダの phi のうち、戻り辺から phi + c か phi - c が来るものと、1回あたりの増分
+fn induction_variables(
+    function: &Function,
+    lp: &Loop,
+    latch: BlockId,
+    consts: &HashMap<Value, i64>,
+) -> HashMap<Value, i64> {
+    let defs: HashMap<Value, &InstKind> = lp
+        .blocks
+        .iter()
+        .flat_map(|block| &function.block(*block).insts)
+        .filter_map(|inst| inst.result.map(|result| (result, &inst.kind)))
+        .collect();
+    let mut steps = HashMap::new();
+    for inst in &function.block(lp.header).insts {
+        let (Some(phi), InstKind::Phi(incoming)) = (inst.result, &inst.kind) else {
+            continue;
+        };
+        let [(a, _), (b, _)] = incoming[..] else {
+            continue;
+        };
+        if [a, b].iter().filter(|pred| lp.contains(**pred)).count() != 1 {
+            continue;
+        }
+        let Some((_, next)) = incoming.iter().find(|(pred, _)| *pred == latch) else {
+            continue;
+        };
+        let Some(InstKind::Binary { op, lhs, rhs, .. }) = defs.get(next) else {
+            continue;
+        };
+        let step = match op {
+            BinOp::Add if *lhs == phi => consts.get(rhs).copied(),
+            BinOp::Add if *rhs == phi => consts.get(lhs).copied(),
+            BinOp::Sub if *lhs == phi => consts.get(rhs).map(|step| step.wrapping_neg()),
+            _ => None,
+        };
+        if let Some(step) = step {
+            steps.insert(phi, step);
+        }
+    }
+    steps
+}
+
+// 型の幅で折り返す
+fn wrap(ty: Ty, value: i64) -> i64 {
+    match ty {
+        Ty::I32 => value as i32 as i64,
+        Ty::I64 => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::interp::interpret;
+    use crate::mir::parse::parse_program;
+    use crate::mir::verify::verify_program;
+
+    // s = 0; for (i = 0; i < 10; i += 2) { s += wrapping_mul(i, 3) }
+    const LOOP: &str = "function main() {\n\
+                        bb0:\n    \
+                            %0: i64 = const 0\n    \
+                            %1: i64 = const 10\n    \
+                            %2: i64 = const 2\n    \
+                            %3: i64 = const 3\n    \
+                            jump bb1\n\
+                        bb1:\n    \
+                            %4: i64 = phi [bb0: %0], [bb2: %8]\n    \
+                            %5: i64 = phi [bb0: %0], [bb2: %7]\n    \
+                            %9: i32 = lt %4, %1 @ 1:1\n    \
+                            branch %9, bb2, bb3\n\
+                        bb2:\n    \
+                            %6: i64 = mul %4, %3 @ 1:1\n    \
+                            %7: i64 = checked add %5, %6 @ 1:1\n    \
+                            %8: i64 = checked add %4, %2 @ 1:1\n    \
+                            jump bb1\n\
+                        bb3:\n    \
+                            print %5\n    \
+                            return\n\
+                        }";
+
+    #[test]
+    fn test_reduce_multiplication() {
+        let mut program = parse_program(LOOP).expect("Failed to parse MIR");
+        let before = interpret(&program, 1000).unwrap();
+        assert!(reduce_induction_variables(&mut program.main));
+        assert_eq!(verify_program(&program), Ok(()));
+        let text = program.main.to_string();
+        assert!(!text.contains("mul %4"), "{}", text);
+        assert!(
+            text.contains("%13: i64 = phi [bb0: %11], [bb2: %14]"),
+            "{}",
+            text
+        );
+        assert!(text.contains("%14: i64 = add %13, %12 @ 1:1"), "{}", text);
+        assert!(text.contains("%12: i64 = const 6"), "{}", text);
+        assert_eq!(interpret(&program, 1000).unwrap().output, before.output);
+        assert_eq!(before.output, "60\n");
+    }
+
+    #[test]
+    fn test_checked_multiplication_is_kept() {
+        let mut program = parse_program(&LOOP.replace("mul %4, %3", "checked mul %4, %3")).unwrap();
+        assert!(!reduce_induction_variables(&mut program.main));
+    }
+}
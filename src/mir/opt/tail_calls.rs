@@ -0,0 +1,296 @@
+use crate::mir::lower::remove_unreachable_blocks;
+use crate::mir::opt::replace_values;
+use crate::mir::{Block, BlockId, Function, Inst, InstKind, Program, Terminator, Ty, Value};
+use std::collections::{HashMap, HashSet};
+
+// 末尾呼び出しの最適化
+// 呼び出しの結果をそのまま return するなら、呼び出しを末尾呼び出し (TailCall) にしてフレームを使い回す
+// - return f(...) のほか、phi だけのブロックを通って return に着く呼び出しも末尾にある
+//   (ブロックや if の値になる呼び出しは合流点の phi を通って return に着く)
+// - 呼び出し元の引数より引数の多い関数は、引数を書き直す場所がないので末尾呼び出しにしない
+// - 自分への末尾呼び出しは入口へ戻るループにする。引数は入口の phi で選ぶ
+// main は呼び出し元に戻らないので対象にしない
+pub fn eliminate_tail_calls(program: &mut Program) -> bool {
+    let return_types: HashMap<String, Option<Ty>> = program
+        .functions
+        .iter()
+        .map(|function| (function.name.clone(), function.return_type))
+        .collect();
+    let mut changed = false;
+    for function in &mut program.functions {
+        changed |= convert_tail_calls(function, &return_types);
+        changed |= loop_self_tail_calls(function);
+    }
+    changed
+}
+
+fn convert_tail_calls(function: &mut Function, return_types: &HashMap<String, Option<Ty>>) -> bool {
+    let mut changed = false;
+    for block in function.reachable_blocks() {
+        let Some(Inst {
+            result,
+            kind: InstKind::Call { callee, args },
+        }) = function.block(block).insts.last()
+        else {
+            continue;
+        };
+        if args.len() > function.params.len()
+            || return_types.get(callee) != Some(&function.return_type)
+            || !returns_directly(function, block, *result)
+        {
+            continue;
+        }
+        let Some(Inst {
+            kind: InstKind::Call { callee, args },
+            ..
+        }) = function.blocks[block.0].insts.pop()
+        else {
+            unreachable!("the last instruction is a call");
+        };
+        let terminator = std::mem::replace(
+            &mut function.blocks[block.0].terminator,
+            Terminator::TailCall { callee, args },
+        );
+        // 後続の phi はもうこのブロックから来ない
+        for succ in terminator.successors() {
+            for inst in &mut function.blocks[succ.0].insts {
+                if let InstKind::Phi(incoming) = &mut inst.kind {
+                    incoming.retain(|(pred, _)| *pred != block);
+                }
+            }
+        }
+        changed = true;
+    }
+    if changed {
+        remove_unreachable_blocks(function);
+    }
+    changed
+}
+
+// block の終わりにある呼び出しの結果 result が、phi だけのブロックを通ってそのまま return されるか
+fn returns_directly(function: &Function, block: BlockId, result: Option<Value>) -> bool {
+    // result と同じ値。通った phi の結果を加えていく
+    let mut carried: HashSet<Value> = result.into_iter().collect();
+    let mut visited = HashSet::new();
+    let mut from = block;
+    loop {
+        match function.block(from).terminator {
+            Terminator::Return(None) => return result.is_none(),
+            Terminator::Return(Some(value)) => return carried.contains(&value),
+            Terminator::Jump(target) if visited.insert(target) => {
+                for inst in &function.block(target).insts {
+                    let (Some(phi), InstKind::Phi(incoming)) = (inst.result, &inst.kind) else {
+                        return false;
+                    };
+                    if incoming
+                        .iter()
+                        .any(|(pred, value)| *pred == from && carried.contains(value))
+                    {
+                        carried.insert(phi);
+                    }
+                }
+                from = target;
+            }
+            _ => return false,
+        }
+    }
+}
+
+// 自分への末尾呼び出しを、入口の後ろに作ったブロックへのジャンプにする
+// 入口は空にしてそのブロックへ飛ばし、引数の代わりにそのブロックの phi を使う
+fn loop_self_tail_calls(function: &mut Function) -> bool {
+    let name = function.name.clone();
+    let is_self_call = |block: &Block| matches!(&block.terminator, Terminator::TailCall { callee, .. } if *callee == name);
+    // 入口へ戻る辺があると、入口の前に値を選ぶ場所がない
+    if !function.blocks.iter().any(is_self_call) || !function.predecessors()[0].is_empty() {
+        return false;
+    }
+    let header = BlockId(function.blocks.len());
+    let entry = std::mem::replace(
+        &mut function.blocks[0],
+        Block {
+            insts: Vec::new(),
+            terminator: Terminator::Jump(header),
+        },
+    );
+    for succ in entry.terminator.successors() {
+        for inst in &mut function.blocks[succ.0].insts {
+            if let InstKind::Phi(incoming) = &mut inst.kind {
+                for (pred, _) in incoming {
+                    if *pred == BlockId(0) {
+                        *pred = header;
+                    }
+                }
+            }
+        }
+    }
+    function.blocks.push(entry);
+
+    let mut aliases = HashMap::new();
+    let mut phis = Vec::new();
+    for param in function.params.clone() {
+        function.values.push(function.value_type(param));
+        let phi = Value(function.values.len() - 1);
+        aliases.insert(param, phi);
+        phis.push((phi, vec![(BlockId(0), param)]));
+    }
+    replace_values(function, &aliases);
+    for (i, block) in function.blocks.iter_mut().enumerate() {
+        if !is_self_call(block) {
+            continue;
+        }
+        let Terminator::TailCall { args, .. } =
+            std::mem::replace(&mut block.terminator, Terminator::Jump(header))
+        else {
+            unreachable!("checked above");
+        };
+        for ((_, incoming), arg) in phis.iter_mut().zip(args) {
+            incoming.push((BlockId(i), arg));
+        }
+    }
+    function.blocks[header.0].insts.splice(
+        0..0,
+        phis.into_iter().map(|(phi, incoming)| Inst {
+            result: Some(phi),
+            kind: InstKind::Phi(incoming),
+        }),
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::interp::interpret;
+    use crate::mir::parse::parse_program;
+    use crate::mir::verify::verify_program;
+
+    // sum(n, acc) = n < 1 ? acc : sum(n - 1, acc + n)。if の値として呼び出す
+    const SUM: &str = "function sum(%0: i64, %1: i64) -> i64 {\n\
+                       bb0:\n    \
+                           %2: i64 = const 1\n    \
+                           %3: i32 = lt %0, %2 @ 1:1\n    \
+                           branch %3, bb1, bb2\n\
+                       bb1:\n    \
+                           jump bb3\n\
+                       bb2:\n    \
+                           %4: i64 = checked sub %0, %2 @ 1:1\n    \
+                           %5: i64 = checked add %1, %0 @ 1:1\n    \
+                           %6: i64 = call sum(%4, %5)\n    \
+                           jump bb3\n\
+                       bb3:\n    \
+                           %7: i64 = phi [bb1: %1], [bb2: %6]\n    \
+                           return %7\n\
+                       }\n";
+
+    #[test]
+    fn test_self_recursion_becomes_loop() {
+        let mut program = parse_program(&format!(
+            "{}function main() {{\n\
+             bb0:\n    \
+                 %0: i64 = const 100000\n    \
+                 %1: i64 = const 0\n    \
+                 %2: i64 = call sum(%0, %1)\n    \
+                 print %2\n    \
+                 return\n\
+             }}",
+            SUM
+        ))
+        .expect("Failed to parse MIR");
+        assert!(eliminate_tail_calls(&mut program));
+        assert_eq!(verify_program(&program), Ok(()));
+        assert_eq!(
+            program.functions[0].to_string(),
+            "function sum(%0: i64, %1: i64) -> i64 {\n\
+             bb0:\n    \
+                 jump bb4\n\
+             bb1:\n    \
+                 jump bb3\n\
+             bb2:\n    \
+                 %4: i64 = checked sub %8, %2 @ 1:1\n    \
+                 %5: i64 = checked add %9, %8 @ 1:1\n    \
+                 jump bb4\n\
+             bb3:\n    \
+                 %7: i64 = phi [bb1: %9]\n    \
+                 return %7\n\
+             bb4:\n    \
+                 %8: i64 = phi [bb0: %0], [bb2: %4]\n    \
+                 %9: i64 = phi [bb0: %1], [bb2: %5]\n    \
+                 %2: i64 = const 1\n    \
+                 %3: i32 = lt %8, %2 @ 1:1\n    \
+                 branch %3, bb1, bb2\n\
+             }\n"
+        );
+        assert_eq!(
+            interpret(&program, 10_000_000).unwrap().output,
+            "5000050000\n"
+        );
+    }
+
+    #[test]
+    fn test_mutual_recursion() {
+        // even(n) = n < 1 ? 1 : odd(n - 1)、odd(n) = n < 1 ? 0 : even(n - 1)
+        let function = |name: &str, base: i64, other: &str| {
+            format!(
+                "function {}(%0: i64) -> i64 {{\n\
+                 bb0:\n    \
+                     %1: i64 = const 1\n    \
+                     %2: i32 = lt %0, %1 @ 1:1\n    \
+                     branch %2, bb1, bb2\n\
+                 bb1:\n    \
+                     %3: i64 = const {}\n    \
+                     return %3\n\
+                 bb2:\n    \
+                     %4: i64 = checked sub %0, %1 @ 1:1\n    \
+                     %5: i64 = call {}(%4)\n    \
+                     return %5\n\
+                 }}\n",
+                name, base, other
+            )
+        };
+        let mut program = parse_program(&format!(
+            "{}{}function main() {{\n\
+             bb0:\n    \
+                 %0: i64 = const 1000001\n    \
+                 %1: i64 = call even(%0)\n    \
+                 print %1\n    \
+                 return\n\
+             }}",
+            function("even", 1, "odd"),
+            function("odd", 0, "even")
+        ))
+        .expect("Failed to parse MIR");
+        assert!(eliminate_tail_calls(&mut program));
+        assert_eq!(verify_program(&program), Ok(()));
+        let text = program.functions[0].to_string();
+        assert!(text.contains("    tail call odd(%4)\n"), "{}", text);
+        // 末尾呼び出しは Rust のスタックも使わないので、深い再帰でも溢れない
+        assert_eq!(interpret(&program, 10_000_000).unwrap().output, "0\n");
+        // main の呼び出しは末尾呼び出しにしない
+        assert!(program.main.to_string().contains("call even(%0)"));
+    }
+
+    #[test]
+    fn test_calls_that_stay() {
+        // 引数が呼び出し元より多い呼び出しと、結果を使ってから返す呼び出し
+        let mut program = parse_program(
+            "function two(%0: i64, %1: i64) -> i64 {\n\
+             bb0:\n    \
+                 return %0\n\
+             }\n\
+             function one(%0: i64) -> i64 {\n\
+             bb0:\n    \
+                 %1: i64 = call two(%0, %0)\n    \
+                 %2: i64 = call one(%1)\n    \
+                 %3: i64 = checked add %2, %0 @ 1:1\n    \
+                 return %3\n\
+             }\n\
+             function main() {\n\
+             bb0:\n    \
+                 return\n\
+             }",
+        )
+        .expect("Failed to parse MIR");
+        assert!(!eliminate_tail_calls(&mut program));
+    }
+}
@@ -0,0 +1,451 @@
+use crate::hir::InlineHint;
+use crate::mir::{
+    BinOp, Block, BlockId, Function, Global, GlobalId, Inst, InstKind, Program, Terminator, Ty,
+    Value,
+};
+use crate::parser::span::Span;
+use std::collections::HashMap;
+
+// テキスト形式の MIR を読む。Display で書き出したものをそのまま読み戻せる
+// 最適化のテストで入力と期待する結果を MIR で書くのに使う
+// function main がトップレベルの文になる
+pub fn parse_program(text: &str) -> Result<Program, String> {
+    let mut parser = TextParser::new(text);
+    let mut functions = Vec::new();
+    while let Some((line, text)) = parser.next_line() {
+        if let Some(rest) = text.strip_prefix("global ") {
+            let global = parse_global(rest).map_err(|e| format!("line {}: {}", line, e))?;
+            parser.globals.push(global);
+        } else {
+            functions.push(parser.function(line, text)?);
+        }
+    }
+    let Some(main) = functions
+        .iter()
+        .position(|function| function.name == "main")
+    else {
+        return Err("No function 'main'".to_string());
+    };
+    let main = functions.remove(main);
+    Ok(Program {
+        globals: parser.globals,
+        functions,
+        main,
+    })
+}
+
+// 関数を1つだけ読む。グローバルは @0 のように番号で書く
+pub fn parse_function(text: &str) -> Result<Function, String> {
+    let mut parser = TextParser::new(text);
+    let Some((line, first)) = parser.next_line() else {
+        return Err("No function".to_string());
+    };
+    let function = parser.function(line, first)?;
+    if let Some((line, text)) = parser.next_line() {
+        return Err(format!("line {}: Unexpected '{}'", line, text));
+    }
+    Ok(function)
+}
+
+struct TextParser<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    globals: Vec<Global>,
+}
+
+impl<'a> TextParser<'a> {
+    fn new(text: &'a str) -> Self {
+        TextParser {
+            lines: text.lines().enumerate(),
+            globals: Vec::new(),
+        }
+    }
+
+    // 空行を飛ばして次の行を返す。行番号は1始まり
+    fn next_line(&mut self) -> Option<(usize, &'a str)> {
+        self.lines
+            .by_ref()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .find(|(_, line)| !line.is_empty())
+    }
+
+    fn function(&mut self, line: usize, header: &str) -> Result<Function, String> {
+        let at = |line: usize| move |e: String| format!("line {}: {}", line, e);
+        // 関数の前の #[inline] と #[inline(never)]
+        let (inline, line, header) = match header {
+            "#[inline]" | "#[inline(never)]" => {
+                let Some((next, text)) = self.next_line() else {
+                    return Err(at(line)(
+                        "Expected a function after the attribute".to_string(),
+                    ));
+                };
+                let inline = if header == "#[inline]" {
+                    InlineHint::Always
+                } else {
+                    InlineHint::Never
+                };
+                (inline, next, text)
+            }
+            _ => (InlineHint::Default, line, header),
+        };
+        let mut types = HashMap::new();
+        let (name, params, return_type) = parse_header(header, &mut types).map_err(at(line))?;
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut insts = Vec::new();
+        // 終端命令を読むまでのブロック
+        let mut open = false;
+        loop {
+            let Some((line, text)) = self.next_line() else {
+                return Err(format!("Function '{}' is not closed", name));
+            };
+            if text == "}" {
+                if open {
+                    return Err(at(line)(format!(
+                        "{} has no terminator",
+                        BlockId(blocks.len())
+                    )));
+                }
+                break;
+            }
+            if let Some(label) = text.strip_suffix(':') {
+                let id = parse_block(label).map_err(at(line))?;
+                if open || id.0 != blocks.len() {
+                    return Err(at(line)(format!("Expected bb{}", blocks.len())));
+                }
+                open = true;
+                continue;
+            }
+            if !open {
+                return Err(at(line)(format!("Expected bb{}", blocks.len())));
+            }
+            if let Some(terminator) = parse_terminator(text).map_err(at(line))? {
+                blocks.push(Block {
+                    insts: std::mem::take(&mut insts),
+                    terminator,
+                });
+                open = false;
+                continue;
+            }
+            let inst = self.inst(text, &mut types).map_err(at(line))?;
+            insts.push(inst);
+        }
+        // 定義のない番号の型は使われないので何でもよい
+        let count = types
+            .keys()
+            .map(|value: &Value| value.0 + 1)
+            .max()
+            .unwrap_or(0);
+        let values = (0..count)
+            .map(|i| types.get(&Value(i)).copied().unwrap_or(Ty::I64))
+            .collect();
+        Ok(Function {
+            name,
+            params,
+            return_type,
+            values,
+            blocks,
+            inline,
+        })
+    }
+
+    // %1: i64 = add %0, %0 @ 1:5 や store @x, %1
+    fn inst(&self, text: &str, types: &mut HashMap<Value, Ty>) -> Result<Inst, String> {
+        let (result, text) = if text.starts_with('%') {
+            let (def, rest) = text
+                .split_once(" = ")
+                .ok_or_else(|| format!("Expected '=' in '{}'", text))?;
+            let (value, ty) = parse_typed_value(def)?;
+            if types.insert(value, ty).is_some() {
+                return Err(format!("{} is defined more than once", value));
+            }
+            (Some(value), rest)
+        } else {
+            (None, text)
+        };
+        let (opcode, args) = text.split_once(' ').unwrap_or((text, ""));
+        let kind = match opcode {
+            "const" => InstKind::Const(
+                args.parse()
+                    .map_err(|_| format!("Invalid constant '{}'", args))?,
+            ),
+            "copy" => InstKind::Copy(parse_value(args)?),
+            "widen" => InstKind::Widen(parse_value(args)?),
+            "phi" => {
+                let mut incoming = Vec::new();
+                for entry in args.split(',') {
+                    let entry = entry
+                        .trim()
+                        .strip_prefix('[')
+                        .and_then(|entry| entry.strip_suffix(']'))
+                        .ok_or_else(|| format!("Invalid phi operand '{}'", entry.trim()))?;
+                    let (block, value) = split_pair(entry, ":")?;
+                    incoming.push((parse_block(block)?, parse_value(value)?));
+                }
+                InstKind::Phi(incoming)
+            }
+            "load" => InstKind::Load(self.global(args)?),
+            "store" => {
+                let (global, value) = split_pair(args, ",")?;
+                InstKind::Store(self.global(global)?, parse_value(value)?)
+            }
+            "call" => {
+                let (callee, args) = parse_call(args)?;
+                InstKind::Call { callee, args }
+            }
+            "print" if args.starts_with('"') => InstKind::PrintStr(parse_string(args)?),
+            "print" => InstKind::Print(parse_value(args)?),
+            "checked" => parse_binary(args, true)?,
+            _ => parse_binary(text, false)?,
+        };
+        Ok(Inst { result, kind })
+    }
+
+    // @name か番号
+    fn global(&self, text: &str) -> Result<GlobalId, String> {
+        let name = text
+            .trim()
+            .strip_prefix('@')
+            .ok_or_else(|| format!("Expected a global, found '{}'", text))?;
+        if let Some(index) = self.globals.iter().position(|global| global.name == name) {
+            return Ok(GlobalId(index));
+        }
+        name.parse()
+            .map(GlobalId)
+            .map_err(|_| format!("Undefined global '@{}'", name))
+    }
+}
+
+// function add(%0: i64, %1: i64) -> i64 {
+fn parse_header(
+    text: &str,
+    types: &mut HashMap<Value, Ty>,
+) -> Result<(String, Vec<Value>, Option<Ty>), String> {
+    let rest = text
+        .strip_prefix("function ")
+        .and_then(|rest| rest.strip_suffix('{'))
+        .ok_or_else(|| format!("Expected a function, found '{}'", text))?;
+    let (name, rest) = rest
+        .split_once('(')
+        .ok_or_else(|| "Expected '(' after the function name".to_string())?;
+    let (params_text, rest) = rest
+        .split_once(')')
+        .ok_or_else(|| "Expected ')' after the parameters".to_string())?;
+    let mut params = Vec::new();
+    for param in params_text.split(',').map(str::trim) {
+        if param.is_empty() {
+            continue;
+        }
+        let (value, ty) = parse_typed_value(param)?;
+        types.insert(value, ty);
+        params.push(value);
+    }
+    let return_type = match rest.trim().strip_prefix("->") {
+        Some(ty) => Some(parse_type(ty.trim())?),
+        None if rest.trim().is_empty() => None,
+        None => return Err(format!("Unexpected '{}'", rest.trim())),
+    };
+    Ok((name.trim().to_string(), params, return_type))
+}
+
+// global @x: i64 = 1
+fn parse_global(text: &str) -> Result<Global, String> {
+    let (def, init) = split_pair(text, "=")?;
+    let (name, ty) = split_pair(def, ":")?;
+    let name = name
+        .strip_prefix('@')
+        .ok_or_else(|| format!("Expected a global, found '{}'", name))?;
+    Ok(Global {
+        name: name.to_string(),
+        ty: parse_type(ty)?,
+        init: init
+            .parse()
+            .map_err(|_| format!("Invalid constant '{}'", init))?,
+    })
+}
+
+// 終端命令でなければ None
+fn parse_terminator(text: &str) -> Result<Option<Terminator>, String> {
+    let (opcode, args) = text.split_once(' ').unwrap_or((text, ""));
+    let terminator = match opcode {
+        "jump" => Terminator::Jump(parse_block(args)?),
+        "branch" => {
+            let args: Vec<&str> = args.split(',').map(str::trim).collect();
+            let [cond, then, else_] = args.as_slice() else {
+                return Err(format!("Invalid branch '{}'", text));
+            };
+            Terminator::Branch {
+                cond: parse_value(cond)?,
+                then: parse_block(then)?,
+                else_: parse_block(else_)?,
+            }
+        }
+        "return" if args.is_empty() => Terminator::Return(None),
+        "return" => Terminator::Return(Some(parse_value(args)?)),
+        "unreachable" => Terminator::Unreachable,
+        "tail" => {
+            let call = args
+                .strip_prefix("call ")
+                .ok_or_else(|| format!("Invalid tail call '{}'", text))?;
+            let (callee, args) = parse_call(call)?;
+            Terminator::TailCall { callee, args }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(terminator))
+}
+
+// f(%1, %2)
+fn parse_call(text: &str) -> Result<(String, Vec<Value>), String> {
+    let (callee, args) = text
+        .strip_suffix(')')
+        .and_then(|call| call.split_once('('))
+        .ok_or_else(|| format!("Invalid call '{}'", text))?;
+    let args = args
+        .split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .map(parse_value)
+        .collect::<Result<_, _>>()?;
+    Ok((callee.to_string(), args))
+}
+
+// add %0, %1 @ 2:5。位置は省略できる
+fn parse_binary(text: &str, checked: bool) -> Result<InstKind, String> {
+    let (text, span) = match text.split_once(" @ ") {
+        Some((text, span)) => (text, parse_span(span)?),
+        None => (text, Span::default()),
+    };
+    let (opcode, args) = text.split_once(' ').unwrap_or((text, ""));
+    let op = BinOp::from_name(opcode).ok_or_else(|| format!("Unknown instruction '{}'", opcode))?;
+    let (lhs, rhs) = split_pair(args, ",")?;
+    Ok(InstKind::Binary {
+        op,
+        lhs: parse_value(lhs)?,
+        rhs: parse_value(rhs)?,
+        checked,
+        span,
+    })
+}
+
+fn parse_span(text: &str) -> Result<Span, String> {
+    let (line, column) = split_pair(text, ":")?;
+    match (line.parse(), column.parse()) {
+        (Ok(line), Ok(column)) => Ok(Span::new(line, column)),
+        _ => Err(format!("Invalid position '{}'", text)),
+    }
+}
+
+// %1: i64
+fn parse_typed_value(text: &str) -> Result<(Value, Ty), String> {
+    let (value, ty) = split_pair(text, ":")?;
+    Ok((parse_value(value)?, parse_type(ty)?))
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    text.trim()
+        .strip_prefix('%')
+        .and_then(|n| n.parse().ok())
+        .map(Value)
+        .ok_or_else(|| format!("Expected a value, found '{}'", text.trim()))
+}
+
+fn parse_block(text: &str) -> Result<BlockId, String> {
+    text.trim()
+        .strip_prefix("bb")
+        .and_then(|n| n.parse().ok())
+        .map(BlockId)
+        .ok_or_else(|| format!("Expected a block, found '{}'", text.trim()))
+}
+
+fn parse_type(text: &str) -> Result<Ty, String> {
+    match text.trim() {
+        "i32" => Ok(Ty::I32),
+        "i64" => Ok(Ty::I64),
+        ty => Err(format!("Unknown type '{}'", ty)),
+    }
+}
+
+// Debug で書き出した文字列リテラル
+fn parse_string(text: &str) -> Result<String, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| format!("Invalid string {}", text))?;
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some('u') => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                let c = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid escape in {}", text))?;
+                result.push(c);
+            }
+            Some(c @ ('\\' | '"' | '\'')) => result.push(c),
+            _ => return Err(format!("Invalid escape in {}", text)),
+        }
+    }
+    Ok(result)
+}
+
+fn split_pair<'a>(text: &'a str, separator: &str) -> Result<(&'a str, &'a str), String> {
+    text.split_once(separator)
+        .map(|(left, right)| (left.trim(), right.trim()))
+        .ok_or_else(|| format!("Expected '{}' in '{}'", separator, text.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let text = "global @x: i64 = 1\n\
+                    #[inline(never)]\n\
+                    function f(%0: i64) -> i64 {\n\
+                    bb0:\n    \
+                        %1: i64 = const -1\n    \
+                        %2: i32 = lt %0, %1 @ 3:9\n    \
+                        branch %2, bb1, bb2\n\
+                    bb1:\n    \
+                        %3: i64 = checked add %0, %1 @ 4:13\n    \
+                        jump bb2\n\
+                    bb2:\n    \
+                        %4: i64 = phi [bb0: %1], [bb1: %3]\n    \
+                        store @x, %4\n    \
+                        print \"a \\\"b\\\"\\n\"\n    \
+                        return %4\n\
+                    }\n\
+                    function main() {\n\
+                    bb0:\n    \
+                        %0: i64 = load @x\n    \
+                        %1: i64 = call f(%0)\n    \
+                        call f(%1)\n    \
+                        print %1\n    \
+                        return\n\
+                    }\n";
+        let program = parse_program(text).expect("Failed to parse MIR");
+        assert_eq!(program.to_string(), text);
+        assert_eq!(
+            program.functions[0].blocks[2].insts[2].kind,
+            InstKind::PrintStr("a \"b\"\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_terminator() {
+        assert_eq!(
+            parse_function("function f() {\nbb0:\n    %0: i64 = const 1\n}"),
+            Err("line 4: bb0 has no terminator".to_string())
+        );
+    }
+}
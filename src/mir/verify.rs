@@ -0,0 +1,434 @@
+use crate::mir::{BlockId, Function, InstKind, Program, Terminator, Ty, Value};
+use std::collections::HashMap;
+
+// MIR が正しい形をしているか確かめる
+// - 値の定義は一度だけで、使う場所を支配している (phi は合流元のブロックの終わりで使う)
+// - phi はブロックの先頭にあり、合流元ごとにちょうど1つの値を持つ
+// - オペランドと結果の型が命令に合っている
+// - 呼び出す関数があり、引数の数と型が合っている
+// - 末尾呼び出しは main の外にあり、呼び出し元の引数の場所に引数が収まる
+// 入口から届かないブロックは調べない
+pub fn verify_program(program: &Program) -> Result<(), String> {
+    let signatures: HashMap<&str, (Vec<Ty>, Option<Ty>)> = program
+        .functions
+        .iter()
+        .map(|function| {
+            let params = function
+                .params
+                .iter()
+                .map(|param| function.value_type(*param))
+                .collect();
+            (function.name.as_str(), (params, function.return_type))
+        })
+        .collect();
+    for function in program.functions.iter().chain([&program.main]) {
+        Verifier {
+            program,
+            signatures: &signatures,
+            function,
+        }
+        .verify()
+        .map_err(|e| format!("function '{}': {}", function.name, e))?;
+    }
+    Ok(())
+}
+
+struct Verifier<'a> {
+    program: &'a Program,
+    signatures: &'a HashMap<&'a str, (Vec<Ty>, Option<Ty>)>,
+    function: &'a Function,
+}
+
+// 値が定義された位置。引数は入口の先頭より前
+#[derive(Clone, Copy)]
+enum Def {
+    Param,
+    Inst(BlockId, usize),
+}
+
+impl Verifier<'_> {
+    fn verify(&self) -> Result<(), String> {
+        let function = self.function;
+        let blocks = function.blocks.len();
+        if blocks == 0 {
+            return Err("no entry block".to_string());
+        }
+        for (i, block) in function.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                if succ.0 >= blocks {
+                    return Err(format!("{}: jump to undefined block {}", BlockId(i), succ));
+                }
+            }
+        }
+
+        let mut defs = HashMap::new();
+        for param in &function.params {
+            self.define(&mut defs, *param, Def::Param)?;
+        }
+        for (i, block) in function.blocks.iter().enumerate() {
+            for (j, inst) in block.insts.iter().enumerate() {
+                if let Some(result) = inst.result {
+                    self.define(&mut defs, result, Def::Inst(BlockId(i), j))
+                        .map_err(|e| format!("{}: {}", BlockId(i), e))?;
+                }
+            }
+        }
+
+        let preds = function.predecessors();
+        let doms = function.dominators();
+        let reachable = function.reachable_blocks();
+        let available = |value: Value, block: BlockId, index: usize| match defs.get(&value) {
+            None => Err(format!("{} is not defined", value)),
+            Some(Def::Param) => Ok(()),
+            Some(Def::Inst(def_block, def_index)) => {
+                if (*def_block == block && *def_index < index)
+                    || (*def_block != block && doms[block.0].contains(def_block))
+                {
+                    Ok(())
+                } else {
+                    Err(format!("{} does not dominate its use", value))
+                }
+            }
+        };
+
+        for (i, block) in function.blocks.iter().enumerate() {
+            let id = BlockId(i);
+            if !reachable.contains(&id) {
+                continue;
+            }
+            let at = |e: String| format!("{}: {}", id, e);
+            let mut phis_done = false;
+            for (j, inst) in block.insts.iter().enumerate() {
+                if let InstKind::Phi(incoming) = &inst.kind {
+                    if phis_done {
+                        return Err(at("phi after a non-phi instruction".to_string()));
+                    }
+                    let mut sources: Vec<BlockId> =
+                        incoming.iter().map(|(pred, _)| *pred).collect();
+                    sources.sort();
+                    let mut expected: Vec<BlockId> = preds[i]
+                        .iter()
+                        .filter(|pred| reachable.contains(pred))
+                        .copied()
+                        .collect();
+                    expected.sort();
+                    if sources != expected {
+                        let expected: Vec<String> =
+                            expected.iter().map(BlockId::to_string).collect();
+                        return Err(at(format!(
+                            "phi does not match the predecessors [{}]",
+                            expected.join(", ")
+                        )));
+                    }
+                    for (pred, value) in incoming {
+                        available(*value, *pred, usize::MAX).map_err(at)?;
+                    }
+                } else {
+                    phis_done = true;
+                    for value in inst.kind.operands() {
+                        available(value, id, j).map_err(at)?;
+                    }
+                }
+                self.check_types(inst.result, &inst.kind).map_err(at)?;
+            }
+            for value in block.terminator.operands() {
+                available(value, id, usize::MAX).map_err(at)?;
+            }
+            self.check_terminator(&block.terminator).map_err(at)?;
+        }
+        Ok(())
+    }
+
+    fn define(&self, defs: &mut HashMap<Value, Def>, value: Value, def: Def) -> Result<(), String> {
+        if value.0 >= self.function.values.len() {
+            return Err(format!("{} has no type", value));
+        }
+        if defs.insert(value, def).is_some() {
+            return Err(format!("{} is defined more than once", value));
+        }
+        Ok(())
+    }
+
+    fn ty(&self, value: Value) -> Ty {
+        self.function.value_type(value)
+    }
+
+    fn check_types(&self, result: Option<Value>, kind: &InstKind) -> Result<(), String> {
+        let result_ty = result.map(|value| self.ty(value));
+        let expected = match kind {
+            InstKind::Const(_) => result_ty.or(Some(Ty::I64)),
+            InstKind::Copy(value) => Some(self.ty(*value)),
+            InstKind::Binary { op, lhs, rhs, .. } => {
+                if self.ty(*lhs) != self.ty(*rhs) {
+                    return Err(format!(
+                        "operands of '{}' have different types {} and {}",
+                        op.name(),
+                        self.ty(*lhs),
+                        self.ty(*rhs)
+                    ));
+                }
+                Some(if op.is_comparison() {
+                    Ty::I32
+                } else {
+                    self.ty(*lhs)
+                })
+            }
+            InstKind::Widen(value) => {
+                if self.ty(*value) != Ty::I32 {
+                    return Err(format!("widen of {} which is not i32", value));
+                }
+                Some(Ty::I64)
+            }
+            InstKind::Phi(incoming) => {
+                let ty = result_ty.unwrap_or(Ty::I64);
+                if let Some((_, value)) = incoming.iter().find(|(_, value)| self.ty(*value) != ty) {
+                    return Err(format!("phi operand {} is not {}", value, ty));
+                }
+                Some(ty)
+            }
+            InstKind::Load(global) => Some(self.global_type(global.0)?),
+            InstKind::Store(global, value) => {
+                let ty = self.global_type(global.0)?;
+                if self.ty(*value) != ty {
+                    return Err(format!("store of {} to a global of type {}", value, ty));
+                }
+                None
+            }
+            InstKind::Call { callee, args } => {
+                let Some((params, return_type)) = self.signatures.get(callee.as_str()) else {
+                    return Err(format!("call to undefined function '{}'", callee));
+                };
+                let args: Vec<Ty> = args.iter().map(|arg| self.ty(*arg)).collect();
+                if &args != params {
+                    return Err(format!("arguments of '{}' do not match", callee));
+                }
+                *return_type
+            }
+            InstKind::Print(_) | InstKind::PrintStr(_) => None,
+        };
+        match (result_ty, expected) {
+            (Some(found), Some(expected)) if found != expected => Err(format!(
+                "{} has type {} but the instruction produces {}",
+                result.expect("result"),
+                found,
+                expected
+            )),
+            (Some(_), None) => Err(format!(
+                "{} is defined by an instruction without a result",
+                result.expect("result")
+            )),
+            // 戻り値を使わない呼び出しは結果を持たなくてよい
+            (None, Some(_)) if !matches!(kind, InstKind::Call { .. }) => {
+                Err("instruction without a result value".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn global_type(&self, index: usize) -> Result<Ty, String> {
+        self.program
+            .globals
+            .get(index)
+            .map(|global| global.ty)
+            .ok_or_else(|| format!("undefined global @{}", index))
+    }
+
+    fn check_terminator(&self, terminator: &Terminator) -> Result<(), String> {
+        match (terminator, self.function.return_type) {
+            (Terminator::Return(Some(value)), Some(ty)) if self.ty(*value) != ty => Err(format!(
+                "return of {} from a function returning {}",
+                value, ty
+            )),
+            (Terminator::Return(Some(_)), None) => {
+                Err("return with a value from a function without a result".to_string())
+            }
+            (Terminator::Return(None), Some(_)) => {
+                Err("return without a value from a function with a result".to_string())
+            }
+            (Terminator::TailCall { callee, args }, _) => self.check_tail_call(callee, args),
+            _ => Ok(()),
+        }
+    }
+
+    // 末尾呼び出しは呼び出し元の引数の場所とリターンアドレスを使うので、main からはできない
+    fn check_tail_call(&self, callee: &str, args: &[Value]) -> Result<(), String> {
+        if std::ptr::eq(self.function, &self.program.main) {
+            return Err("tail call from main".to_string());
+        }
+        let Some((params, return_type)) = self.signatures.get(callee) else {
+            return Err(format!("tail call to undefined function '{}'", callee));
+        };
+        let args: Vec<Ty> = args.iter().map(|arg| self.ty(*arg)).collect();
+        if &args != params {
+            return Err(format!("arguments of '{}' do not match", callee));
+        }
+        if *return_type != self.function.return_type {
+            return Err(format!(
+                "tail call to '{}' returns a different type",
+                callee
+            ));
+        }
+        if args.len() > self.function.params.len() {
+            return Err(format!(
+                "tail call to '{}' passes {} arguments in {} argument slots",
+                callee,
+                args.len(),
+                self.function.params.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::InlineHint;
+    use crate::mir::{BinOp, Block, Inst};
+    use crate::parser::span::Span;
+
+    // function f(%0: i32) -> i32 { bb0: %1 = const 1; %2 = add %0, %1; return %2 }
+    fn function() -> Function {
+        Function {
+            name: "f".to_string(),
+            params: vec![Value(0)],
+            return_type: Some(Ty::I32),
+            values: vec![Ty::I32, Ty::I32, Ty::I32],
+            blocks: vec![Block {
+                insts: vec![
+                    Inst {
+                        result: Some(Value(1)),
+                        kind: InstKind::Const(1),
+                    },
+                    Inst {
+                        result: Some(Value(2)),
+                        kind: InstKind::Binary {
+                            op: BinOp::Add,
+                            lhs: Value(0),
+                            rhs: Value(1),
+                            checked: true,
+                            span: Span::default(),
+                        },
+                    },
+                ],
+                terminator: Terminator::Return(Some(Value(2))),
+            }],
+            inline: InlineHint::Default,
+        }
+    }
+
+    fn verify(function: Function) -> Result<(), String> {
+        verify_functions(vec![function])
+    }
+
+    fn verify_functions(functions: Vec<Function>) -> Result<(), String> {
+        verify_program(&Program {
+            globals: Vec::new(),
+            functions,
+            main: Function {
+                name: "main".to_string(),
+                params: Vec::new(),
+                return_type: None,
+                values: Vec::new(),
+                blocks: vec![Block {
+                    insts: Vec::new(),
+                    terminator: Terminator::Return(None),
+                }],
+                inline: InlineHint::Default,
+            },
+        })
+    }
+
+    #[test]
+    fn test_valid_function() {
+        assert_eq!(verify(function()), Ok(()));
+    }
+
+    #[test]
+    fn test_use_before_definition() {
+        let mut function = function();
+        function.blocks[0].insts.swap(0, 1);
+        assert_eq!(
+            verify(function),
+            Err("function 'f': bb0: %1 does not dominate its use".to_string())
+        );
+    }
+
+    #[test]
+    fn test_operand_type_mismatch() {
+        let mut function = function();
+        function.values[1] = Ty::I64;
+        assert_eq!(
+            verify(function),
+            Err(
+                "function 'f': bb0: operands of 'add' have different types i32 and i64".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_phi_must_match_predecessors() {
+        let mut function = function();
+        function.blocks[0].terminator = Terminator::Jump(BlockId(1));
+        function.values.push(Ty::I32);
+        function.blocks.push(Block {
+            insts: vec![Inst {
+                result: Some(Value(3)),
+                kind: InstKind::Phi(vec![(BlockId(0), Value(2)), (BlockId(2), Value(2))]),
+            }],
+            terminator: Terminator::Return(Some(Value(3))),
+        });
+        assert_eq!(
+            verify(function),
+            Err("function 'f': bb1: phi does not match the predecessors [bb0]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_return_value() {
+        let mut function = function();
+        function.blocks[0].terminator = Terminator::Return(None);
+        assert_eq!(
+            verify(function),
+            Err(
+                "function 'f': bb0: return without a value from a function with a result"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_tail_call_fits_in_argument_slots() {
+        let mut function = function();
+        function.blocks[0].terminator = Terminator::TailCall {
+            callee: "f".to_string(),
+            args: vec![Value(2)],
+        };
+        assert_eq!(verify(function), Ok(()));
+        // 引数のない g から f へは引数を渡す場所がない
+        let g = Function {
+            name: "g".to_string(),
+            params: Vec::new(),
+            return_type: Some(Ty::I32),
+            values: vec![Ty::I32],
+            blocks: vec![Block {
+                insts: vec![Inst {
+                    result: Some(Value(0)),
+                    kind: InstKind::Const(1),
+                }],
+                terminator: Terminator::TailCall {
+                    callee: "f".to_string(),
+                    args: vec![Value(0)],
+                },
+            }],
+            inline: InlineHint::Default,
+        };
+        assert_eq!(
+            verify_functions(vec![self::function(), g]),
+            Err(
+                "function 'g': bb0: tail call to 'f' passes 1 arguments in 0 argument slots"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -1,27 +1,118 @@
+use crate::parser::span::Span;
+use std::fmt;
+
+// プログラム全体。トップレベルには関数定義と文が並ぶ
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Function(FunctionDef),
+    Stmt(Stmt),
+}
+
+// AST ノードの番号。構文解析の後に Parser が振り、名前解決などの結果をノードに対応付けるのに使う
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NodeId(pub usize);
+
+impl NodeId {
+    // まだ番号が振られていないノード
+    pub const DUMMY: NodeId = NodeId(0);
+}
+
+// #[name(arg, arg)]。関数定義と文の前に書ける
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct FunctionDef {
+    pub attrs: Vec<Attribute>,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub body: Block,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+// { stmt; stmt; tail }
+// 末尾のセミコロンのない式(tail)がブロックの値になる
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Option<Box<Expr>>,
+    pub span: Span,
+    // 閉じ括弧 `}` の位置
+    pub close_span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stmt {
+    pub attrs: Vec<Attribute>,
+    pub kind: StmtKind,
+    pub span: Span,
+    pub id: NodeId,
+}
+
 #[derive(Clone, Debug, PartialEq)]
-pub enum Expr {
-    FunctionDef {
+pub enum StmtKind {
+    Let {
+        kind: DeclKind,
+        mutable: bool,
         name: String,
-        params: Vec<(String, String)>,
-        body: Box<Expr>,
+        type_decl: Option<Type>,
+        value: Option<Expr>,
+    },
+    // op があれば複合代入 `name op= value`
+    Assignment {
+        name: String,
+        op: Option<Op>,
+        value: Expr,
+    },
+    WhileLoop {
+        label: Option<String>,
+        condition: Expr,
+        body: Block,
     },
+    Return(Option<Expr>),
+    Break(Option<String>),
+    Continue(Option<String>),
+    Print(Expr),
+    // 式文。if やブロック以外はセミコロンが必要
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    pub id: NodeId,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
     FunctionCall {
         name: String,
         args: Vec<Expr>,
     },
     IfExpr {
         condition: Box<Expr>,
-        consequence: Box<Expr>,
-        alternative: Option<Box<Expr>>,
-    },
-    WhileLoop {
-        condition: Box<Expr>,
-        body: Box<Expr>,
-    },
-    Assignment {
-        name: String,
-        type_decl: Option<String>,
-        value: Box<Expr>,
+        consequence: Block,
+        alternative: Option<Block>,
     },
     BinaryOp {
         left: Box<Expr>,
@@ -30,9 +121,40 @@ pub enum Expr {
     },
     Literal(Literal),
     Variable(String),
-    Block(Vec<Expr>),
-    Return(Box<Expr>),
-    Print(Box<Expr>),
+    Block(Block),
+}
+
+// 型の構文
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    // i32, i64, string など名前だけの型
+    Named(String),
+    // [T; N]。N を省略すると長さの決まらない [T]
+    Array {
+        element: Box<Type>,
+        length: Option<usize>,
+    },
+    // *T / *mut T
+    Pointer {
+        mutable: bool,
+        pointee: Box<Type>,
+    },
+    // function(T, U) -> R
+    Function {
+        params: Vec<Type>,
+        return_type: Option<Box<Type>>,
+    },
+    // Name<T, U>。ジェネリクスはまだ構文だけ
+    Generic {
+        name: String,
+        args: Vec<Type>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeclKind {
+    Let,
+    Const,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -49,6 +171,137 @@ pub enum Op {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
     LessThan,
     GreaterThan,
 }
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt {
+            attrs: Vec::new(),
+            kind,
+            span,
+            id: NodeId::DUMMY,
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr {
+            kind,
+            span,
+            id: NodeId::DUMMY,
+        }
+    }
+
+    // if やブロックのように `}` で終わる式は、文として書くときにセミコロンが要らない
+    pub fn is_block_like(&self) -> bool {
+        matches!(self.kind, ExprKind::IfExpr { .. } | ExprKind::Block(_))
+    }
+}
+
+impl Type {
+    pub fn named(name: &str) -> Self {
+        Type::Named(name.to_string())
+    }
+
+    // 名前だけの型 name か
+    pub fn is_named(&self, name: &str) -> bool {
+        matches!(self, Type::Named(n) if n == name)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Array {
+                element,
+                length: Some(length),
+            } => write!(f, "[{}; {}]", element, length),
+            Type::Array {
+                element,
+                length: None,
+            } => write!(f, "[{}]", element),
+            Type::Pointer { mutable, pointee } => {
+                write!(f, "*{}{}", if *mutable { "mut " } else { "" }, pointee)
+            }
+            Type::Function {
+                params,
+                return_type,
+            } => {
+                write!(f, "function({})", join_types(params))?;
+                if let Some(return_type) = return_type {
+                    write!(f, " -> {}", return_type)?;
+                }
+                Ok(())
+            }
+            Type::Generic { name, args } => write!(f, "{}<{}>", name, join_types(args)),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Op::Add => "+",
+            Op::Subtract => "-",
+            Op::Multiply => "*",
+            Op::Divide => "/",
+            Op::Modulo => "%",
+            Op::LessThan => "<",
+            Op::GreaterThan => ">",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+fn join_types(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(|ty| ty.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Block {
+    pub fn new(stmts: Vec<Stmt>, tail: Option<Expr>, span: Span, close_span: Span) -> Self {
+        Block {
+            stmts,
+            tail: tail.map(Box::new),
+            span,
+            close_span,
+        }
+    }
+}
+
+// 位置と同じく、ノード番号は AST の構造比較には含めない
+impl PartialEq for FunctionDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.attrs == other.attrs
+            && self.name == other.name
+            && self.params == other.params
+            && self.return_type == other.return_type
+            && self.body == other.body
+    }
+}
+
+impl PartialEq for Param {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.ty == other.ty
+    }
+}
+
+impl PartialEq for Stmt {
+    fn eq(&self, other: &Self) -> bool {
+        self.attrs == other.attrs && self.kind == other.kind
+    }
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
@@ -1,4 +1,5 @@
 //#![allow(dead_code)]
+use crate::parser::span::Span;
 use crate::parser::token::Token;
 use nom::{
     //number::complete::{
@@ -50,6 +51,15 @@ use nom::{
     IResult,
 };
 
+// `//` から行末までのコメント。トークン列には含めず、フォーマッタのために別に保持する
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+    // 同じ行の前にトークンがある(行末のコメント)
+    pub trailing: bool,
+}
+
 pub fn display_tokens(tokens: &[Token]) {
     println!("Generated tokens(lexer output):");
     for (index, token) in tokens.iter().enumerate() {
@@ -76,9 +86,9 @@ fn string_literal(input: &str) -> IResult<&str, Token> {
 }
 
 // 空白をスキップする関数
-fn ws<'a, F: 'a, O>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+fn ws<'a, F, O>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
 where
-    F: FnMut(&'a str) -> IResult<&'a str, O>,
+    F: FnMut(&'a str) -> IResult<&'a str, O> + 'a,
 {
     delimited(multispace0, inner, multispace0)
 }
@@ -93,6 +103,11 @@ fn minus(input: &str) -> IResult<&str, Token> {
     map(ws(char('-')), |_| Token::Minus)(input)
 }
 
+// '->'
+fn arrow(input: &str) -> IResult<&str, Token> {
+    map(ws(tag("->")), |_| Token::Arrow)(input)
+}
+
 // '*'
 fn star(input: &str) -> IResult<&str, Token> {
     map(ws(char('*')), |_| Token::Star)(input)
@@ -129,6 +144,17 @@ fn assignment(input: &str) -> IResult<&str, Token> {
     result
 }
 
+// '+=', '-=', '*=', '/=', '%='
+fn compound_assignment(input: &str) -> IResult<&str, Token> {
+    alt((
+        map(ws(tag("+=")), |_| Token::PlusAssign),
+        map(ws(tag("-=")), |_| Token::MinusAssign),
+        map(ws(tag("*=")), |_| Token::StarAssign),
+        map(ws(tag("/=")), |_| Token::SlashAssign),
+        map(ws(tag("%=")), |_| Token::ModuloAssign),
+    ))(input)
+}
+
 // '=='
 fn double_equal(input: &str) -> IResult<&str, Token> {
     map(ws(tag("==")), |_| Token::DoubleEqual)(input)
@@ -154,16 +180,36 @@ fn r_brace(input: &str) -> IResult<&str, Token> {
     map(ws(char('}')), |_| Token::RBrace)(input)
 }
 
+// '['
+fn l_bracket(input: &str) -> IResult<&str, Token> {
+    map(ws(char('[')), |_| Token::LBracket)(input)
+}
+
+// ']'
+fn r_bracket(input: &str) -> IResult<&str, Token> {
+    map(ws(char(']')), |_| Token::RBracket)(input)
+}
+
 // ';'
 fn semicolon(input: &str) -> IResult<&str, Token> {
     map(ws(char(';')), |_| Token::Semicolon)(input)
 }
 
+// ':'
+fn colon(input: &str) -> IResult<&str, Token> {
+    map(ws(char(':')), |_| Token::Colon)(input)
+}
+
 // ','
 fn comma(input: &str) -> IResult<&str, Token> {
     map(ws(char(',')), |_| Token::Comma)(input)
 }
 
+// '#'
+fn hash(input: &str) -> IResult<&str, Token> {
+    map(ws(char('#')), |_| Token::Hash)(input)
+}
+
 // キーワードの解析関数
 fn keyword(input: &str) -> IResult<&str, Token> {
     alt((
@@ -172,6 +218,11 @@ fn keyword(input: &str) -> IResult<&str, Token> {
         map(tag("else"), |_| Token::Else),
         map(tag("while"), |_| Token::While),
         map(tag("return"), |_| Token::Return),
+        map(tag("break"), |_| Token::Break),
+        map(tag("continue"), |_| Token::Continue),
+        map(tag("let"), |_| Token::Let),
+        map(tag("const"), |_| Token::Const),
+        map(tag("mut"), |_| Token::Mut),
     ))(input)
     .and_then(|(next_input, token)| {
         multispace1(next_input).map(|(final_input, _)| (final_input, token))
@@ -195,46 +246,27 @@ fn identifier(input: &str) -> IResult<&str, Token> {
     result.map(|(remaining, ident)| (remaining, Token::Ident(ident.to_string())))
 }
 
-fn type_declaration(input: &str) -> IResult<&str, Token> {
+// ループラベル 'outer を解析
+fn label(input: &str) -> IResult<&str, Token> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = char('\'')(input)?;
     let (input, ident_token) = identifier(input)?;
-    let (input, _) = ws(char(':'))(input)?;
-    let (input, var_type) = ws(alt((tag("i32"), tag("i64"), tag("string"))))(input)?;
-
-    if let Token::Ident(var_name) = ident_token {
-        Ok((
-            input,
-            Token::TypeDeclaration(var_name, var_type.to_string()),
-        ))
-    } else {
-        Err(nom::Err::Error(nom::error::Error::new(
+    let (input, _) = multispace0(input)?;
+    match ident_token {
+        Token::Ident(name) => Ok((input, Token::Label(name))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(
             input,
             nom::error::ErrorKind::Tag,
-        )))
+        ))),
     }
 }
 
-fn print_function(input: &str) -> IResult<&str, Token> {
-    map(ws(tag("print")), |_| Token::Print)(input)
-}
-
-pub fn tokenizer(input: &str) -> IResult<&str, Vec<Token>> {
-    let (input, _) = multispace0(input)?;
-
-    let (remaining_input, mut tokens) = many0(alt((
-        type_declaration,
-        keyword,
-        print_function,
-        map(identifier, |ident: Token| match &ident {
-            Token::Ident(name) if name == "function" => Token::Function,
-            Token::Ident(name) if name == "while" => Token::While,
-            Token::Ident(name) if name == "if" => Token::If,
-            Token::Ident(name) if name == "else" => Token::Else,
-            Token::Ident(name) if name == "return" => Token::Return,
-            _ => ident,
-        }),
-        integer,
-        string_literal,
+// 演算子トークン
+fn operator(input: &str) -> IResult<&str, Token> {
+    alt((
+        compound_assignment,
         plus,
+        arrow,
         minus,
         star,
         slash,
@@ -243,13 +275,55 @@ pub fn tokenizer(input: &str) -> IResult<&str, Vec<Token>> {
         greater_than,
         double_equal,
         assignment,
-        l_paren,
-        r_paren,
-        l_brace,
-        r_brace,
-        semicolon,
-        comma,
-    )))(input)?;
+    ))(input)
+}
+
+// 括弧・区切り記号トークン
+fn punctuation(input: &str) -> IResult<&str, Token> {
+    alt((
+        l_paren, r_paren, l_brace, r_brace, l_bracket, r_bracket, semicolon, colon, comma, hash,
+    ))(input)
+}
+
+fn print_function(input: &str) -> IResult<&str, Token> {
+    map(ws(tag("print")), |_| Token::Print)(input)
+}
+
+// 識別子のうちキーワードになるものを変換
+fn keyword_from_identifier(ident: Token) -> Token {
+    match &ident {
+        Token::Ident(name) if name == "function" => Token::Function,
+        Token::Ident(name) if name == "while" => Token::While,
+        Token::Ident(name) if name == "if" => Token::If,
+        Token::Ident(name) if name == "else" => Token::Else,
+        Token::Ident(name) if name == "return" => Token::Return,
+        Token::Ident(name) if name == "break" => Token::Break,
+        Token::Ident(name) if name == "continue" => Token::Continue,
+        Token::Ident(name) if name == "let" => Token::Let,
+        Token::Ident(name) if name == "const" => Token::Const,
+        Token::Ident(name) if name == "mut" => Token::Mut,
+        _ => ident,
+    }
+}
+
+// トークンを1つ解析
+fn token(input: &str) -> IResult<&str, Token> {
+    alt((
+        keyword,
+        print_function,
+        map(identifier, keyword_from_identifier),
+        label,
+        integer,
+        string_literal,
+        operator,
+        punctuation,
+    ))(input)
+}
+
+pub fn tokenizer(input: &str) -> IResult<&str, Vec<Token>> {
+    let (input, _) = multispace0(input)?;
+
+    let (remaining_input, mut tokens) = many0(token)(input)?;
 
     //println!("Remaining input: {:?}", remaining_input); // 残りの入力を表示
     //println!("Tokens: {:?}", tokens); // 解析したトークンを表示
@@ -262,6 +336,63 @@ pub fn tokenizer(input: &str) -> IResult<&str, Vec<Token>> {
     Ok((remaining_input, tokens))
 }
 
+// トークン列と、各トークンの開始位置を返す
+pub fn tokenize(source: &str) -> Result<(Vec<Token>, Vec<Span>), String> {
+    let (tokens, spans, _) = tokenize_with_comments(source)?;
+    Ok((tokens, spans))
+}
+
+// トークン列、各トークンの開始位置、コメント
+pub type TokensWithComments = (Vec<Token>, Vec<Span>, Vec<Comment>);
+
+// tokenize と同じだが、読み飛ばしたコメントも返す
+pub fn tokenize_with_comments(source: &str) -> Result<TokensWithComments, String> {
+    let mut tokens = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut comments = Vec::new();
+    let mut span = Span::default();
+    let mut input = source;
+
+    loop {
+        let (rest, skipped) = multispace0::<&str, nom::error::Error<&str>>(input)
+            .map_err(|_| format!("{}: Failed to skip whitespace", span))?;
+        span = span.advance(skipped);
+        input = rest;
+
+        if input.is_empty() {
+            tokens.push(Token::EOF);
+            spans.push(span);
+            return Ok((tokens, spans, comments));
+        }
+
+        if input.starts_with("//") {
+            let end = input.find('\n').unwrap_or(input.len());
+            let text = &input[..end];
+            comments.push(Comment {
+                text: text.trim_end().to_string(),
+                span,
+                trailing: spans.last().is_some_and(|last| last.line == span.line),
+            });
+            span = span.advance(text);
+            input = &input[end..];
+            continue;
+        }
+
+        match token(input) {
+            Ok((rest, tok)) => {
+                tokens.push(tok);
+                spans.push(span);
+                span = span.advance(&input[..input.len() - rest.len()]);
+                input = rest;
+            }
+            Err(_) => {
+                let found = input.chars().next().unwrap_or_default();
+                return Err(format!("{}: Unexpected character '{}'", span, found));
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -294,6 +425,30 @@ mod tests {
         assert_eq!(star("*"), Ok(("", Token::Star)));
         assert_eq!(slash("/"), Ok(("", Token::Slash)));
         assert_eq!(modulo("%"), Ok(("", Token::Modulo)));
+        assert_eq!(arrow("->"), Ok(("", Token::Arrow)));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let input = "x += 1; x -= 2; x *= 3; x /= 4; x %= 5; y = x - 1;";
+        let (_, tokens) = tokenizer(input).expect("Tokenization failed");
+        let operators: Vec<Token> = tokens
+            .into_iter()
+            .filter(|token| !matches!(token, Token::Ident(_) | Token::I32(_) | Token::Semicolon))
+            .collect();
+        assert_eq!(
+            operators,
+            vec![
+                Token::PlusAssign,
+                Token::MinusAssign,
+                Token::StarAssign,
+                Token::SlashAssign,
+                Token::ModuloAssign,
+                Token::Assignment,
+                Token::Minus,
+                Token::EOF,
+            ]
+        );
     }
 
     #[test]
@@ -385,6 +540,128 @@ mod tests {
         assert_eq!(integer("2147483648 "), Ok((" ", Token::I64(2147483648))));
     }
 
+    #[test]
+    fn test_labeled_break_and_continue() {
+        let input = "'outer: while (x < 10) { break 'outer; continue; }";
+        assert_eq!(
+            tokenizer(input),
+            Ok((
+                "",
+                vec![
+                    Token::Label("outer".to_string()),
+                    Token::Colon,
+                    Token::While,
+                    Token::LParen,
+                    Token::Ident("x".to_string()),
+                    Token::LessThan,
+                    Token::I32(10),
+                    Token::RParen,
+                    Token::LBrace,
+                    Token::Break,
+                    Token::Label("outer".to_string()),
+                    Token::Semicolon,
+                    Token::Continue,
+                    Token::Semicolon,
+                    Token::RBrace,
+                    Token::EOF,
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_let_declarations() {
+        let input = "let mut x:i64 = 1; const N:i32 = 2; let y = x;";
+        assert_eq!(
+            tokenizer(input),
+            Ok((
+                "",
+                vec![
+                    Token::Let,
+                    Token::Mut,
+                    Token::Ident("x".to_string()),
+                    Token::Colon,
+                    Token::Ident("i64".to_string()),
+                    Token::Assignment,
+                    Token::I32(1),
+                    Token::Semicolon,
+                    Token::Const,
+                    Token::Ident("N".to_string()),
+                    Token::Colon,
+                    Token::Ident("i32".to_string()),
+                    Token::Assignment,
+                    Token::I32(2),
+                    Token::Semicolon,
+                    Token::Let,
+                    Token::Ident("y".to_string()),
+                    Token::Assignment,
+                    Token::Ident("x".to_string()),
+                    Token::Semicolon,
+                    Token::EOF,
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let input = "x:i64 = 10;\n  print(x);";
+        let (tokens, spans) = tokenize(input).expect("Tokenization failed");
+        assert_eq!(tokens.len(), spans.len());
+        let positions: Vec<(usize, usize)> = spans.iter().map(|s| (s.line, s.column)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (1, 7),
+                (1, 9),
+                (1, 11),
+                (2, 3),
+                (2, 8),
+                (2, 9),
+                (2, 10),
+                (2, 11),
+                (2, 12)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unexpected_character() {
+        let result = tokenize("x:i64 = 10;\nprint(x) @");
+        assert_eq!(result, Err("2:10: Unexpected character '@'".to_string()));
+    }
+
+    #[test]
+    fn test_tokenize_comments() {
+        let input = "// header\nx:i64 = 10; // ten\nprint(x);";
+        let (tokens, spans, comments) = tokenize_with_comments(input).expect("Tokenization failed");
+        assert_eq!(tokens.len(), 12);
+        assert_eq!((spans[6].line, spans[6].column), (3, 1));
+        assert_eq!(
+            comments,
+            vec![
+                Comment {
+                    text: "// header".to_string(),
+                    span: Span::new(1, 1),
+                    trailing: false,
+                },
+                Comment {
+                    text: "// ten".to_string(),
+                    span: Span::new(2, 13),
+                    trailing: true,
+                },
+            ]
+        );
+        let positions: Vec<(usize, usize)> = comments
+            .iter()
+            .map(|c| (c.span.line, c.span.column))
+            .collect();
+        assert_eq!(positions, vec![(1, 1), (2, 13)]);
+    }
+
     #[test]
     fn test_type_declaration() {
         let input = "x:i32 = 10;";
@@ -393,7 +670,9 @@ mod tests {
             Ok((
                 "",
                 vec![
-                    Token::TypeDeclaration("x".to_string(), "i32".to_string()),
+                    Token::Ident("x".to_string()),
+                    Token::Colon,
+                    Token::Ident("i32".to_string()),
                     Token::Assignment,
                     Token::I32(10),
                     Token::Semicolon,
@@ -1,20 +1,58 @@
 pub mod ast;
 pub mod lexer;
+pub mod span;
 pub mod token;
+pub mod visit;
 
 //use crate::utils::{
 //debug_token,
 //debug_log,
 //};
-use crate::parser::ast::{Expr, Literal, Op};
+use crate::parser::ast::{
+    Attribute, Block, DeclKind, Expr, ExprKind, FunctionDef, Item, Literal, NodeId, Op, Param,
+    Program, Stmt, StmtKind, Type,
+};
+use crate::parser::span::Span;
 use crate::parser::token::Token;
+use crate::parser::visit::{walk_expr_mut, walk_function_def_mut, walk_stmt_mut, VisitorMut};
 
 pub struct Parser {
     pub tokens: Vec<Token>,
     pub current: usize,
+    // tokens と同じ並びの位置情報(空の場合は位置なし)
+    spans: Vec<Span>,
+    // 解析中のループのラベル(内側が末尾)。break/continue の検査に使う
+    loop_labels: Vec<Option<String>>,
+}
+
+// ブロック内の1要素。セミコロンのない最後の式はブロックの値になる
+enum BlockItem {
+    Stmt(Stmt),
+    Tail(Expr),
 }
 
 impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            spans: Vec::new(),
+            loop_labels: Vec::new(),
+        }
+    }
+
+    // lexer::tokenize の結果から位置情報付きで作成する
+    pub fn with_spans(tokens: Vec<Token>, spans: Vec<Span>) -> Self {
+        Parser {
+            spans,
+            ..Parser::new(tokens)
+        }
+    }
+
+    fn current_span(&self) -> Span {
+        self.spans.get(self.current).copied().unwrap_or_default()
+    }
+
     // return current token
     fn current_token(&self) -> Option<&Token> {
         let token = self.tokens.get(self.current);
@@ -48,68 +86,158 @@ impl Parser {
             }
         }
         Err(format!(
-            "Expected {:?}, found {:?}",
+            "{}: Expected {:?}, found {:?}",
+            self.current_span(),
             expected,
             self.current_token()
         ))
     }
 
-    // 式を解析
-    pub fn parse_statement(&mut self) -> Result<Expr, String> {
+    // 文を解析する。終端のセミコロンも読む
+    pub fn parse_statement(&mut self) -> Result<Stmt, String> {
+        match self.parse_block_item()? {
+            BlockItem::Stmt(stmt) => Ok(stmt),
+            BlockItem::Tail(_) => Err(format!(
+                "{}: Expected ';' after expression, found {:?}",
+                self.current_span(),
+                self.current_token()
+            )),
+        }
+    }
+
+    // 文、またはブロックの値となる末尾の式を解析する
+    // - let/代入/return/break/continue/print と通常の式文はセミコロンで終わる
+    // - while と、if やブロックの式文はセミコロンが要らない
+    // - `}` の直前にあるセミコロンのない式はブロックの値になる
+    fn parse_block_item(&mut self) -> Result<BlockItem, String> {
+        let attrs = self.parse_attributes()?;
+        let span = self.current_span();
+        match self.parse_unattributed_block_item()? {
+            BlockItem::Stmt(stmt) => Ok(BlockItem::Stmt(Stmt { attrs, ..stmt })),
+            BlockItem::Tail(_) if !attrs.is_empty() => Err(format!(
+                "{}: Attributes are not allowed on the value of a block",
+                span
+            )),
+            tail => Ok(tail),
+        }
+    }
+
+    fn parse_unattributed_block_item(&mut self) -> Result<BlockItem, String> {
         println!(
-            "parse_statement: Starting with token {:?}",
+            "parse_block_item: Starting with token {:?}",
             self.current_token()
         );
-        let stmt = match self.current_token() {
-            Some(Token::TypeDeclaration(_, _)) => {
-                println!("parse_statement: Detected TypeDeclaration");
-                self.parse_type_declaration()
+        let span = self.current_span();
+        let kind = match self.current_token() {
+            Some(Token::Ident(_)) if self.peek_token() == Some(&Token::Colon) => {
+                println!("parse_block_item: Detected type declaration");
+                self.parse_type_declaration()?
             }
+            Some(Token::Let) | Some(Token::Const) => self.parse_let_declaration()?,
             Some(Token::While) => {
                 //println!("Parsing WhileLoop");
-                self.parse_while_loop()
-            }
-            Some(Token::Function) => self.parse_function_def(),
-            Some(Token::If) => self.parse_if_expr(),
-            Some(Token::Return) => self.parse_return_statement(),
-            Some(Token::Ident(_)) => match self.peek_token() {
-                Some(Token::LParen) => self.parse_function_call(),
-                Some(Token::Assignment) => self.parse_assignment(),
-                _ => self.parse_expression(),
-            },
-            Some(Token::Print) => self.parse_print_statement(),
-            //Some(Token::LBrace) => self.parse_block(),
-            _ => self.parse_expression(),
+                let stmt = self.parse_while_loop(None)?;
+                return Ok(BlockItem::Stmt(Stmt::new(stmt, span)));
+            }
+            Some(Token::Label(_)) => {
+                let stmt = self.parse_labeled_loop()?;
+                return Ok(BlockItem::Stmt(Stmt::new(stmt, span)));
+            }
+            Some(Token::Break) => self.parse_break_statement()?,
+            Some(Token::Continue) => self.parse_continue_statement()?,
+            Some(Token::Return) => self.parse_return_statement()?,
+            Some(Token::Print) => self.parse_print_statement()?,
+            Some(Token::Function) => {
+                return Err(format!(
+                    "{}: Functions can only be defined at the top level",
+                    span
+                ))
+            }
+            Some(Token::Ident(_))
+                if self
+                    .peek_token()
+                    .is_some_and(|token| Self::assignment_op(token).is_some()) =>
+            {
+                self.parse_assignment()?
+            }
+            Some(Token::If) | Some(Token::LBrace) => {
+                // 文の位置にある if/ブロックはそこで文が終わる
+                let expr = self.parse_primary()?;
+                return self.finish_expression_item(expr);
+            }
+            _ => {
+                let expr = self.parse_expression()?;
+                return self.finish_expression_item(expr);
+            }
         };
-        if let Ok(ref expr) = stmt {
-            println!("parse_statement: Parsed expression {:?}", expr);
-        } else {
-            println!("parse_statement: Failed to parse expression");
+        self.consume_terminator()?;
+        let stmt = Stmt::new(kind, span);
+        println!("parse_block_item: Parsed statement {:?}", stmt);
+        Ok(BlockItem::Stmt(stmt))
+    }
+
+    // 式の後ろを見て、式文かブロックの値かを決める
+    fn finish_expression_item(&mut self, expr: Expr) -> Result<BlockItem, String> {
+        let span = expr.span;
+        match self.current_token() {
+            Some(Token::Semicolon) => {
+                self.next_token(); // Consume the semicolon
+                Ok(BlockItem::Stmt(Stmt::new(StmtKind::Expr(expr), span)))
+            }
+            Some(Token::RBrace) => Ok(BlockItem::Tail(expr)),
+            _ if expr.is_block_like() => Ok(BlockItem::Stmt(Stmt::new(StmtKind::Expr(expr), span))),
+            found => Err(format!(
+                "{}: Expected ';' after expression, found {:?}",
+                self.current_span(),
+                found
+            )),
         }
-        if matches!(self.current_token(), Some(Token::Semicolon)) {
+    }
+
+    fn consume_terminator(&mut self) -> Result<(), String> {
+        if self.current_token() == Some(&Token::Semicolon) {
             self.next_token(); // Consume the semicolon
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: Expected ';' after statement, found {:?}",
+                self.current_span(),
+                self.current_token()
+            ))
         }
-        stmt
     }
 
     // ブロックを解析
-    fn parse_block(&mut self) -> Result<Expr, String> {
+    fn parse_block(&mut self) -> Result<Block, String> {
+        let span = self.current_span();
         let mut statements = Vec::new();
+        let mut tail = None;
 
         self.consume_token(Token::LBrace)?;
 
         // `}` が見つかるまで文を解析し続ける
         while let Some(token) = self.current_token() {
-            if matches!(token, Token::RBrace) {
-                break; // ブロックの終わり
+            match token {
+                Token::RBrace => break, // ブロックの終わり
+                Token::Semicolon => {
+                    self.next_token(); // 空文
+                    continue;
+                }
+                _ => {}
+            }
+            match self.parse_block_item()? {
+                BlockItem::Stmt(stmt) => statements.push(stmt),
+                BlockItem::Tail(expr) => {
+                    tail = Some(expr);
+                    break;
+                }
             }
-            let stmt = self.parse_statement()?;
-            statements.push(stmt);
         }
 
+        let close_span = self.current_span();
         self.consume_token(Token::RBrace)?;
 
-        Ok(Expr::Block(statements))
+        Ok(Block::new(statements, tail, span, close_span))
     }
 
     fn parse_identifier(&mut self) -> Result<String, String> {
@@ -119,23 +247,44 @@ impl Parser {
             self.next_token();
             Ok(name_clone)
         } else {
-            Err("Expected identifier".to_string())
+            Err(format!(
+                "{}: Expected identifier, found {:?}",
+                self.current_span(),
+                self.current_token()
+            ))
         }
     }
 
-    fn parse_parameters(&mut self) -> Result<Vec<(String, String)>, String> {
+    fn parse_parameters(&mut self) -> Result<Vec<Param>, String> {
         let mut parameters = Vec::new();
         self.consume_token(Token::LParen)?;
 
         while self.current_token() != Some(&Token::RParen) {
-            if let Some(Token::TypeDeclaration(ident, type_name)) = self.current_token().cloned() {
-                self.next_token(); // Consume TypeDeclaration
-                parameters.push((ident, type_name));
-                if self.current_token() == Some(&Token::Comma) {
-                    self.consume_token(Token::Comma)?;
+            let span = self.current_span();
+            let (name, ty) = self.parse_typed_name()?;
+            let ty = match ty {
+                Some(ty) => ty,
+                None => {
+                    return Err(format!(
+                        "{}: Parameter '{}' needs a type annotation",
+                        span, name
+                    ))
                 }
-            } else {
-                return Err("Expected type declaration in function parameters".to_string());
+            };
+            parameters.push(Param {
+                name,
+                ty,
+                span,
+                id: NodeId::DUMMY,
+            });
+            if self.current_token() == Some(&Token::Comma) {
+                self.consume_token(Token::Comma)?;
+            } else if self.current_token() != Some(&Token::RParen) {
+                return Err(format!(
+                    "{}: Expected ',' or ')' after parameter, found {:?}",
+                    self.current_span(),
+                    self.current_token()
+                ));
             }
         }
 
@@ -153,12 +302,12 @@ impl Parser {
         result
     }
 
-    fn parse_print_statement(&mut self) -> Result<Expr, String> {
+    fn parse_print_statement(&mut self) -> Result<StmtKind, String> {
         self.consume_token(Token::Print)?;
         self.consume_token(Token::LParen)?;
         let expr = self.parse_expression()?;
         self.consume_token(Token::RParen)?;
-        Ok(Expr::Print(Box::new(expr)))
+        Ok(StmtKind::Print(expr))
     }
 
     fn parse_binary_operator(&mut self) -> Result<Expr, String> {
@@ -169,6 +318,7 @@ impl Parser {
             Some(Token::Minus) => Some(Op::Subtract),
             Some(Token::Star) => Some(Op::Multiply),
             Some(Token::Slash) => Some(Op::Divide),
+            Some(Token::Modulo) => Some(Op::Modulo),
             Some(Token::LessThan) => Some(Op::LessThan),
             Some(Token::GreaterThan) => Some(Op::GreaterThan),
             // Some(Token::LessThanEqual) => Some(Op::LessThanEqual),
@@ -179,27 +329,221 @@ impl Parser {
         } {
             self.next_token(); // Skip the operator
             let right = self.parse_primary()?;
-            expr = Expr::BinaryOp {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
+            let span = expr.span;
+            expr = Expr::new(
+                ExprKind::BinaryOp {
+                    left: Box::new(expr),
+                    op,
+                    right: Box::new(right),
+                },
+                span,
+            );
         }
 
         Ok(expr)
     }
 
-    fn parse_assignment(&mut self) -> Result<Expr, String> {
+    fn parse_assignment(&mut self) -> Result<StmtKind, String> {
         let ident = self.parse_identifier()?;
-        self.consume_token(Token::Assignment)?;
+        let op = match self.current_token().and_then(Self::assignment_op) {
+            Some(op) => op,
+            None => {
+                return Err(format!(
+                    "{}: Expected an assignment operator, found {:?}",
+                    self.current_span(),
+                    self.current_token()
+                ))
+            }
+        };
+        self.next_token(); // Consume the operator
         let value = self.parse_expression()?;
-        Ok(Expr::Assignment {
+        Ok(StmtKind::Assignment {
             name: ident,
-            type_decl: None, // ここでは型情報なし
-            value: Box::new(value),
+            op,
+            value,
         })
     }
 
+    // `=` は Some(None)、`+=` などは Some(Some(op))
+    fn assignment_op(token: &Token) -> Option<Option<Op>> {
+        match token {
+            Token::Assignment => Some(None),
+            Token::PlusAssign => Some(Some(Op::Add)),
+            Token::MinusAssign => Some(Some(Op::Subtract)),
+            Token::StarAssign => Some(Some(Op::Multiply)),
+            Token::SlashAssign => Some(Some(Op::Divide)),
+            Token::ModuloAssign => Some(Some(Op::Modulo)),
+            _ => None,
+        }
+    }
+
+    // let [mut] x[:type] [= value] / const X:type = value
+    fn parse_let_declaration(&mut self) -> Result<StmtKind, String> {
+        let kind = if self.current_token() == Some(&Token::Const) {
+            DeclKind::Const
+        } else {
+            DeclKind::Let
+        };
+        self.next_token(); // Consume let/const
+
+        let mutable = self.current_token() == Some(&Token::Mut);
+        if mutable {
+            if kind == DeclKind::Const {
+                return Err(format!(
+                    "{}: Constants cannot be declared 'mut'",
+                    self.current_span()
+                ));
+            }
+            self.next_token(); // Consume mut
+        }
+
+        let span = self.current_span();
+        if !matches!(self.current_token(), Some(Token::Ident(_))) {
+            return Err(format!(
+                "{}: Expected a variable name in declaration, found {:?}",
+                span,
+                self.current_token()
+            ));
+        }
+        let (name, type_decl) = self.parse_typed_name()?;
+
+        let value = if self.current_token() == Some(&Token::Assignment) {
+            self.next_token(); // Consume Assignment
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        match (kind, &type_decl, &value) {
+            (DeclKind::Const, None, _) => Err(format!(
+                "{}: Constant '{}' must have a type annotation",
+                span, name
+            )),
+            (DeclKind::Const, _, None) => {
+                Err(format!("{}: Constant '{}' must be initialized", span, name))
+            }
+            (DeclKind::Let, None, None) => Err(format!(
+                "{}: Type annotation needed for '{}' declared without a value",
+                span, name
+            )),
+            _ => Ok(StmtKind::Let {
+                kind,
+                mutable,
+                name,
+                type_decl,
+                value,
+            }),
+        }
+    }
+
+    // name または name:type
+    fn parse_typed_name(&mut self) -> Result<(String, Option<Type>), String> {
+        let name = self.parse_identifier()?;
+        if self.current_token() != Some(&Token::Colon) {
+            return Ok((name, None));
+        }
+        self.next_token(); // Consume Colon
+        let ty = self.parse_type()?;
+        Ok((name, Some(ty)))
+    }
+
+    // 型を解析する
+    // - i64, string などの名前
+    // - [T; N] / [T] の配列
+    // - *T / *mut T のポインタ
+    // - function(T, U) -> R の関数型
+    // - Name<T, U> のジェネリクス
+    pub fn parse_type(&mut self) -> Result<Type, String> {
+        let span = self.current_span();
+        match self.current_token().cloned() {
+            Some(Token::Ident(name)) => {
+                self.next_token();
+                if self.current_token() != Some(&Token::LessThan) {
+                    return Ok(Type::Named(name));
+                }
+                self.next_token(); // Consume '<'
+                let args = self.parse_type_list(Token::GreaterThan)?;
+                if args.is_empty() {
+                    return Err(format!(
+                        "{}: Generic type '{}' needs at least one type argument",
+                        span, name
+                    ));
+                }
+                Ok(Type::Generic { name, args })
+            }
+            Some(Token::LBracket) => {
+                self.next_token();
+                let element = self.parse_type()?;
+                let length = if self.current_token() == Some(&Token::Semicolon) {
+                    self.next_token();
+                    match self.current_token().cloned() {
+                        Some(Token::I32(length)) if length >= 0 => {
+                            self.next_token();
+                            Some(length as usize)
+                        }
+                        found => {
+                            return Err(format!(
+                                "{}: Expected an array length, found {:?}",
+                                self.current_span(),
+                                found
+                            ))
+                        }
+                    }
+                } else {
+                    None
+                };
+                self.consume_token(Token::RBracket)?;
+                Ok(Type::Array {
+                    element: Box::new(element),
+                    length,
+                })
+            }
+            Some(Token::Star) => {
+                self.next_token();
+                let mutable = self.current_token() == Some(&Token::Mut);
+                if mutable {
+                    self.next_token();
+                }
+                let pointee = self.parse_type()?;
+                Ok(Type::Pointer {
+                    mutable,
+                    pointee: Box::new(pointee),
+                })
+            }
+            Some(Token::Function) => {
+                self.next_token();
+                self.consume_token(Token::LParen)?;
+                let params = self.parse_type_list(Token::RParen)?;
+                let return_type = if self.current_token() == Some(&Token::Arrow) {
+                    self.next_token();
+                    Some(Box::new(self.parse_type()?))
+                } else {
+                    None
+                };
+                Ok(Type::Function {
+                    params,
+                    return_type,
+                })
+            }
+            found => Err(format!("{}: Expected a type, found {:?}", span, found)),
+        }
+    }
+
+    // カンマ区切りの型を close まで読む。close も読み進める
+    fn parse_type_list(&mut self, close: Token) -> Result<Vec<Type>, String> {
+        let mut types = Vec::new();
+        while self.current_token() != Some(&close) {
+            types.push(self.parse_type()?);
+            if self.current_token() == Some(&Token::Comma) {
+                self.next_token();
+            } else {
+                break;
+            }
+        }
+        self.consume_token(close)?;
+        Ok(types)
+    }
+
     fn parse_arguments(&mut self) -> Result<Vec<Expr>, String> {
         let mut args = Vec::new();
 
@@ -218,35 +562,83 @@ impl Parser {
         Ok(args)
     }
 
-    fn parse_function_def(&mut self) -> Result<Expr, String> {
+    fn parse_function_def(&mut self) -> Result<FunctionDef, String> {
         println!("Parsing function definition.");
+        let span = self.current_span();
         self.consume_token(Token::Function)?;
 
         let name = self.parse_identifier()?;
 
         let parameters = self.parse_parameters()?;
-        let body = self.parse_block()?;
-
-        Ok(Expr::FunctionDef {
+        let return_type = self.parse_return_type()?;
+        // 関数本体から外側のループへ break/continue はできない
+        let enclosing_loops = std::mem::take(&mut self.loop_labels);
+        let body = self.parse_block();
+        self.loop_labels = enclosing_loops;
+        let body = body?;
+
+        Ok(FunctionDef {
+            attrs: Vec::new(),
             name,
             params: parameters,
-            body: Box::new(body),
+            return_type,
+            body,
+            span,
+            id: NodeId::DUMMY,
         })
     }
 
+    // #[name] / #[name(arg, arg)] を0個以上解析する
+    fn parse_attributes(&mut self) -> Result<Vec<Attribute>, String> {
+        let mut attrs = Vec::new();
+        while self.current_token() == Some(&Token::Hash) {
+            let span = self.current_span();
+            self.next_token(); // Consume Hash
+            self.consume_token(Token::LBracket)?;
+            let name = self.parse_identifier()?;
+            let mut args = Vec::new();
+            if self.current_token() == Some(&Token::LParen) {
+                self.next_token(); // Consume LParen
+                while self.current_token() != Some(&Token::RParen) {
+                    args.push(self.parse_identifier()?);
+                    if self.current_token() == Some(&Token::Comma) {
+                        self.next_token();
+                    } else {
+                        break;
+                    }
+                }
+                self.consume_token(Token::RParen)?;
+            }
+            self.consume_token(Token::RBracket)?;
+            attrs.push(Attribute { name, args, span });
+        }
+        Ok(attrs)
+    }
+
+    // 省略可能な `-> type` を解析する。省略時は値を返さない関数
+    fn parse_return_type(&mut self) -> Result<Option<Type>, String> {
+        if self.current_token() != Some(&Token::Arrow) {
+            return Ok(None);
+        }
+        self.consume_token(Token::Arrow)?;
+        self.parse_type().map(Some)
+    }
+
     fn parse_function_call(&mut self) -> Result<Expr, String> {
+        let span = self.current_span();
         let name = self.parse_identifier()?;
         self.consume_token(Token::LParen)?;
-        let args = if self.peek_token() != Some(&Token::RParen) {
+        let args = if self.current_token() != Some(&Token::RParen) {
             self.parse_arguments()?
         } else {
             Vec::new()
         };
         self.consume_token(Token::RParen)?;
-        Ok(Expr::FunctionCall { name, args })
+        Ok(Expr::new(ExprKind::FunctionCall { name, args }, span))
     }
 
     fn parse_if_expr(&mut self) -> Result<Expr, String> {
+        let span = self.current_span();
         self.consume_token(Token::If)?;
         self.consume_token(Token::LParen)?;
         let condition = self.parse_expression()?;
@@ -254,107 +646,173 @@ impl Parser {
         let consequence = self.parse_block()?;
         let alternative = if self.current_token() == Some(&Token::Else) {
             self.consume_token(Token::Else)?;
-            Some(Box::new(self.parse_block()?))
+            Some(self.parse_block()?)
         } else {
             None
         };
-        Ok(Expr::IfExpr {
-            condition: Box::new(condition),
-            consequence: Box::new(consequence),
-            alternative,
-        })
+        Ok(Expr::new(
+            ExprKind::IfExpr {
+                condition: Box::new(condition),
+                consequence,
+                alternative,
+            },
+            span,
+        ))
     }
 
-    fn parse_while_loop(&mut self) -> Result<Expr, String> {
+    fn parse_while_loop(&mut self, label: Option<String>) -> Result<StmtKind, String> {
         self.consume_token(Token::While)?;
         self.consume_token(Token::LParen)?;
         let condition = self.parse_expression()?;
         self.consume_token(Token::RParen)?;
-        let body = self.parse_block()?;
-        Ok(Expr::WhileLoop {
-            condition: Box::new(condition),
-            body: Box::new(body),
+        self.loop_labels.push(label.clone());
+        let body = self.parse_block();
+        self.loop_labels.pop();
+        Ok(StmtKind::WhileLoop {
+            label,
+            condition,
+            body: body?,
         })
     }
 
-    fn parse_return_statement(&mut self) -> Result<Expr, String> {
-        self.consume_token(Token::Return)?;
-        let value = self.parse_expression()?;
-        self.consume_token(Token::Semicolon)?;
-        Ok(Expr::Return(Box::new(value)))
+    // 'outer: while (...) { ... }
+    fn parse_labeled_loop(&mut self) -> Result<StmtKind, String> {
+        let label = match self.current_token().cloned() {
+            Some(Token::Label(name)) => name,
+            _ => return Err(format!("{}: Expected a loop label", self.current_span())),
+        };
+        self.next_token();
+        self.consume_token(Token::Colon)?;
+        if self.current_token() != Some(&Token::While) {
+            return Err(format!(
+                "{}: Label '{} must be followed by a while loop, found {:?}",
+                self.current_span(),
+                label,
+                self.current_token()
+            ));
+        }
+        self.parse_while_loop(Some(label))
     }
 
-    fn parse_type_declaration(&mut self) -> Result<Expr, String> {
-        if let Some(Token::TypeDeclaration(ident, type_name)) = self.current_token().cloned() {
-            println!(
-                "parse_type_declaration: Current token is {:?}",
-                self.current_token()
-            );
-            self.next_token(); // Consume TypeDeclaration
-
-            if self.current_token() == Some(&Token::Assignment) {
-                self.next_token(); // Consume Assignment
-                let value = self.parse_expression()?;
-
-                match (&type_name[..], &value) {
-                    ("i64", Expr::Literal(Literal::I32(num))) => {
-                        // Convert i32 literal to i64 if assigned to an i64 variable
-                        Ok(Expr::Assignment {
-                            name: ident,
-                            type_decl: Some(type_name),
-                            value: Box::new(Expr::Literal(Literal::I64(*num as i64))),
-                        })
-                    },
-                    ("i32", Expr::Literal(Literal::I32(_))) | ("i64", Expr::Literal(Literal::I64(_))) => {
-                        // No conversion needed, types match
-                        Ok(Expr::Assignment {
-                            name: ident,
-                            type_decl: Some(type_name),
-                            value: Box::new(value),
-                        })
-                    },
-                    _ => {
-                        Err(format!("Type mismatch: variable '{}' declared as '{}' cannot be initialized with value '{:?}'", ident, type_name, value))
-                    }
-                }
-            } else {
-                Err(format!(
-                    "Expected an assignment after type declaration for '{}'",
-                    ident
-                ))
+    // break/continue の後ろの省略可能なラベルを解析し、囲んでいるループがあるか確認する
+    // keyword_span はキーワードの位置。ループの外にあるときのエラーに使う
+    fn parse_loop_target(
+        &mut self,
+        keyword: &str,
+        keyword_span: Span,
+    ) -> Result<Option<String>, String> {
+        let span = self.current_span();
+        let label = match self.current_token().cloned() {
+            Some(Token::Label(name)) => {
+                self.next_token();
+                Some(name)
             }
+            _ => None,
+        };
+        if self.loop_labels.is_empty() {
+            return Err(format!("{}: '{}' outside of a loop", keyword_span, keyword));
+        }
+        if let Some(name) = &label {
+            if !self.loop_labels.contains(&Some(name.clone())) {
+                return Err(format!(
+                    "{}: Use of undeclared label '{} in '{}'",
+                    span, name, keyword
+                ));
+            }
+        }
+        Ok(label)
+    }
+
+    fn parse_break_statement(&mut self) -> Result<StmtKind, String> {
+        let span = self.current_span();
+        self.consume_token(Token::Break)?;
+        let label = self.parse_loop_target("break", span)?;
+        Ok(StmtKind::Break(label))
+    }
+
+    fn parse_continue_statement(&mut self) -> Result<StmtKind, String> {
+        let span = self.current_span();
+        self.consume_token(Token::Continue)?;
+        let label = self.parse_loop_target("continue", span)?;
+        Ok(StmtKind::Continue(label))
+    }
+
+    fn parse_return_statement(&mut self) -> Result<StmtKind, String> {
+        self.consume_token(Token::Return)?;
+        // `return;` は値を返さない
+        let value = if self.current_token() == Some(&Token::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        Ok(StmtKind::Return(value))
+    }
+
+    // 型付きの宣言 `x:i64 = 10` は `let mut x:i64 = 10` と同じ意味
+    fn parse_type_declaration(&mut self) -> Result<StmtKind, String> {
+        let span = self.current_span();
+        let (ident, type_decl) = self.parse_typed_name()?;
+        let ty = match type_decl {
+            Some(ty) => ty,
+            None => return Err(format!("{}: Expected a type declaration", span)),
+        };
+        println!(
+            "parse_type_declaration: Declared '{}' with type {}",
+            ident, ty
+        );
+
+        if self.current_token() == Some(&Token::Assignment) {
+            self.next_token(); // Consume Assignment
+            let value = self.parse_expression()?;
+            Ok(StmtKind::Let {
+                kind: DeclKind::Let,
+                mutable: true,
+                name: ident,
+                type_decl: Some(ty),
+                value: Some(value),
+            })
         } else {
-            Err("Expected a type declaration".to_string())
+            Err(format!(
+                "{}: Expected an assignment after type declaration for '{}'",
+                span, ident
+            ))
         }
     }
 
     fn parse_primary(&mut self) -> Result<Expr, String> {
+        let span = self.current_span();
         let token_clone = if let Some(token) = self.current_token() {
             println!("Parsing primary expression, current token: {:?}", token);
             token.clone()
         } else {
-            return Err("Unexpected end of tokens".to_string());
+            return Err(format!("{}: Unexpected end of tokens", span));
         };
 
         match &token_clone {
             Token::I32(value) => {
                 //println!("Parsing integer literal: {}", value);
                 self.next_token();
-                Ok(Expr::Literal(Literal::I32(*value)))
+                Ok(Expr::new(ExprKind::Literal(Literal::I32(*value)), span))
             }
             Token::I64(value) => {
                 //println!("Parsing integer literal: {}", value);
                 self.next_token();
-                Ok(Expr::Literal(Literal::I64(*value)))
+                Ok(Expr::new(ExprKind::Literal(Literal::I64(*value)), span))
             }
             Token::String(value) => {
                 //println!("Parsing integer literal: {}", value);
                 self.next_token();
-                Ok(Expr::Literal(Literal::String(value.clone())))
+                Ok(Expr::new(
+                    ExprKind::Literal(Literal::String(value.clone())),
+                    span,
+                ))
+            }
+            Token::Ident(_) if self.peek_token() == Some(&Token::LParen) => {
+                self.parse_function_call()
             }
             Token::Ident(_) => {
                 let ident = self.parse_identifier()?;
-                Ok(Expr::Variable(ident))
+                Ok(Expr::new(ExprKind::Variable(ident), span))
             }
             Token::LParen => {
                 self.next_token();
@@ -362,36 +820,59 @@ impl Parser {
                 self.consume_token(Token::RParen)?;
                 Ok(expr)
             }
+            Token::If => self.parse_if_expr(),
+            Token::LBrace => {
+                let block = self.parse_block()?;
+                Ok(Expr::new(ExprKind::Block(block), span))
+            }
             _ => {
                 println!("Failed to parse primary with token: {:?}", token_clone);
-                Err("Unexpected token in primary expression".to_string())
+                Err(format!(
+                    "{}: Unexpected token in primary expression: {:?}",
+                    span, token_clone
+                ))
             }
         }
     }
 
-    pub fn parse_tokens(&mut self) -> Result<Expr, String> {
-        let mut statements = Vec::new();
+    pub fn parse_tokens(&mut self) -> Result<Program, String> {
+        let mut items = Vec::new();
         println!("parse_tokens: Starting token parsing loop");
 
         while let Some(token) = self.current_token() {
-            if matches!(token, Token::EOF) {
-                println!("parse_tokens: Reached EOF, breaking out of the loop");
-                break; // EOF
+            match token {
+                Token::EOF => {
+                    println!("parse_tokens: Reached EOF, breaking out of the loop");
+                    break; // EOF
+                }
+                Token::Semicolon => {
+                    self.next_token(); // 空文
+                    continue;
+                }
+                _ => {}
             }
 
             println!(
                 "parse_tokens: Current token before parse: {:?}",
                 self.current_token()
             );
-            let stmt = self.parse_statement();
+            let item = match self.parse_attributes() {
+                Ok(attrs) if self.current_token() == Some(&Token::Function) => self
+                    .parse_function_def()
+                    .map(|function| Item::Function(FunctionDef { attrs, ..function })),
+                Ok(attrs) => self
+                    .parse_statement()
+                    .map(|stmt| Item::Stmt(Stmt { attrs, ..stmt })),
+                Err(e) => Err(e),
+            };
 
-            match stmt {
-                Ok(expr) => {
+            match item {
+                Ok(item) => {
                     println!(
-                        "parse_tokens: Parsed statement and adding to block: {:?}",
-                        expr
+                        "parse_tokens: Parsed item and adding to program: {:?}",
+                        item
                     );
-                    statements.push(expr);
+                    items.push(item);
                 }
                 Err(e) => {
                     println!("parse_tokens: Error parsing statement: {}", e);
@@ -400,16 +881,107 @@ impl Parser {
             }
         }
 
-        println!("parse_tokens: Final parsed block: {:?}", statements);
-        Ok(Expr::Block(statements))
+        println!("parse_tokens: Final parsed program: {:?}", items);
+        let mut program = Program { items };
+        NodeNumbering { next: 1 }.visit_program_mut(&mut program);
+        Ok(program)
+    }
+}
+
+// 関数、引数、文、式に出現順の番号を振る
+struct NodeNumbering {
+    next: usize,
+}
+
+impl NodeNumbering {
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next);
+        self.next += 1;
+        id
+    }
+}
+
+impl VisitorMut for NodeNumbering {
+    fn visit_function_def_mut(&mut self, function: &mut FunctionDef) {
+        function.id = self.next_id();
+        walk_function_def_mut(self, function);
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        param.id = self.next_id();
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        stmt.id = self.next_id();
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        expr.id = self.next_id();
+        walk_expr_mut(self, expr);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::lexer::tokenizer;
+    use crate::parser::lexer::{tokenize, tokenizer};
     use crate::parser::token::Token;
+
+    fn expr(kind: ExprKind) -> Expr {
+        Expr::new(kind, Span::default())
+    }
+
+    fn stmt(kind: StmtKind) -> Stmt {
+        Stmt::new(kind, Span::default())
+    }
+
+    fn int(value: i32) -> Expr {
+        expr(ExprKind::Literal(Literal::I32(value)))
+    }
+
+    fn var(name: &str) -> Expr {
+        expr(ExprKind::Variable(name.to_string()))
+    }
+
+    fn binary(left: Expr, op: Op, right: Expr) -> Expr {
+        expr(ExprKind::BinaryOp {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        })
+    }
+
+    fn call(name: &str, args: Vec<Expr>) -> Expr {
+        expr(ExprKind::FunctionCall {
+            name: name.to_string(),
+            args,
+        })
+    }
+
+    fn block(stmts: Vec<StmtKind>, tail: Option<Expr>) -> Block {
+        Block::new(
+            stmts.into_iter().map(stmt).collect(),
+            tail,
+            Span::default(),
+            Span::default(),
+        )
+    }
+
+    fn program(stmts: Vec<StmtKind>) -> Program {
+        Program {
+            items: stmts
+                .into_iter()
+                .map(|kind| Item::Stmt(stmt(kind)))
+                .collect(),
+        }
+    }
+
+    fn parse(source: &str) -> Result<Program, String> {
+        let (tokens, spans) = tokenize(source).expect("Tokenization failed");
+        Parser::with_spans(tokens, spans).parse_tokens()
+    }
+
     // 二項演算のテスト: 加算
     #[test]
     fn test_binary_addition() {
@@ -420,15 +992,11 @@ mod tests {
             Token::Semicolon,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let result = parser.parse_tokens();
 
         assert!(result.is_ok());
-        let expected = Expr::Block(vec![Expr::BinaryOp {
-            left: Box::new(Expr::Literal(Literal::I32(10))),
-            op: Op::Add,
-            right: Box::new(Expr::Literal(Literal::I32(20))),
-        }]);
+        let expected = program(vec![StmtKind::Expr(binary(int(10), Op::Add, int(20)))]);
         assert_eq!(result.unwrap(), expected);
     }
 
@@ -442,15 +1010,11 @@ mod tests {
             Token::Semicolon,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let result = parser.parse_tokens();
 
         assert!(result.is_ok());
-        let expected = Expr::Block(vec![Expr::BinaryOp {
-            left: Box::new(Expr::Literal(Literal::I32(30))),
-            op: Op::Subtract,
-            right: Box::new(Expr::Literal(Literal::I32(20))),
-        }]);
+        let expected = program(vec![StmtKind::Expr(binary(int(30), Op::Subtract, int(20)))]);
         assert_eq!(result.unwrap(), expected);
     }
 
@@ -464,15 +1028,11 @@ mod tests {
             Token::Semicolon,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let result = parser.parse_tokens();
 
         assert!(result.is_ok());
-        let expected = Expr::Block(vec![Expr::BinaryOp {
-            left: Box::new(Expr::Literal(Literal::I32(5))),
-            op: Op::Multiply,
-            right: Box::new(Expr::Literal(Literal::I32(4))),
-        }]);
+        let expected = program(vec![StmtKind::Expr(binary(int(5), Op::Multiply, int(4)))]);
         assert_eq!(result.unwrap(), expected);
     }
 
@@ -486,15 +1046,11 @@ mod tests {
             Token::Semicolon,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let result = parser.parse_tokens();
 
         assert!(result.is_ok());
-        let expected = Expr::Block(vec![Expr::BinaryOp {
-            left: Box::new(Expr::Literal(Literal::I32(20))),
-            op: Op::Divide,
-            right: Box::new(Expr::Literal(Literal::I32(5))),
-        }]);
+        let expected = program(vec![StmtKind::Expr(binary(int(20), Op::Divide, int(5)))]);
         assert_eq!(result.unwrap(), expected);
     }
 
@@ -504,9 +1060,13 @@ mod tests {
             Token::Function,
             Token::Ident("add".to_string()),
             Token::LParen,
-            Token::TypeDeclaration("x".to_string(), "i32".to_string()),
+            Token::Ident("x".to_string()),
+            Token::Colon,
+            Token::Ident("i32".to_string()),
             Token::Comma,
-            Token::TypeDeclaration("y".to_string(), "i32".to_string()),
+            Token::Ident("y".to_string()),
+            Token::Colon,
+            Token::Ident("i32".to_string()),
             Token::RParen,
             Token::LBrace,
             Token::Return,
@@ -524,7 +1084,7 @@ mod tests {
             Token::Semicolon,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
 
         let result = parser.parse_tokens();
         assert!(
@@ -551,7 +1111,7 @@ mod tests {
             Token::RBrace,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
 
         let result = parser.parse_tokens();
         assert!(
@@ -580,7 +1140,7 @@ mod tests {
             Token::RBrace,
             Token::EOF,
         ];
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let result = parser.parse_tokens();
         assert!(
             result.is_ok(),
@@ -589,6 +1149,317 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compound_assignment() {
+        let assign = |op, value| StmtKind::Assignment {
+            name: "x".to_string(),
+            op,
+            value,
+        };
+        assert_eq!(
+            parse("x += 1; x -= 2; x *= 3; x /= 4; x %= 5; x = x % 6;"),
+            Ok(program(vec![
+                assign(Some(Op::Add), int(1)),
+                assign(Some(Op::Subtract), int(2)),
+                assign(Some(Op::Multiply), int(3)),
+                assign(Some(Op::Divide), int(4)),
+                assign(Some(Op::Modulo), int(5)),
+                assign(None, binary(var("x"), Op::Modulo, int(6))),
+            ]))
+        );
+        assert!(parse("x += ;").is_err());
+    }
+
+    #[test]
+    fn test_function_return_type() {
+        let source = r#"
+        function add(x:i64, y:i64) -> i64 {
+            return x + y;
+        };
+
+        print(add(1, two()));
+        "#;
+        let (_, tokens) = tokenizer(source).expect("Tokenization failed");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_tokens().expect("Failed to parse tokens");
+
+        let expected_ast = Program {
+            items: vec![
+                Item::Function(FunctionDef {
+                    name: "add".to_string(),
+                    params: vec![
+                        Param {
+                            name: "x".to_string(),
+                            ty: Type::named("i64"),
+                            span: Span::default(),
+                            id: NodeId::DUMMY,
+                        },
+                        Param {
+                            name: "y".to_string(),
+                            ty: Type::named("i64"),
+                            span: Span::default(),
+                            id: NodeId::DUMMY,
+                        },
+                    ],
+                    return_type: Some(Type::named("i64")),
+                    body: block(
+                        vec![StmtKind::Return(Some(binary(var("x"), Op::Add, var("y"))))],
+                        None,
+                    ),
+                    span: Span::default(),
+                    id: NodeId::DUMMY,
+                    attrs: Vec::new(),
+                }),
+                Item::Stmt(stmt(StmtKind::Print(call(
+                    "add",
+                    vec![int(1), call("two", vec![])],
+                )))),
+            ],
+        };
+
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_let_declarations() {
+        let source = "let x:i64 = 1; let mut y = x; const N:i32 = 3; let z:i32;";
+        let (tokens, spans) = tokenize(source).expect("Tokenization failed");
+        let mut parser = Parser::with_spans(tokens, spans);
+        let ast = parser.parse_tokens().expect("Failed to parse tokens");
+
+        let expected_ast = program(vec![
+            StmtKind::Let {
+                kind: DeclKind::Let,
+                mutable: false,
+                name: "x".to_string(),
+                type_decl: Some(Type::named("i64")),
+                value: Some(int(1)),
+            },
+            StmtKind::Let {
+                kind: DeclKind::Let,
+                mutable: true,
+                name: "y".to_string(),
+                type_decl: None,
+                value: Some(var("x")),
+            },
+            StmtKind::Let {
+                kind: DeclKind::Const,
+                mutable: false,
+                name: "N".to_string(),
+                type_decl: Some(Type::named("i32")),
+                value: Some(int(3)),
+            },
+            StmtKind::Let {
+                kind: DeclKind::Let,
+                mutable: false,
+                name: "z".to_string(),
+                type_decl: Some(Type::named("i32")),
+                value: None,
+            },
+        ]);
+        assert_eq!(ast, expected_ast);
+
+        match &ast.items[1] {
+            Item::Stmt(stmt) => assert_eq!((stmt.span.line, stmt.span.column), (1, 16)),
+            other => panic!("Expected a let declaration, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_type_syntax() {
+        let source = "let a:[i64; 4]; let b:*mut i32; let c:function(i32, i64) -> i64; \
+                      let d:Vec<[i32]>; let e:*Map<string, function()>;";
+        let ast = parse(source).expect("Failed to parse tokens");
+        let types: Vec<Type> = ast
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Stmt(Stmt {
+                    kind:
+                        StmtKind::Let {
+                            type_decl: Some(ty),
+                            ..
+                        },
+                    ..
+                }) => ty.clone(),
+                other => panic!("Expected a typed declaration, found {:?}", other),
+            })
+            .collect();
+
+        let i32_type = Type::named("i32");
+        let i64_type = Type::named("i64");
+        assert_eq!(
+            types,
+            vec![
+                Type::Array {
+                    element: Box::new(i64_type.clone()),
+                    length: Some(4),
+                },
+                Type::Pointer {
+                    mutable: true,
+                    pointee: Box::new(i32_type.clone()),
+                },
+                Type::Function {
+                    params: vec![i32_type.clone(), i64_type.clone()],
+                    return_type: Some(Box::new(i64_type.clone())),
+                },
+                Type::Generic {
+                    name: "Vec".to_string(),
+                    args: vec![Type::Array {
+                        element: Box::new(i32_type),
+                        length: None,
+                    }],
+                },
+                Type::Pointer {
+                    mutable: false,
+                    pointee: Box::new(Type::Generic {
+                        name: "Map".to_string(),
+                        args: vec![
+                            Type::named("string"),
+                            Type::Function {
+                                params: vec![],
+                                return_type: None,
+                            },
+                        ],
+                    }),
+                },
+            ]
+        );
+        let printed: Vec<String> = types.iter().map(Type::to_string).collect();
+        assert_eq!(
+            printed,
+            vec![
+                "[i64; 4]",
+                "*mut i32",
+                "function(i32, i64) -> i64",
+                "Vec<[i32]>",
+                "*Map<string, function()>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_syntax_errors() {
+        let tests = vec![
+            ("let a:;", "1:7: Expected a type, found Some(Semicolon)"),
+            (
+                "let a:[i64; n];",
+                "1:13: Expected an array length, found Some(Ident(\"n\"))",
+            ),
+            (
+                "let a:[i64;",
+                "1:12: Expected an array length, found Some(EOF)",
+            ),
+            (
+                "let a:Vec<>;",
+                "1:7: Generic type 'Vec' needs at least one type argument",
+            ),
+            (
+                "let a:Vec<i32;",
+                "1:14: Expected GreaterThan, found Some(Semicolon)",
+            ),
+            (
+                "function f(x) {}",
+                "1:12: Parameter 'x' needs a type annotation",
+            ),
+            (
+                "function f(x:i32 y:i32) {}",
+                "1:18: Expected ',' or ')' after parameter, found Some(Ident(\"y\"))",
+            ),
+            (
+                "function f() -> {}",
+                "1:17: Expected a type, found Some(LBrace)",
+            ),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(parse(source), Err(expected.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_invalid_let_declarations() {
+        let tests = vec![
+            "let x;",
+            "const N = 1;",
+            "const N:i32;",
+            "const mut N:i32 = 1;",
+        ];
+
+        for test in tests {
+            let (_, tokens) = tokenizer(test).expect("Tokenization failed");
+            let mut parser = Parser::new(tokens);
+            assert!(parser.parse_tokens().is_err(), "Should reject: {}", test);
+        }
+    }
+
+    #[test]
+    fn test_labeled_break_and_continue() {
+        let source = r#"
+        'outer: while (i < 10) {
+            while (j < 10) {
+                if (j > 5) { continue 'outer; }
+                break;
+            };
+        };
+        "#;
+        let (_, tokens) = tokenizer(source).expect("Tokenization failed");
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse_tokens().expect("Failed to parse tokens");
+
+        let expected_ast = program(vec![StmtKind::WhileLoop {
+            label: Some("outer".to_string()),
+            condition: binary(var("i"), Op::LessThan, int(10)),
+            body: block(
+                vec![StmtKind::WhileLoop {
+                    label: None,
+                    condition: binary(var("j"), Op::LessThan, int(10)),
+                    body: block(
+                        vec![
+                            StmtKind::Expr(expr(ExprKind::IfExpr {
+                                condition: Box::new(binary(var("j"), Op::GreaterThan, int(5))),
+                                consequence: block(
+                                    vec![StmtKind::Continue(Some("outer".to_string()))],
+                                    None,
+                                ),
+                                alternative: None,
+                            })),
+                            StmtKind::Break(None),
+                        ],
+                        None,
+                    ),
+                }],
+                None,
+            ),
+        }]);
+
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let tests = vec![
+            "break;",
+            "continue;",
+            "if (x < 1) { break; }",
+            "while (x < 1) { break 'outer; }",
+            "while (x < 1) { function f() { break; } }",
+        ];
+
+        for test in tests {
+            let (_, tokens) = tokenizer(test).expect("Tokenization failed");
+            let mut parser = Parser::new(tokens);
+            assert!(parser.parse_tokens().is_err(), "Should reject: {}", test);
+        }
+
+        // エラーはキーワードの位置を指す
+        let (tokens, spans) = tokenize("if (x < 1) {\n  continue;\n}\nbreak;").unwrap();
+        let mut parser = Parser::with_spans(tokens, spans);
+        assert_eq!(
+            parser.parse_tokens().unwrap_err(),
+            "2:3: 'continue' outside of a loop"
+        );
+    }
+
     // #[test]
     // fn test_string_concatenation() {
     //     let tokens = vec![
@@ -626,30 +1497,39 @@ mod tests {
         add(100, 200);
         "#;
         let (_, tokens) = tokenizer(source).expect("Tokenization failed");
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let ast = parser.parse_tokens().expect("Failed to parse tokens");
 
-        let expected_ast = Expr::Block(vec![
-            Expr::FunctionDef {
-                name: "add".to_string(),
-                params: vec![
-                    ("x".to_string(), "i32".to_string()),
-                    ("y".to_string(), "i32".to_string()),
-                ],
-                body: Box::new(Expr::Block(vec![Expr::Return(Box::new(Expr::BinaryOp {
-                    left: Box::new(Expr::Variable("x".to_string())),
-                    op: Op::Add,
-                    right: Box::new(Expr::Variable("y".to_string())),
-                }))])),
-            },
-            Expr::FunctionCall {
-                name: "add".to_string(),
-                args: vec![
-                    Expr::Literal(Literal::I32(100)),
-                    Expr::Literal(Literal::I32(200)),
-                ],
-            },
-        ]);
+        let expected_ast = Program {
+            items: vec![
+                Item::Function(FunctionDef {
+                    name: "add".to_string(),
+                    params: vec![
+                        Param {
+                            name: "x".to_string(),
+                            ty: Type::named("i32"),
+                            span: Span::default(),
+                            id: NodeId::DUMMY,
+                        },
+                        Param {
+                            name: "y".to_string(),
+                            ty: Type::named("i32"),
+                            span: Span::default(),
+                            id: NodeId::DUMMY,
+                        },
+                    ],
+                    return_type: None,
+                    body: block(
+                        vec![StmtKind::Return(Some(binary(var("x"), Op::Add, var("y"))))],
+                        None,
+                    ),
+                    span: Span::default(),
+                    id: NodeId::DUMMY,
+                    attrs: Vec::new(),
+                }),
+                Item::Stmt(stmt(StmtKind::Expr(call("add", vec![int(100), int(200)])))),
+            ],
+        };
 
         assert_eq!(ast, expected_ast, "AST did not match the expected output");
     }
@@ -667,7 +1547,7 @@ mod tests {
 
         for test in tests {
             let (_, tokens) = tokenizer(test).expect("Tokenization failed");
-            let mut parser = Parser { tokens, current: 0 };
+            let mut parser = Parser::new(tokens);
             assert!(parser.parse_tokens().is_ok(), "Failed to parse: {}", test);
         }
     }
@@ -678,18 +1558,20 @@ mod tests {
         let (_, tokens) = tokenizer(input).expect("Tokenization failed");
         println!("Tokens generated: {:?}", tokens);
 
-        let mut parser = Parser { tokens, current: 0 };
+        let mut parser = Parser::new(tokens);
         let result = parser.parse_statement();
 
         assert!(
             result.is_ok(),
             "Failed to parse type declaration and assignment"
         );
-        let expected = Expr::Assignment {
+        let expected = stmt(StmtKind::Let {
+            kind: DeclKind::Let,
+            mutable: true,
             name: "x".to_string(),
-            type_decl: Some("i32".to_string()),
-            value: Box::new(Expr::Literal(Literal::I32(10))),
-        };
+            type_decl: Some(Type::named("i32")),
+            value: Some(int(10)),
+        });
 
         assert_eq!(
             result.unwrap(),
@@ -697,4 +1579,107 @@ mod tests {
             "Parsed expression does not match expected"
         );
     }
+
+    #[test]
+    fn test_block_tail_values() {
+        let source = r#"
+        function max(x:i64, y:i64) -> i64 {
+            if (x > y) { x } else { y }
+        }
+        let z:i64 = { let w:i64 = 1; w + 2 };
+        "#;
+        let ast = parse(source).expect("Failed to parse tokens");
+
+        let if_expr = expr(ExprKind::IfExpr {
+            condition: Box::new(binary(var("x"), Op::GreaterThan, var("y"))),
+            consequence: block(vec![], Some(var("x"))),
+            alternative: Some(block(vec![], Some(var("y")))),
+        });
+        match &ast.items[0] {
+            Item::Function(function) => assert_eq!(function.body, block(vec![], Some(if_expr))),
+            other => panic!("Expected a function, found {:?}", other),
+        }
+
+        let initializer = expr(ExprKind::Block(block(
+            vec![StmtKind::Let {
+                kind: DeclKind::Let,
+                mutable: false,
+                name: "w".to_string(),
+                type_decl: Some(Type::named("i64")),
+                value: Some(int(1)),
+            }],
+            Some(binary(var("w"), Op::Add, int(2))),
+        )));
+        assert_eq!(
+            ast.items[1],
+            Item::Stmt(stmt(StmtKind::Let {
+                kind: DeclKind::Let,
+                mutable: false,
+                name: "z".to_string(),
+                type_decl: Some(Type::named("i64")),
+                value: Some(initializer),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_statement_terminators() {
+        let accepted = vec![
+            "function f() { print(1); }\nf();",
+            "function f() { print(1); };",
+            "if (x < 1) { print(1); } print(2);",
+            "while (x < 1) { x = x + 1; } print(x);",
+            "{ print(1); } print(2);",
+            "f(1);;",
+        ];
+        for source in accepted {
+            assert!(parse(source).is_ok(), "Should accept: {}", source);
+        }
+
+        let rejected = vec![
+            "print(1)",
+            "x = 1 print(x);",
+            "f(1) f(2);",
+            "let x:i64 = 1",
+            "10 + 20",
+            "while (x < 1) { x = x + 1 }",
+            "function f() -> i64 { return 1 }",
+            "if (x < 1) { function g() { } }",
+        ];
+        for source in rejected {
+            assert!(parse(source).is_err(), "Should reject: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_missing_semicolon_reports_position() {
+        let result = parse("let x:i64 = 1;\nprint(x)\nprint(2);");
+        assert_eq!(
+            result,
+            Err("3:1: Expected ';' after statement, found Some(Print)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_node_ids_are_unique() {
+        let ast = parse("function f(x:i64) -> i64 { x + 1 }; print(f(2));").unwrap();
+        let function = match &ast.items[0] {
+            Item::Function(function) => function,
+            item => panic!("Unexpected item: {:?}", item),
+        };
+        let body = function.body.tail.as_ref().unwrap();
+        let ids = [
+            function.id,
+            function.params[0].id,
+            body.id,
+            match &ast.items[1] {
+                Item::Stmt(stmt) => stmt.id,
+                item => panic!("Unexpected item: {:?}", item),
+            },
+        ];
+        assert!(ids.iter().all(|id| *id != NodeId::DUMMY));
+        for (i, id) in ids.iter().enumerate() {
+            assert!(!ids[i + 1..].contains(id), "Duplicate node id: {:?}", id);
+        }
+    }
 }
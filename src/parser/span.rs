@@ -0,0 +1,44 @@
+use std::fmt;
+
+// ソースコード上の位置(1始まりの行と列)
+#[derive(Clone, Copy, Debug)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Span { line, column }
+    }
+
+    // text を読み進めた後の位置を返す
+    pub fn advance(self, text: &str) -> Span {
+        text.chars().fold(self, |span, c| {
+            if c == '\n' {
+                Span::new(span.line + 1, 1)
+            } else {
+                Span::new(span.line, span.column + 1)
+            }
+        })
+    }
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span::new(1, 1)
+    }
+}
+
+// 位置情報は AST の構造比較には含めない
+impl PartialEq for Span {
+    fn eq(&self, _other: &Span) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
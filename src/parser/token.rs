@@ -5,9 +5,13 @@ pub enum Token {
     I32(i32),
     I64(i64),
     String(String),
-    Ident(String),                   // identifier
-    TypeDeclaration(String, String), // x:i32
+    Ident(String), // identifier
     Assignment,
+    PlusAssign,   // +=
+    MinusAssign,  // -=
+    StarAssign,   // *=
+    SlashAssign,  // /=
+    ModuloAssign, // %=
     Plus,
     Minus,
     Star,
@@ -20,15 +24,25 @@ pub enum Token {
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Semicolon,
     Colon,
     Comma,
+    Arrow,
+    Hash, // #
     Function,
     If,
     Else,
     While,
     Print,
     Return,
+    Break,
+    Continue,
+    Let,
+    Const,
+    Mut,
+    Label(String), // 'outer
     EOF,
 }
 
@@ -39,8 +53,12 @@ impl fmt::Display for Token {
             Token::I64(value) => write!(f, "Int({})", value),
             Token::String(value) => write!(f, "String(\"{}\")", value),
             Token::Ident(value) => write!(f, "Ident({})", value),
-            Token::TypeDeclaration(name, ty) => write!(f, "TypeDeclaration({}, {})", name, ty),
             Token::Assignment => write!(f, "Assignment"),
+            Token::PlusAssign => write!(f, "PlusAssign"),
+            Token::MinusAssign => write!(f, "MinusAssign"),
+            Token::StarAssign => write!(f, "StarAssign"),
+            Token::SlashAssign => write!(f, "SlashAssign"),
+            Token::ModuloAssign => write!(f, "ModuloAssign"),
             Token::Plus => write!(f, "Plus"),
             Token::Minus => write!(f, "Minus"),
             Token::Star => write!(f, "Star"),
@@ -53,15 +71,25 @@ impl fmt::Display for Token {
             Token::RParen => write!(f, "RParen"),
             Token::LBrace => write!(f, "LBrace"),
             Token::RBrace => write!(f, "RBrace"),
+            Token::LBracket => write!(f, "LBracket"),
+            Token::RBracket => write!(f, "RBracket"),
             Token::Semicolon => write!(f, "Semicolon"),
             Token::Colon => write!(f, "Colon"),
             Token::Comma => write!(f, "Comma"),
+            Token::Arrow => write!(f, "Arrow"),
+            Token::Hash => write!(f, "Hash"),
             Token::Function => write!(f, "Function"),
             Token::If => write!(f, "If"),
             Token::Else => write!(f, "Else"),
             Token::While => write!(f, "While"),
             Token::Print => write!(f, "Print"),
             Token::Return => write!(f, "Return"),
+            Token::Break => write!(f, "Break"),
+            Token::Continue => write!(f, "Continue"),
+            Token::Let => write!(f, "Let"),
+            Token::Const => write!(f, "Const"),
+            Token::Mut => write!(f, "Mut"),
+            Token::Label(name) => write!(f, "Label('{})", name),
             Token::EOF => write!(f, "EOF"),
         }
     }
@@ -0,0 +1,476 @@
+use crate::parser::ast::{
+    Block, Expr, ExprKind, FunctionDef, Item, Param, Program, Stmt, StmtKind,
+};
+
+// AST を読み取りで辿る
+// 各 visit_* の既定の実装は walk_* で子を順に訪れる。上書きした場合、子を辿るには walk_* を呼ぶ
+pub trait Visitor<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_item(&mut self, item: &'ast Item) {
+        walk_item(self, item);
+    }
+
+    fn visit_function_def(&mut self, function: &'ast FunctionDef) {
+        walk_function_def(self, function);
+    }
+
+    fn visit_param(&mut self, _param: &'ast Param) {}
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_program<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, program: &'ast Program) {
+    for item in &program.items {
+        visitor.visit_item(item);
+    }
+}
+
+pub fn walk_item<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, item: &'ast Item) {
+    match item {
+        Item::Function(function) => visitor.visit_function_def(function),
+        Item::Stmt(stmt) => visitor.visit_stmt(stmt),
+    }
+}
+
+pub fn walk_function_def<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    function: &'ast FunctionDef,
+) {
+    for param in &function.params {
+        visitor.visit_param(param);
+    }
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, block: &'ast Block) {
+    for stmt in &block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+    if let Some(tail) = &block.tail {
+        visitor.visit_expr(tail);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, stmt: &'ast Stmt) {
+    match &stmt.kind {
+        StmtKind::Let { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::Assignment { value, .. } => visitor.visit_expr(value),
+        StmtKind::WhileLoop {
+            condition, body, ..
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        StmtKind::Break(_) | StmtKind::Continue(_) => {}
+        StmtKind::Print(expr) | StmtKind::Expr(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, expr: &'ast Expr) {
+    match &expr.kind {
+        ExprKind::FunctionCall { args, .. } => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::IfExpr {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(consequence);
+            if let Some(alt) = alternative {
+                visitor.visit_block(alt);
+            }
+        }
+        ExprKind::BinaryOp { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+        ExprKind::Block(block) => visitor.visit_block(block),
+    }
+}
+
+// AST をその場で書き換えながら辿る
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        walk_item_mut(self, item);
+    }
+
+    fn visit_function_def_mut(&mut self, function: &mut FunctionDef) {
+        walk_function_def_mut(self, function);
+    }
+
+    fn visit_param_mut(&mut self, _param: &mut Param) {}
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for item in &mut program.items {
+        visitor.visit_item_mut(item);
+    }
+}
+
+pub fn walk_item_mut<V: VisitorMut + ?Sized>(visitor: &mut V, item: &mut Item) {
+    match item {
+        Item::Function(function) => visitor.visit_function_def_mut(function),
+        Item::Stmt(stmt) => visitor.visit_stmt_mut(stmt),
+    }
+}
+
+pub fn walk_function_def_mut<V: VisitorMut + ?Sized>(visitor: &mut V, function: &mut FunctionDef) {
+    for param in &mut function.params {
+        visitor.visit_param_mut(param);
+    }
+    visitor.visit_block_mut(&mut function.body);
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for stmt in &mut block.stmts {
+        visitor.visit_stmt_mut(stmt);
+    }
+    if let Some(tail) = &mut block.tail {
+        visitor.visit_expr_mut(tail);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Let { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        StmtKind::Assignment { value, .. } => visitor.visit_expr_mut(value),
+        StmtKind::WhileLoop {
+            condition, body, ..
+        } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_block_mut(body);
+        }
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        StmtKind::Break(_) | StmtKind::Continue(_) => {}
+        StmtKind::Print(expr) | StmtKind::Expr(expr) => visitor.visit_expr_mut(expr),
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::FunctionCall { args, .. } => {
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+        }
+        ExprKind::IfExpr {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_block_mut(consequence);
+            if let Some(alt) = alternative {
+                visitor.visit_block_mut(alt);
+            }
+        }
+        ExprKind::BinaryOp { left, right, .. } => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        }
+        ExprKind::Literal(_) | ExprKind::Variable(_) => {}
+        ExprKind::Block(block) => visitor.visit_block_mut(block),
+    }
+}
+
+// AST を受け取り、作り直した AST を返す
+// 既定の実装は子を fold して同じ形のノードを組み立て直す
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+
+    fn fold_item(&mut self, item: Item) -> Item {
+        fold_item(self, item)
+    }
+
+    fn fold_function_def(&mut self, function: FunctionDef) -> FunctionDef {
+        fold_function_def(self, function)
+    }
+
+    fn fold_param(&mut self, param: Param) -> Param {
+        param
+    }
+
+    fn fold_block(&mut self, block: Block) -> Block {
+        fold_block(self, block)
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        fold_stmt(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, program: Program) -> Program {
+    Program {
+        items: program
+            .items
+            .into_iter()
+            .map(|item| folder.fold_item(item))
+            .collect(),
+    }
+}
+
+pub fn fold_item<F: Fold + ?Sized>(folder: &mut F, item: Item) -> Item {
+    match item {
+        Item::Function(function) => Item::Function(folder.fold_function_def(function)),
+        Item::Stmt(stmt) => Item::Stmt(folder.fold_stmt(stmt)),
+    }
+}
+
+pub fn fold_function_def<F: Fold + ?Sized>(folder: &mut F, function: FunctionDef) -> FunctionDef {
+    FunctionDef {
+        params: function
+            .params
+            .into_iter()
+            .map(|param| folder.fold_param(param))
+            .collect(),
+        body: folder.fold_block(function.body),
+        ..function
+    }
+}
+
+pub fn fold_block<F: Fold + ?Sized>(folder: &mut F, block: Block) -> Block {
+    Block {
+        stmts: block
+            .stmts
+            .into_iter()
+            .map(|stmt| folder.fold_stmt(stmt))
+            .collect(),
+        tail: block.tail.map(|tail| Box::new(folder.fold_expr(*tail))),
+        ..block
+    }
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(folder: &mut F, stmt: Stmt) -> Stmt {
+    let kind = match stmt.kind {
+        StmtKind::Let {
+            kind,
+            mutable,
+            name,
+            type_decl,
+            value,
+        } => StmtKind::Let {
+            kind,
+            mutable,
+            name,
+            type_decl,
+            value: value.map(|value| folder.fold_expr(value)),
+        },
+        StmtKind::Assignment { name, op, value } => StmtKind::Assignment {
+            name,
+            op,
+            value: folder.fold_expr(value),
+        },
+        StmtKind::WhileLoop {
+            label,
+            condition,
+            body,
+        } => StmtKind::WhileLoop {
+            label,
+            condition: folder.fold_expr(condition),
+            body: folder.fold_block(body),
+        },
+        StmtKind::Return(value) => StmtKind::Return(value.map(|value| folder.fold_expr(value))),
+        StmtKind::Break(label) => StmtKind::Break(label),
+        StmtKind::Continue(label) => StmtKind::Continue(label),
+        StmtKind::Print(expr) => StmtKind::Print(folder.fold_expr(expr)),
+        StmtKind::Expr(expr) => StmtKind::Expr(folder.fold_expr(expr)),
+    };
+    Stmt { kind, ..stmt }
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    let kind = match expr.kind {
+        ExprKind::FunctionCall { name, args } => ExprKind::FunctionCall {
+            name,
+            args: args.into_iter().map(|arg| folder.fold_expr(arg)).collect(),
+        },
+        ExprKind::IfExpr {
+            condition,
+            consequence,
+            alternative,
+        } => ExprKind::IfExpr {
+            condition: Box::new(folder.fold_expr(*condition)),
+            consequence: folder.fold_block(consequence),
+            alternative: alternative.map(|alt| folder.fold_block(alt)),
+        },
+        ExprKind::BinaryOp { left, op, right } => ExprKind::BinaryOp {
+            left: Box::new(folder.fold_expr(*left)),
+            op,
+            right: Box::new(folder.fold_expr(*right)),
+        },
+        ExprKind::Literal(literal) => ExprKind::Literal(literal),
+        ExprKind::Variable(name) => ExprKind::Variable(name),
+        ExprKind::Block(block) => ExprKind::Block(folder.fold_block(block)),
+    };
+    Expr { kind, ..expr }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::{Literal, Op};
+    use crate::test_util::parse;
+
+    const SOURCE: &str = "function add(x:i64, y:i64) -> i64 { x + y }
+        let mut i = 0;
+        while (i < 10) {
+            if (i > 5) { print(add(i, 1)); } else { i = { i + 2 }; }
+        }
+        print(i * 3);";
+
+    // 変数の参照を数える
+    struct VariableCounter<'ast> {
+        names: Vec<&'ast str>,
+        params: usize,
+    }
+
+    impl<'ast> Visitor<'ast> for VariableCounter<'ast> {
+        fn visit_param(&mut self, _param: &'ast Param) {
+            self.params += 1;
+        }
+
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            if let ExprKind::Variable(name) = &expr.kind {
+                self.names.push(name);
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn test_visitor_reaches_every_expression() {
+        let program = parse(SOURCE);
+        let mut counter = VariableCounter {
+            names: Vec::new(),
+            params: 0,
+        };
+        counter.visit_program(&program);
+        assert_eq!(counter.params, 2);
+        assert_eq!(counter.names, vec!["x", "y", "i", "i", "i", "i", "i"]);
+    }
+
+    // 変数の名前を付け替える
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            if let ExprKind::Variable(name) = &mut expr.kind {
+                name.insert_str(0, "renamed_");
+            }
+            walk_expr_mut(self, expr);
+        }
+
+        fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+            if let StmtKind::Assignment { name, .. } | StmtKind::Let { name, .. } = &mut stmt.kind {
+                name.insert_str(0, "renamed_");
+            }
+            walk_stmt_mut(self, stmt);
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut_rewrites_in_place() {
+        let mut program = parse("let mut i = 0; while (i < 10) { i = i + 1; }");
+        Rename.visit_program_mut(&mut program);
+        assert_eq!(
+            program,
+            parse("let mut renamed_i = 0; while (renamed_i < 10) { renamed_i = renamed_i + 1; }")
+        );
+    }
+
+    // リテラル同士の加算を畳み込む
+    struct AddLiterals;
+
+    impl Fold for AddLiterals {
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            let expr = fold_expr(self, expr);
+            match &expr.kind {
+                ExprKind::BinaryOp {
+                    left,
+                    op: Op::Add,
+                    right,
+                } => match (&left.kind, &right.kind) {
+                    (ExprKind::Literal(Literal::I32(a)), ExprKind::Literal(Literal::I32(b))) => {
+                        Expr::new(ExprKind::Literal(Literal::I32(a + b)), expr.span)
+                    }
+                    _ => expr,
+                },
+                _ => expr,
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold_rebuilds_tree() {
+        let program = parse("function f() -> i32 { 1 + 2 + 3 } print({ 4 + 5 });");
+        assert_eq!(
+            AddLiterals.fold_program(program),
+            parse("function f() -> i32 { 6 } print({ 9 });")
+        );
+    }
+
+    #[test]
+    fn test_default_fold_is_identity() {
+        struct Identity;
+        impl Fold for Identity {}
+        let program = parse(SOURCE);
+        assert_eq!(Identity.fold_program(program.clone()), program);
+    }
+}
@@ -0,0 +1,70 @@
+// テスト用のパイプライン。パスの順序は main と同じにする
+use crate::analysis::attributes::check_attributes;
+use crate::analysis::cfg::{build_control_flow, ControlFlow};
+use crate::analysis::consteval::fold_constants;
+use crate::analysis::init::check_initialization;
+use crate::analysis::lint::{check_lints, LintConfig};
+use crate::analysis::mutability::check_mutability;
+use crate::analysis::resolve::{resolve_names, Resolutions};
+use crate::analysis::returns::check_function_returns;
+use crate::analysis::tail::check_tail_calls;
+use crate::analysis::typeck::{check_types, TypeTable};
+use crate::parser::ast::Program;
+use crate::parser::lexer::tokenize;
+use crate::parser::Parser;
+
+pub(crate) fn parse(source: &str) -> Program {
+    let (tokens, spans) = tokenize(source).expect("Tokenization failed");
+    let mut parser = Parser::with_spans(tokens, spans);
+    parser.parse_tokens().expect("Failed to parse tokens")
+}
+
+// 検査をすべて通した AST と解析結果
+pub(crate) struct Analysis {
+    pub ast: Program,
+    pub resolutions: Resolutions,
+    pub types: TypeTable,
+    pub control_flow: ControlFlow,
+}
+
+pub(crate) fn analyze(source: &str) -> Analysis {
+    let mut ast = parse(source);
+    check_attributes(&ast).expect("Failed to check attributes");
+    let resolutions = resolve_names(&ast).expect("Failed to resolve names");
+    check_mutability(&ast).expect("Failed to check mutability");
+    check_initialization(&ast, &resolutions).expect("Failed to check initialization");
+    let types = check_types(&ast, &resolutions).expect("Failed to check types");
+    fold_constants(&mut ast, &types).expect("Failed to evaluate constants");
+    let control_flow = build_control_flow(&ast);
+    check_function_returns(&ast, &types, &control_flow).expect("Failed to check returns");
+    check_tail_calls(&ast, &resolutions, &types).expect("Failed to check tail calls");
+    let diagnostics = check_lints(&ast, &resolutions, &control_flow, &LintConfig::default());
+    if let Some(error) = diagnostics.iter().find(|d| d.is_error()) {
+        panic!("Lint error: {}", error);
+    }
+    Analysis {
+        ast,
+        resolutions,
+        types,
+        control_flow,
+    }
+}
+
+pub(crate) fn lower_to_hir(source: &str) -> crate::hir::Program {
+    let analysis = analyze(source);
+    crate::hir::lower::lower_program(
+        &analysis.ast,
+        &analysis.resolutions,
+        &analysis.types,
+        &analysis.control_flow,
+    )
+    .expect("Failed to lower to HIR")
+}
+
+// 最適化前の MIR。検証も済ませる
+pub(crate) fn lower_to_mir(source: &str) -> crate::mir::Program {
+    let program =
+        crate::mir::lower::lower_program(&lower_to_hir(source)).expect("Failed to lower to MIR");
+    crate::mir::verify::verify_program(&program).expect("Invalid MIR");
+    program
+}
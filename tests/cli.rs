@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// 一時ディレクトリにソースを書いてコンパイラを実行する。output.asm もそこに書かれる
+fn compile(name: &str, source: &str, args: &[&str]) -> Output {
+    let dir = std::env::temp_dir().join(format!("compiler-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed to create a temporary directory");
+    let file: PathBuf = dir.join("main.sim");
+    std::fs::write(&file, source).expect("Failed to write the source file");
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .args(args)
+        .arg(&file)
+        .current_dir(&dir)
+        .output()
+        .expect("Failed to run the compiler");
+    std::fs::remove_dir_all(&dir).ok();
+    output
+}
+
+#[test]
+fn test_exit_status() {
+    let unused = "let x:i64 = 1;";
+    assert_eq!(compile("warn", unused, &[]).status.code(), Some(0));
+    assert_eq!(
+        compile("deny-warnings", unused, &["--deny-warnings"])
+            .status
+            .code(),
+        Some(1)
+    );
+    assert_eq!(
+        compile("deny", unused, &["-D", "unused_variables"])
+            .status
+            .code(),
+        Some(1)
+    );
+    assert_eq!(
+        compile("type-error", "let x:i32 = \"a\";", &[])
+            .status
+            .code(),
+        Some(1)
+    );
+    assert_eq!(
+        compile("usage", unused, &["--no-such-option"])
+            .status
+            .code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn test_print_passes() {
+    let source = "function f(x:i64) -> i64 { x + 1 } print(f(1));";
+    let stdout = |args: &[&str]| {
+        let output = compile("print-passes", source, args);
+        assert_eq!(output.status.code(), Some(0));
+        String::from_utf8(output.stdout).expect("Output is not UTF-8")
+    };
+    let quiet = stdout(&["-O2"]);
+    assert!(!quiet.contains("Pass '"), "{}", quiet);
+    let verbose = stdout(&["-O2", "--print-passes"]);
+    assert!(
+        verbose.contains("Pass 'inline' changed the program"),
+        "{}",
+        verbose
+    );
+}